@@ -0,0 +1,27 @@
+// Flips one bit of an XTS-encrypted sector and shows which plaintext blocks
+// change: exactly one is randomized and the rest decrypt untouched.
+//
+//     cargo run --example xts_malleability
+use cryptopals::{
+    utils::{bytes, hexdump},
+    xts::Xts,
+};
+
+fn main() -> anyhow::Result<()> {
+    let xts = Xts::aes(&bytes::rand_of_len(32))?;
+    let sector = 42;
+    let plaintext = b"user=alice;uid=1000;gid=1000;home=/home/alice;shell=/bin/sh;ok";
+    let mut encrypted = xts.encrypt_sector(sector, plaintext)?;
+
+    println!("ciphertext:\n{}\n", hexdump::hexdump(&encrypted, 16));
+    encrypted[20] ^= 1;
+    let decrypted = xts.decrypt_sector(sector, &encrypted)?;
+
+    println!("plaintext after flipping bit 0 of byte 20:");
+    println!("{}\n", hexdump::block_diff(plaintext, &decrypted, 16));
+    println!(
+        "changed blocks: {:?}",
+        hexdump::changed_blocks(plaintext, &decrypted, 16)
+    );
+    Ok(())
+}
@@ -0,0 +1,353 @@
+// AES using the x86-64 AES-NI instructions, checked for at runtime.
+// References: Intel, "Advanced Encryption Standard (AES) New Instructions Set"
+// (Gueron, 2010), sections 3 and 4
+//
+// The key schedule is the portable one from `native`. Decryption uses the
+// equivalent inverse cipher, so its middle round keys go through
+// InvMixColumns first. Several blocks can be encrypted at once, interleaving
+// their rounds so the AESENC latency is hidden. Without AES-NI everything
+// falls back to `native::Aes`.
+use anyhow::{ensure, Result};
+
+use crate::{
+    aes::native::{self, inv_mix_columns, Block, BLOCK_SIZE},
+    cipher::BlockCipher,
+    padding::{PadPkcs7, UnpadPkcs7},
+};
+
+/// How many blocks the pipelined functions keep in flight
+pub const LANES: usize = 4;
+
+/// Whether this CPU has AES-NI
+pub fn is_available() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        std::arch::is_x86_feature_detected!("aes") && std::arch::is_x86_feature_detected!("sse2")
+    }
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AesNi {
+    portable: native::Aes,
+    encrypt_keys: Vec<Block>,
+    decrypt_keys: Vec<Block>,
+    accelerated: bool,
+}
+
+impl AesNi {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Self::with_acceleration(key, is_available())
+    }
+
+    /// Always uses the portable path, for comparing against the accelerated one
+    pub fn portable(key: &[u8]) -> Result<Self> {
+        Self::with_acceleration(key, false)
+    }
+
+    fn with_acceleration(key: &[u8], accelerated: bool) -> Result<Self> {
+        let portable = native::Aes::new(key)?;
+        let encrypt_keys = portable.round_keys().to_vec();
+        let rounds = encrypt_keys.len() - 1;
+        let decrypt_keys = (0..=rounds)
+            .map(|i| {
+                let mut round_key = encrypt_keys[rounds - i];
+                if i != 0 && i != rounds {
+                    inv_mix_columns(&mut round_key);
+                }
+                round_key
+            })
+            .collect();
+        Ok(AesNi {
+            portable,
+            encrypt_keys,
+            decrypt_keys,
+            accelerated,
+        })
+    }
+
+    pub fn is_accelerated(&self) -> bool {
+        self.accelerated
+    }
+
+    pub fn encrypt_block(&self, block: &mut Block) {
+        self.encrypt_blocks(std::slice::from_mut(block));
+    }
+
+    pub fn decrypt_block(&self, block: &mut Block) {
+        self.decrypt_blocks(std::slice::from_mut(block));
+    }
+
+    /// Encrypts every block in place, `LANES` at a time
+    pub fn encrypt_blocks(&self, blocks: &mut [Block]) {
+        #[cfg(target_arch = "x86_64")]
+        if self.accelerated {
+            // SAFETY: `accelerated` is only set when `is_available` found AES-NI
+            unsafe { x86::encrypt_blocks(&self.encrypt_keys, blocks) };
+            return;
+        }
+        for block in blocks {
+            self.portable.encrypt_block(block);
+        }
+    }
+
+    pub fn decrypt_blocks(&self, blocks: &mut [Block]) {
+        #[cfg(target_arch = "x86_64")]
+        if self.accelerated {
+            // SAFETY: as in `encrypt_blocks`
+            unsafe { x86::decrypt_blocks(&self.decrypt_keys, blocks) };
+            return;
+        }
+        for block in blocks {
+            self.portable.decrypt_block(block);
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::{
+        __m128i, _mm_aesdec_si128, _mm_aesdeclast_si128, _mm_aesenc_si128, _mm_aesenclast_si128,
+        _mm_loadu_si128, _mm_setzero_si128, _mm_storeu_si128, _mm_xor_si128,
+    };
+
+    use super::LANES;
+    use crate::aes::native::Block;
+
+    // AES-256 has the most, 14 rounds plus the initial whitening key
+    const MAX_ROUND_KEYS: usize = 15;
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn load(block: &Block) -> __m128i {
+        _mm_loadu_si128(block.as_ptr() as *const __m128i)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn store(block: &mut Block, state: __m128i) {
+        _mm_storeu_si128(block.as_mut_ptr() as *mut __m128i, state)
+    }
+
+    // Runs the rounds over up to `LANES` blocks side by side. `round` and
+    // `last` are AESENC/AESENCLAST or AESDEC/AESDECLAST.
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn crypt_blocks(
+        keys: &[Block],
+        blocks: &mut [Block],
+        round: unsafe fn(__m128i, __m128i) -> __m128i,
+        last: unsafe fn(__m128i, __m128i) -> __m128i,
+    ) {
+        // on the stack, since single blocks come through here one at a time
+        let rounds = keys.len() - 1;
+        let mut loaded = [_mm_setzero_si128(); MAX_ROUND_KEYS];
+        for (slot, key) in loaded.iter_mut().zip(keys) {
+            *slot = load(key);
+        }
+        let keys = &loaded[..=rounds];
+        for chunk in blocks.chunks_mut(LANES) {
+            let mut states = [keys[0]; LANES];
+            for (state, block) in states.iter_mut().zip(chunk.iter()) {
+                *state = _mm_xor_si128(load(block), keys[0]);
+            }
+            let lanes = chunk.len();
+            for key in &keys[1..rounds] {
+                for state in &mut states[..lanes] {
+                    *state = round(*state, *key);
+                }
+            }
+            for (state, block) in states.iter().zip(chunk.iter_mut()) {
+                store(block, last(*state, keys[rounds]));
+            }
+        }
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn aesenc(state: __m128i, key: __m128i) -> __m128i {
+        _mm_aesenc_si128(state, key)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn aesenclast(state: __m128i, key: __m128i) -> __m128i {
+        _mm_aesenclast_si128(state, key)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn aesdec(state: __m128i, key: __m128i) -> __m128i {
+        _mm_aesdec_si128(state, key)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    unsafe fn aesdeclast(state: __m128i, key: __m128i) -> __m128i {
+        _mm_aesdeclast_si128(state, key)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    pub(super) unsafe fn encrypt_blocks(keys: &[Block], blocks: &mut [Block]) {
+        crypt_blocks(keys, blocks, aesenc, aesenclast)
+    }
+
+    #[target_feature(enable = "aes,sse2")]
+    pub(super) unsafe fn decrypt_blocks(keys: &[Block], blocks: &mut [Block]) {
+        crypt_blocks(keys, blocks, aesdec, aesdeclast)
+    }
+}
+
+impl BlockCipher for AesNi {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let mut block = to_block(block)?;
+        AesNi::encrypt_block(self, &mut block);
+        Ok(block.to_vec())
+    }
+
+    fn decrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let mut block = to_block(block)?;
+        AesNi::decrypt_block(self, &mut block);
+        Ok(block.to_vec())
+    }
+}
+
+fn to_block(block: &[u8]) -> Result<Block> {
+    block
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("expected len of {BLOCK_SIZE}, got {}", block.len()))
+}
+
+fn to_blocks(bytes: &[u8]) -> Vec<Block> {
+    bytes
+        .chunks_exact(BLOCK_SIZE)
+        .map(|chunk| chunk.try_into().unwrap())
+        .collect()
+}
+
+/// The key schedule, kept by `AesKey` so key expansion happens once per key
+#[derive(Debug, Clone)]
+pub struct Context(AesNi);
+
+impl Context {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(Context(AesNi::new(key)?))
+    }
+
+    pub fn encrypt_block(&self, block: &Block) -> Result<Block> {
+        let mut block = *block;
+        self.0.encrypt_block(&mut block);
+        Ok(block)
+    }
+
+    pub fn decrypt_block(&self, block: &Block) -> Result<Block> {
+        let mut block = *block;
+        self.0.decrypt_block(&mut block);
+        Ok(block)
+    }
+}
+
+pub fn encrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let mut blocks = to_blocks(&bytes.pad_pkcs7());
+    AesNi::new(key)?.encrypt_blocks(&mut blocks);
+    Ok(blocks.concat())
+}
+
+pub fn decrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        bytes.len().is_multiple_of(BLOCK_SIZE),
+        "expected multiple of {BLOCK_SIZE}, got {}",
+        bytes.len()
+    );
+    let mut blocks = to_blocks(bytes);
+    AesNi::new(key)?.decrypt_blocks(&mut blocks);
+    blocks.concat().validate_unpad_pkcs7()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{
+            aesni::{self, AesNi},
+            native::{self, Block},
+            openssl_backend,
+        },
+        cipher::BlockCipher,
+        hex::{DecodeHex, EncodeHex},
+        utils::bytes,
+    };
+
+    #[test]
+    fn test_fips197_vectors() {
+        // FIPS-197 Appendix C
+        let plaintext: Block = "00112233445566778899aabbccddeeff"
+            .decode_hex()
+            .try_into()
+            .unwrap();
+        let vectors = [
+            (
+                "000102030405060708090a0b0c0d0e0f",
+                "69c4e0d86a7b0430d8cdb78070b4c55a",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f1011121314151617",
+                "dda97ca4864cdfe06eaf70a0ec0d7191",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "8ea2b7ca516745bfeafc49904b496089",
+            ),
+        ];
+        for (key, expected) in vectors {
+            let key = key.decode_hex();
+            for aes in [AesNi::new(&key).unwrap(), AesNi::portable(&key).unwrap()] {
+                let mut block = plaintext;
+                aes.encrypt_block(&mut block);
+                assert_eq!(block.to_vec().to_hex(), expected);
+                aes.decrypt_block(&mut block);
+                assert_eq!(block, plaintext);
+            }
+        }
+        assert_eq!(
+            AesNi::new(&[0; 16]).unwrap().is_accelerated(),
+            aesni::is_available()
+        );
+    }
+
+    // every block count around the lane width, against the other two backends
+    #[test]
+    fn test_matches_other_backends() {
+        for key_len in [16, 24, 32] {
+            let key = bytes::rand_of_len(key_len);
+            let aes = AesNi::new(&key).unwrap();
+            let reference = native::Aes::new(&key).unwrap();
+            for count in 0..=2 * aesni::LANES + 1 {
+                let plaintext = bytes::rand_of_len(count * 16);
+                let mut blocks: Vec<Block> = plaintext
+                    .chunks_exact(16)
+                    .map(|c| c.try_into().unwrap())
+                    .collect();
+                aes.encrypt_blocks(&mut blocks);
+                for (i, block) in blocks.iter().enumerate() {
+                    let expected =
+                        BlockCipher::encrypt_block(&reference, &plaintext[16 * i..16 * (i + 1)])
+                            .unwrap();
+                    assert_eq!(block.to_vec(), expected);
+                }
+                aes.decrypt_blocks(&mut blocks);
+                assert_eq!(blocks.concat(), plaintext);
+            }
+
+            let plaintext = bytes::rand_of_len(100);
+            let encrypted = aesni::encrypt_ecb(&plaintext, &key).unwrap();
+            assert_eq!(
+                encrypted,
+                openssl_backend::encrypt_ecb(&plaintext, &key).unwrap()
+            );
+            assert_eq!(aesni::decrypt_ecb(&encrypted, &key).unwrap(), plaintext);
+        }
+        assert!(AesNi::new(&[0; 15]).is_err());
+        let aes = AesNi::new(&[0; 16]).unwrap();
+        assert!(BlockCipher::encrypt_block(&aes, &[0; 8]).is_err());
+    }
+}
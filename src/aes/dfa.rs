@@ -0,0 +1,202 @@
+// Differential fault analysis of AES-128.
+// Reference: Piret & Quisquater, "A Differential Fault Attack Technique
+// against SPN Structures, with Application to the AES and KHAZAD" (CHES 2003)
+//
+// A single faulty byte at the start of round 9 is spread by that round's
+// MixColumns over one column, so four ciphertext bytes differ, and their
+// differences before the last SubBytes are fixed multiples (2, 3, 1, 1 in some
+// rotation) of one unknown byte. For each guess of the fault row and value,
+// the four last-round key bytes that explain the observed ciphertexts can be
+// solved for separately. One pair leaves about a thousand candidates per
+// column and a second pair almost always leaves one.
+use std::collections::HashSet;
+
+use anyhow::{bail, Result};
+
+use crate::{
+    aes::native::{gmul, invert_key_schedule, Block, BLOCK_SIZE, INV_SBOX},
+    oracle::FaultOracle,
+};
+
+/// The fault round for the attack: the input to round 9 of AES-128
+pub const FAULT_ROUND: usize = 9;
+
+/// Xors `mask` into byte `position` of the state at the start of `round`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fault {
+    pub round: usize,
+    pub position: usize,
+    pub mask: u8,
+}
+
+impl Fault {
+    /// A hook for `Aes::encrypt_block_with_hook`
+    pub fn apply(&self, round: usize, state: &mut Block) {
+        if round == self.round {
+            state[self.position] ^= self.mask;
+        }
+    }
+}
+
+// Column k of the MixColumns matrix: the multiples of a difference in row k
+const MIX_COLUMN_MULTIPLES: [[u8; 4]; 4] = [[2, 1, 1, 3], [3, 2, 1, 1], [1, 3, 2, 1], [1, 1, 3, 2]];
+
+/// The ciphertext positions that a difference in column `column` after round
+/// 9's MixColumns ends up in, row by row, after the final ShiftRows
+pub fn column_positions(column: usize) -> [usize; 4] {
+    let mut positions = [0; 4];
+    for (row, position) in positions.iter_mut().enumerate() {
+        *position = row + 4 * ((column + 4 - row) % 4);
+    }
+    positions
+}
+
+/// Which column a correct/faulty pair's fault went through, if the differences
+/// match a single round 9 fault
+pub fn faulted_column(correct: &Block, faulty: &Block) -> Option<usize> {
+    let differing: Vec<usize> = (0..BLOCK_SIZE)
+        .filter(|&i| correct[i] != faulty[i])
+        .collect();
+    (0..4).find(|&column| {
+        let mut positions = column_positions(column).to_vec();
+        positions.sort();
+        positions == differing
+    })
+}
+
+/// Last round key bytes, at `column_positions(column)`, consistent with a
+/// correct/faulty pair
+pub fn column_candidates(correct: &Block, faulty: &Block, column: usize) -> HashSet<[u8; 4]> {
+    let positions = column_positions(column);
+    // for each row, the key bytes producing each difference before SubBytes
+    let by_difference: Vec<Vec<Vec<u8>>> = positions
+        .iter()
+        .map(|&p| {
+            let mut table = vec![vec![]; 256];
+            for key in 0..=255u8 {
+                let difference =
+                    INV_SBOX[(correct[p] ^ key) as usize] ^ INV_SBOX[(faulty[p] ^ key) as usize];
+                table[difference as usize].push(key);
+            }
+            table
+        })
+        .collect();
+
+    let mut candidates = HashSet::new();
+    for multiples in MIX_COLUMN_MULTIPLES {
+        for delta in 1..=255 {
+            let keys: Vec<&Vec<u8>> = (0..4)
+                .map(|row| &by_difference[row][gmul(multiples[row], delta) as usize])
+                .collect();
+            if keys.iter().any(|k| k.is_empty()) {
+                continue;
+            }
+            for &k0 in keys[0] {
+                for &k1 in keys[1] {
+                    for &k2 in keys[2] {
+                        for &k3 in keys[3] {
+                            candidates.insert([k0, k1, k2, k3]);
+                        }
+                    }
+                }
+            }
+        }
+    }
+    candidates
+}
+
+/// Recovers the AES-128 key, asking the oracle for faulty encryptions until
+/// every column of the last round key is pinned down
+pub fn break_dfa(oracle: &FaultOracle) -> Result<Vec<u8>> {
+    let mut columns: [Option<HashSet<[u8; 4]>>; 4] = Default::default();
+    for _ in 0..100 {
+        let (correct, faulty) = oracle.encrypt_pair()?;
+        let Some(column) = faulted_column(&correct, &faulty) else {
+            continue;
+        };
+        let found = column_candidates(&correct, &faulty, column);
+        columns[column] = Some(match columns[column].take() {
+            Some(known) => known.intersection(&found).copied().collect(),
+            None => found,
+        });
+
+        if columns
+            .iter()
+            .all(|c| c.as_ref().is_some_and(|c| c.len() == 1))
+        {
+            let mut last_round_key = [0; BLOCK_SIZE];
+            for (column, candidates) in columns.iter().enumerate() {
+                let key_bytes = candidates.as_ref().unwrap().iter().next().unwrap();
+                for (&p, &k) in column_positions(column).iter().zip(key_bytes) {
+                    last_round_key[p] = k;
+                }
+            }
+            return Ok(invert_key_schedule(&last_round_key, 10).to_vec());
+        }
+        if columns.iter().flatten().any(|c| c.is_empty()) {
+            bail!("no key candidates left; a fault was not a single round 9 byte");
+        }
+    }
+    bail!("ran out of faulty encryptions")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{
+            dfa::{
+                break_dfa, column_candidates, column_positions, faulted_column, Fault, FAULT_ROUND,
+            },
+            native::{expand_key, Aes, Block},
+        },
+        oracle::FaultOracle,
+        utils::{bytes, random::test_rng},
+    };
+
+    #[test]
+    fn test_fault_spreads_to_one_column() {
+        let key = bytes::rand_of_len(16);
+        let aes = Aes::new(&key).unwrap();
+        let plaintext: Block = bytes::rand_of_len(16).try_into().unwrap();
+        let mut correct = plaintext;
+        aes.encrypt_block(&mut correct);
+
+        for position in 0..16 {
+            let fault = Fault {
+                round: FAULT_ROUND,
+                position,
+                mask: 0x5a,
+            };
+            let mut faulty = plaintext;
+            aes.encrypt_block_with_hook(&mut faulty, |round, state| fault.apply(round, state));
+            let column = faulted_column(&correct, &faulty).unwrap();
+            // ShiftRows at the start of round 9 moves byte r + 4c to column c - r
+            assert_eq!(column, (position / 4 + 4 - position % 4) % 4);
+
+            let last_round_key = expand_key(&key).unwrap()[10];
+            let expected: Vec<u8> = column_positions(column)
+                .iter()
+                .map(|&p| last_round_key[p])
+                .collect();
+            let candidates = column_candidates(&correct, &faulty, column);
+            assert!(candidates.contains(&<[u8; 4]>::try_from(expected).unwrap()));
+        }
+
+        // a fault a round earlier reaches every byte
+        let fault = Fault {
+            round: FAULT_ROUND - 1,
+            position: 0,
+            mask: 1,
+        };
+        let mut faulty = plaintext;
+        aes.encrypt_block_with_hook(&mut faulty, |round, state| fault.apply(round, state));
+        assert_eq!(faulted_column(&correct, &faulty), None);
+    }
+
+    #[test]
+    fn test_dfa() {
+        let oracle = FaultOracle::with_rng(&mut test_rng()).unwrap();
+        let key = break_dfa(&oracle).unwrap();
+        assert!(oracle.verify(&key));
+    }
+}
@@ -0,0 +1,880 @@
+use std::{collections::HashSet, error::Error, fmt, iter::once};
+
+use anyhow::{bail, ensure, Result};
+use itertools::Itertools;
+
+pub mod aesni;
+pub mod dfa;
+pub mod native;
+pub mod openssl_backend;
+pub mod square;
+
+// The backend that performs the raw AES block operations. OpenSSL is the
+// default, the pure-Rust implementation is selected with the `native-aes` feature
+// and AES-NI (falling back to pure Rust at runtime) with `aes-ni`.
+#[cfg(feature = "aes-ni")]
+use aesni as backend;
+#[cfg(all(feature = "native-aes", not(feature = "aes-ni")))]
+use native as backend;
+#[cfg(not(any(feature = "native-aes", feature = "aes-ni")))]
+use openssl_backend as backend;
+
+#[derive(Debug)]
+pub enum AesError {
+    InvalidPadding(String),
+    InvalidKeyLength(String),
+    InvalidTag(String),
+    IntegrityCheck(String),
+}
+
+impl Error for AesError {}
+
+impl fmt::Display for AesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeySize {
+    Aes128,
+    Aes192,
+    Aes256,
+}
+
+impl KeySize {
+    pub fn from_key(key: &[u8]) -> Result<KeySize> {
+        match key.len() {
+            16 => Ok(KeySize::Aes128),
+            24 => Ok(KeySize::Aes192),
+            32 => Ok(KeySize::Aes256),
+            len => bail!(AesError::InvalidKeyLength(format!(
+                "expected 16, 24 or 32 bytes, got {len}"
+            ))),
+        }
+    }
+
+    pub fn key_len(&self) -> usize {
+        match self {
+            KeySize::Aes128 => 16,
+            KeySize::Aes192 => 24,
+            KeySize::Aes256 => 32,
+        }
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.key_len() / 4 + 6
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    ECB,
+    CBC,
+    CTR,
+    CFB,
+    CFB8,
+    OFB,
+    PCBC,
+    GCM,
+}
+
+use crate::{
+    cipher::BlockCipher,
+    frequency::BYTES_BY_FREQ,
+    modes,
+    oracle::{EditOracle, EncryptionOracle, PaddingOracle},
+    padding::{PadPkcs7, UnpadPkcs7},
+    utils::{bytes, random::RandomSource},
+    xor::Xor,
+};
+
+pub fn decrypt_aes_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    backend::decrypt_ecb(bytes, key)
+}
+
+/// False for anything that isn't whole AES blocks, which can't be AES-ECB
+pub fn detect_aes_128_ecb(bytes: &[u8]) -> bool {
+    let blocksize = 16;
+    bytes.len().is_multiple_of(blocksize) && detect_ecb(bytes, blocksize)
+}
+
+/// True if any two blocks of the ciphertext are identical
+pub fn detect_ecb(bytes: &[u8], blocksize: usize) -> bool {
+    let mut set = HashSet::new();
+    for chunk in bytes.chunks_exact(blocksize) {
+        if set.contains(chunk) {
+            return true;
+        }
+        set.insert(chunk);
+    }
+    false
+}
+
+pub fn encrypt_aes_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    backend::encrypt_ecb(bytes, key)
+}
+
+pub fn encrypt_aes_cbc(bytes: &[u8], iv: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    modes::encrypt_cbc(&AesKey::new(key)?, bytes, iv)
+}
+
+pub fn encrypt_aes_ctr(bytes: &[u8], key: &[u8], nonce: u64) -> Result<Vec<u8>> {
+    stream_aes_ctr(bytes, key, nonce)
+}
+
+fn stream_aes_ctr(bytes: &[u8], key: &[u8], nonce: u64) -> Result<Vec<u8>> {
+    modes::ctr(&AesKey::new(key)?, bytes, nonce)
+}
+
+pub fn decrypt_aes_ctr(bytes: &[u8], key: &[u8], nonce: u64) -> Result<Vec<u8>> {
+    stream_aes_ctr(bytes, key, nonce)
+}
+
+pub fn decrypt_aes_cbc(bytes: &[u8], iv: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    modes::decrypt_cbc(&AesKey::new(key)?, bytes, iv)
+}
+
+/// An AES key of any supported size, usable as a [`BlockCipher`]. The backend
+/// context is built once here and reused for every block.
+pub struct AesKey {
+    key: Vec<u8>,
+    size: KeySize,
+    context: backend::Context,
+}
+
+impl AesKey {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(AesKey {
+            key: key.to_vec(),
+            size: KeySize::from_key(key)?,
+            context: backend::Context::new(key)?,
+        })
+    }
+
+    pub fn size(&self) -> KeySize {
+        self.size
+    }
+
+    fn to_block(block: &[u8]) -> Result<native::Block> {
+        block
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("expected len of 16, got {}", block.len()))
+    }
+}
+
+impl fmt::Debug for AesKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // never the key itself, which would end up in logs
+        f.debug_struct("AesKey")
+            .field("size", &self.size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Clone for AesKey {
+    fn clone(&self) -> Self {
+        AesKey::new(&self.key).expect("key was already validated")
+    }
+}
+
+impl BlockCipher for AesKey {
+    fn block_size(&self) -> usize {
+        16
+    }
+
+    fn encrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        Ok(self
+            .context
+            .encrypt_block(&Self::to_block(block)?)?
+            .to_vec())
+    }
+
+    fn decrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        Ok(self
+            .context
+            .decrypt_block(&Self::to_block(block)?)?
+            .to_vec())
+    }
+}
+
+pub fn break_ecb(oracle: &impl EncryptionOracle) -> anyhow::Result<Vec<u8>> {
+    break_ecb_with_rng(oracle, &mut rand::thread_rng())
+}
+
+/// `break_ecb`, drawing its random probes from `rng`
+pub fn break_ecb_with_rng(
+    oracle: &impl EncryptionOracle,
+    rng: &mut impl RandomSource,
+) -> anyhow::Result<Vec<u8>> {
+    let blocksize = match oracle.block_size_hint() {
+        Some(blocksize) => blocksize,
+        None => detect_block_size(oracle)?,
+    };
+    ensure_ecb(blocksize, oracle)?;
+    let alignment = detect_alignment(blocksize, oracle, rng)?;
+    let encrypt_len = detect_payload_length(blocksize, &alignment, oracle)?;
+    let mut decrypted = vec![];
+    for _ in 0..encrypt_len {
+        let byte = decrypt_next_byte(&alignment, blocksize, &decrypted, oracle)?;
+        decrypted.push(byte);
+    }
+
+    Ok(decrypted)
+}
+
+// How many steering blocks `break_cbc_bitflip` tries per spliced block
+const MAX_STEERS: usize = 1 << 16;
+
+fn detect_block_size(oracle: &impl EncryptionOracle) -> anyhow::Result<usize> {
+    for len in 2..=255 {
+        let plaintext = vec![b'0'; len * 4];
+        let encrypted = oracle.encrypt(&plaintext)?;
+
+        for (a, b, c) in encrypted.chunks(len).tuple_windows::<(_, _, _)>() {
+            if a == b && b == c {
+                return Ok(len);
+            }
+        }
+    }
+    bail!("Could not detect block size");
+}
+
+// For any padded mode: the step in ciphertext length as the input grows
+fn detect_padded_block_size(oracle: &impl EncryptionOracle) -> anyhow::Result<usize> {
+    let initial = oracle.encrypt(b"")?.len();
+    for len in 1..=255 {
+        let encrypted_len = oracle.encrypt(&vec![b'A'; len])?.len();
+        if encrypted_len != initial {
+            return Ok(encrypted_len - initial);
+        }
+    }
+    bail!("Could not detect block size");
+}
+
+fn ensure_ecb(blocksize: usize, oracle: &impl EncryptionOracle) -> anyhow::Result<()> {
+    let plaintext = vec![b'A'; blocksize * 3];
+    let encrypted = oracle.encrypt(&plaintext)?;
+    ensure!(detect_ecb(&encrypted, blocksize), "expected to confirm ecb");
+    Ok(())
+}
+
+fn detect_payload_length(
+    blocksize: usize,
+    alignment: &Alignment,
+    oracle: &impl EncryptionOracle,
+) -> anyhow::Result<usize> {
+    // The payload is pkcs7 padded, so the encrypt length will always be a multiple
+    // of the blocksize.
+    // Keep adding a byte at a time to the plaintext len until the ciphertext
+    // jumps by blocksize -- that was the number of padding bytes added to the original plaintext
+    let prefix = vec![b'A'; alignment.len];
+    let curlen = oracle.encrypt(&prefix)?.len();
+
+    let mut bytes = prefix;
+    for padlen in 0..blocksize {
+        bytes.truncate(alignment.len);
+        bytes.extend(vec![b'A'; padlen]);
+        let len = oracle.encrypt(&bytes)?.len();
+        if len != curlen {
+            let payload_len = curlen - padlen - alignment.block_idx * blocksize;
+            return Ok(payload_len);
+        }
+    }
+    bail!("Did not find a length");
+}
+
+fn decrypt_next_byte(
+    alignment: &Alignment,
+    blocksize: usize,
+    decrypted: &[u8],
+    oracle: &impl EncryptionOracle,
+) -> anyhow::Result<u8> {
+    let mut prefix = vec![];
+    while (prefix.len() + decrypted.len()) % blocksize != (blocksize - 1) {
+        prefix.push(b'A');
+    }
+    let mut plaintext = vec![];
+    plaintext.extend(&prefix);
+    plaintext.extend(decrypted);
+
+    ensure!(plaintext.len() >= (blocksize - 1));
+    ensure!(plaintext.len() % blocksize == (blocksize - 1));
+    let mut probe_prefix = plaintext[plaintext.len() - (blocksize - 1)..].to_vec();
+
+    for &probe_byte in BYTES_BY_FREQ.iter() {
+        probe_prefix.truncate(blocksize - 1);
+        probe_prefix.push(probe_byte);
+        let probe = &probe_prefix;
+
+        ensure!(probe.len() == blocksize);
+
+        let mut bytes = vec![];
+        bytes.extend(vec![b'0'; alignment.len]);
+        bytes.extend(probe);
+        bytes.extend(&plaintext);
+        bytes.truncate(bytes.len() - decrypted.len());
+
+        let probe_block_idx = alignment.block_idx;
+        let target_block_idx = probe_block_idx + ((1 + plaintext.len()) / blocksize);
+        let encrypted = oracle.encrypt(&bytes)?;
+
+        let probe_block = encrypted.chunks(blocksize).nth(probe_block_idx).unwrap();
+        let target_block = encrypted.chunks(blocksize).nth(target_block_idx).unwrap();
+
+        if probe_block == target_block {
+            return Ok(probe_byte);
+        }
+    }
+
+    bail!("failed to decrypt a byte");
+}
+
+#[derive(Debug, Default)]
+struct Alignment {
+    len: usize,
+    block_idx: usize,
+}
+
+fn detect_alignment(
+    blocksize: usize,
+    oracle: &impl EncryptionOracle,
+    rng: &mut impl RandomSource,
+) -> anyhow::Result<Alignment> {
+    // `k` bytes short of alignment, the two probe blocks still encrypt identically
+    // when the `k` bytes before them happen to equal the probe's last `k` bytes.
+    // A second probe that differs from the first in every byte can't match too.
+    let probe = bytes::rand_of_len_from(rng, blocksize);
+    let other_probe: Vec<u8> = probe.iter().map(|b| b ^ 0xff).collect();
+    for len in 0..blocksize {
+        let block_idx = find_repeated_block(len, &probe, blocksize, oracle, rng)?;
+        let other_block_idx = find_repeated_block(len, &other_probe, blocksize, oracle, rng)?;
+        if let (Some(block_idx), Some(other_block_idx)) = (block_idx, other_block_idx) {
+            if block_idx == other_block_idx {
+                return Ok(Alignment { len, block_idx });
+            }
+        }
+    }
+    bail!("Could not detect prefix len");
+}
+
+fn find_repeated_block(
+    len: usize,
+    plaintext_block: &[u8],
+    blocksize: usize,
+    oracle: &impl EncryptionOracle,
+    rng: &mut impl RandomSource,
+) -> anyhow::Result<Option<usize>> {
+    let align_bytes = bytes::rand_of_len_from(rng, len);
+    let mut bytes = vec![];
+    bytes.extend(&align_bytes);
+    bytes.extend(plaintext_block);
+    bytes.extend(plaintext_block);
+
+    let encrypted = oracle.encrypt(&bytes)?;
+    Ok(encrypted
+        .chunks(blocksize)
+        .tuple_windows::<(_, _)>()
+        .position(|(a, b)| a == b))
+}
+
+pub fn break_ecb_cut_paste(oracle: &impl EncryptionOracle) -> anyhow::Result<Vec<u8>> {
+    let blocksize = match oracle.block_size_hint() {
+        Some(blocksize) => blocksize,
+        None => detect_block_size(oracle)?,
+    };
+    ensure_ecb(blocksize, oracle)?;
+
+    // TODO detect alignlen assuming we don't know how much prefix padding is added
+    let alignlen = blocksize - "email=".len();
+    let mut email = vec![b'A'; alignlen];
+    email.extend("admin".as_bytes().pad_pkcs7_blocksize(blocksize));
+    email.extend("@a.com".as_bytes()); // to look like an email
+    let encrypted = oracle.encrypt(&email)?;
+    let target_start = "email=".len() + alignlen;
+    let target_end = target_start + blocksize;
+    let target_bytes = &encrypted[target_start..target_end];
+
+    // align email=X&uid=10&role= to end of block
+    let mut email_prefix = vec![b'A'];
+    let email_suffix = "@a.com";
+    while !("email=".len() + email_prefix.len() + email_suffix.len() + "&uid=10&role=".len())
+        .is_multiple_of(blocksize)
+    {
+        email_prefix.push(b'A');
+    }
+    email_prefix.extend(email_suffix.as_bytes()); // make it look like an email
+    let email = email_prefix;
+    let mut encrypted = oracle.encrypt(&email)?;
+    encrypted.truncate(encrypted.len() - blocksize); // drop last block
+    encrypted.extend(target_bytes); // add back an "admin" + fake padded block
+
+    Ok(encrypted)
+}
+
+pub fn break_cbc_bitflip(oracle: &impl EncryptionOracle) -> anyhow::Result<Vec<u8>> {
+    let blocksize = match oracle.block_size_hint() {
+        Some(blocksize) => blocksize,
+        None => detect_padded_block_size(oracle)?,
+    };
+    let data = ";admin=true;".as_bytes();
+
+    // The first ciphertext block that depends on our input is where it starts.
+    // Lengthening the input until a change in its last byte moves on to the
+    // next block tells us how far into that block it starts.
+    let changed_block = |fill: usize| -> anyhow::Result<usize> {
+        let lhs = oracle.encrypt(&[vec![b'A'; fill], b"A".to_vec()].concat())?;
+        let rhs = oracle.encrypt(&[vec![b'A'; fill], b"B".to_vec()].concat())?;
+        lhs.chunks(blocksize)
+            .zip(rhs.chunks(blocksize))
+            .position(|(a, b)| a != b)
+            .ok_or_else(|| anyhow::anyhow!("could not find the input block"))
+    };
+    let input_idx = changed_block(0)?;
+    let mut fill = 1;
+    while changed_block(fill)? == input_idx {
+        fill += 1;
+        ensure!(fill <= blocksize, "could not find where the input starts");
+    }
+    let fill = fill % blocksize;
+    // the first block that is all ours
+    let first = input_idx + usize::from(fill > 0);
+    let encrypt_blocks = |blocks: &[&[u8]]| -> anyhow::Result<Vec<Vec<u8>>> {
+        let input = [vec![b'A'; fill], blocks.concat()].concat();
+        Ok(oracle
+            .encrypt(&input)?
+            .chunks(blocksize)
+            .map(<[u8]>::to_vec)
+            .collect())
+    };
+
+    let mut wanted = data.to_vec();
+    wanted.resize(data.len().div_ceil(blocksize) * blocksize, b'A');
+    let wanted = wanted.chunks(blocksize).collect_vec();
+
+    // Scrambling a ciphertext block flips bits in the next plaintext block, so
+    // the first block of the target comes from flipping the block before it
+    let filler = vec![b'A'; blocksize];
+    let encrypted = encrypt_blocks(&[&filler, &filler])?;
+    let mut patched = encrypted[..first + 2].to_vec();
+    patched[first] = patched[first].xor(&filler).xor(wanted[0]);
+    let mut tail = encrypted[first + 2..].to_vec();
+
+    // Flipping bits for any later block would scramble the one before it, so
+    // each is spliced in from its own query instead: one where the block in
+    // front of it steers our input to the block we need, until that input is
+    // free of anything the oracle quotes
+    for wanted_block in &wanted[1..] {
+        let prev = patched.last().expect("at least one block").clone();
+        let mut spliced = None;
+        for steer in 0..MAX_STEERS {
+            let steer = format!("{steer:0>blocksize$}");
+            let steer = &steer.as_bytes()[steer.len() - blocksize..];
+            let input = wanted_block
+                .xor(&prev)
+                .xor(&encrypt_blocks(&[steer])?[first]);
+            if input
+                .iter()
+                .all(|&b| b.is_ascii() && b != b';' && b != b'=')
+            {
+                spliced = Some(encrypt_blocks(&[steer, &input])?);
+                break;
+            }
+        }
+        let Some(encrypted) = spliced else {
+            bail!("could not steer the input clear of quoted characters");
+        };
+        patched.push(encrypted[first + 1].clone());
+        tail = encrypted[first + 2..].to_vec();
+    }
+    patched.extend(tail);
+    Ok(patched.concat())
+}
+
+/// Decrypts `ciphertext`, which was encrypted under `iv`, one byte at a time
+/// by asking `oracle` whether doctored ciphertexts have valid padding
+pub fn break_cbc_padding_oracle(
+    oracle: &impl PaddingOracle,
+    iv: &[u8],
+    ciphertext: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let blocksize = oracle.block_size_hint().unwrap_or(iv.len());
+    ensure!(
+        iv.len() == blocksize,
+        "expected a {blocksize} byte IV, got {}",
+        iv.len()
+    );
+    ensure!(
+        !ciphertext.is_empty() && ciphertext.len().is_multiple_of(blocksize),
+        "expected a non-empty multiple of {blocksize} bytes of ciphertext, got {}",
+        ciphertext.len()
+    );
+
+    fn break_block(
+        prev_block: &[u8],
+        block: &[u8],
+        oracle: &impl PaddingOracle,
+    ) -> anyhow::Result<Vec<u8>> {
+        let blocksize = block.len();
+        let mut probe_block = prev_block.to_vec();
+        let mut cleartext = vec![0; blocksize];
+
+        for byte_rhs_offset in 0..blocksize {
+            let byte_idx = blocksize - byte_rhs_offset - 1;
+            let pad_target: u8 = byte_rhs_offset as u8 + 1;
+
+            for pad_byte_idx in (byte_idx + 1)..blocksize {
+                probe_block[pad_byte_idx] =
+                    prev_block[pad_byte_idx] ^ cleartext[pad_byte_idx] ^ pad_target;
+            }
+
+            let cipher_byte = prev_block[byte_idx];
+            let mut possible_probe_bytes = vec![];
+
+            for probe_byte in u8::MIN..=u8::MAX {
+                probe_block[byte_idx] = probe_byte;
+                let mut ciphertext = vec![];
+                ciphertext.extend_from_slice(&probe_block);
+                ciphertext.extend_from_slice(block);
+                if oracle.check_padding(&ciphertext)? {
+                    // last byte of decrypted (lbd) == pad_target
+                    // lbd = intermediate ^ prev_block[byte]
+                    // prev_block[byte] ^ pad_target = intermediate[byte]
+                    // dbg!((
+                    //     probe_byte,
+                    //     block[byte_idx],
+                    //     pad_target,
+                    //     cipher_byte,
+                    //     probe_byte ^ pad_target ^ cipher_byte,
+                    //     &cleartext
+                    // ));
+                    let plaintext_byte = probe_byte ^ pad_target ^ cipher_byte;
+                    possible_probe_bytes.push((probe_byte, pad_target, plaintext_byte));
+                }
+            }
+            if possible_probe_bytes.len() == 1 {
+                let (_, _pad_target, plaintext_byte) = possible_probe_bytes[0];
+                cleartext[byte_idx] = plaintext_byte;
+            } else if possible_probe_bytes.len() == 2 {
+                let equals_byte = possible_probe_bytes.iter().find(
+                    |&(_probe_byte, pad_target, plaintext_byte)| pad_target == plaintext_byte,
+                );
+                ensure!(equals_byte.is_some());
+                let unequals_byte = possible_probe_bytes.iter().find(
+                    |&(_probe_byte, pad_target, plaintext_byte)| pad_target != plaintext_byte,
+                );
+                ensure!(unequals_byte.is_some());
+                let plaintext_byte = unequals_byte.unwrap().2;
+                cleartext[byte_idx] = plaintext_byte;
+            } else {
+                bail!("unknown state");
+            }
+        }
+
+        Ok(cleartext)
+    }
+
+    let iv = iv.to_owned();
+    let chunks = ciphertext.chunks(blocksize).map(|x| x.to_vec());
+    let prev_blocks = once(iv).chain(chunks);
+    let cur_blocks = ciphertext.chunks(blocksize).map(|x| x.to_vec());
+
+    let mut cleartext = vec![];
+
+    for (prev_block, cur_block) in prev_blocks.zip(cur_blocks) {
+        let out = break_block(&prev_block, &cur_block, oracle)?;
+        cleartext.extend(out);
+    }
+
+    Ok(cleartext.unpad_pkcs7_blocksize(blocksize))
+}
+
+/// Editing the ciphertext in place reuses the keystream, so "editing" the
+/// whole ciphertext to itself xors the keystream back out, leaving the plaintext.
+pub fn break_ctr_edit(oracle: &impl EditOracle, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+    oracle.edit(ciphertext, 0, ciphertext)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        aes::{
+            break_cbc_bitflip, break_cbc_padding_oracle, break_ctr_edit, break_ecb,
+            break_ecb_with_rng, decrypt_aes_cbc, decrypt_aes_ctr, decrypt_aes_ecb,
+            detect_aes_128_ecb, detect_alignment, detect_ecb, encrypt_aes_cbc, encrypt_aes_ctr,
+            encrypt_aes_ecb, AesError, AesKey, KeySize,
+        },
+        cipher::{BlockCipher, ToyCipher},
+        hex::{DecodeHex, EncodeHex},
+        modes,
+        oracle::{
+            CbcOracle, CbcPaddingOracle, CtrEditOracle, EcbOracle, EncryptionOracle,
+            PrefixEcbOracle, Target, VerificationOracle,
+        },
+        utils::{
+            bytes,
+            random::{seeded, test_rng},
+        },
+    };
+
+    // NIST SP 800-38A Appendix F
+    const SP800_38A_PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    const SP800_38A_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const SP800_38A_KEY_128: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const SP800_38A_KEY_192: &str = "8e73b0f7da0e6452c810f32b809079e562f8ead2522c6b7b";
+    const SP800_38A_KEY_256: &str =
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+
+    #[test]
+    fn test_encrypt_decrypt_aes_128_ecb() {
+        for _ in 0..10 {
+            let bytes = bytes::rand_of_len(16);
+            let key = bytes::rand_of_len(16);
+            let encrypted = encrypt_aes_ecb(&bytes, &key).unwrap();
+            assert_eq!(decrypt_aes_ecb(&encrypted, &key).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_aes_128_cbc() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..10 {
+            let len = rng.gen_range(10..=50);
+            let bytes = bytes::rand_of_len(len);
+            let key = bytes::rand_of_len(16);
+            let iv = bytes::rand_of_len(16);
+            let encrypted = encrypt_aes_cbc(&bytes, &iv, &key).unwrap();
+            assert_eq!(decrypt_aes_cbc(&encrypted, &iv, &key).unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn test_key_size() {
+        assert_eq!(KeySize::from_key(&[0; 16]).unwrap(), KeySize::Aes128);
+        assert_eq!(KeySize::from_key(&[0; 24]).unwrap(), KeySize::Aes192);
+        assert_eq!(KeySize::from_key(&[0; 32]).unwrap(), KeySize::Aes256);
+        assert_eq!(KeySize::Aes192.rounds(), 12);
+        let key = AesKey::new(b"YELLOW SUBMARINE").unwrap();
+        assert_eq!(format!("{key:?}"), "AesKey { size: Aes128, .. }");
+
+        for len in [0, 8, 15, 17, 20, 31, 33, 64] {
+            let key = bytes::rand_of_len(len);
+            let err = encrypt_aes_ecb(b"YELLOW SUBMARINE", &key).unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<AesError>(),
+                Some(AesError::InvalidKeyLength(_))
+            ));
+            assert!(decrypt_aes_ecb(&[0; 16], &key).is_err());
+            assert!(encrypt_aes_cbc(b"data", &[0; 16], &key).is_err());
+            assert!(decrypt_aes_cbc(&[0; 16], &[0; 16], &key).is_err());
+            assert!(encrypt_aes_ctr(b"data", &key, 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_ecb_known_answers() {
+        let vectors = [
+            (SP800_38A_KEY_128, "3ad77bb40d7a3660a89ecaf32466ef97f5d3d58503b9699de785895a96fdbaaf43b1cd7f598ece23881b00e3ed0306887b0c785e27e8ad3f8223207104725dd4"),
+            (SP800_38A_KEY_192, "bd334f1d6e45f25ff712a214571fa5cc974104846d0ad3ad7734ecb3ecee4eefef7afd2270e2e60adce0ba2face6444e9a4b41ba738d6c72fb16691603c18e0e"),
+            (SP800_38A_KEY_256, "f3eed1bdb5d2a03c064b5a7e3db181f8591ccb10d410ed26dc5ba74a31362870b6ed21b99ca6f4f9f153e7b1beafed1d23304b7a39f9f3ff067d8d8f9e24ecc7"),
+        ];
+        let plaintext = SP800_38A_PLAINTEXT.decode_hex();
+        for (key, expected) in vectors {
+            let key = key.decode_hex();
+            let encrypted = encrypt_aes_ecb(&plaintext, &key).unwrap();
+            // the final block is pkcs7 padding
+            assert_eq!(encrypted[..64].to_vec().to_hex(), expected);
+            assert_eq!(decrypt_aes_ecb(&encrypted, &key).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_cbc_known_answers() {
+        let vectors = [
+            (SP800_38A_KEY_128, "7649abac8119b246cee98e9b12e9197d5086cb9b507219ee95db113a917678b273bed6b8e3c1743b7116e69e222295163ff1caa1681fac09120eca307586e1a7"),
+            (SP800_38A_KEY_192, "4f021db243bc633d7178183a9fa071e8b4d9ada9ad7dedf4e5e738763f69145a571b242012fb7ae07fa9baac3df102e008b0e27988598881d920a9e64f5615cd"),
+            (SP800_38A_KEY_256, "f58c4c04d6e5f1ba779eabfb5f7bfbd69cfc4e967edb808d679f777bc6702c7d39f23369a9d9bacfa530e26304231461b2eb05e2c39be9fcda6c19078c6a9d1b"),
+        ];
+        let plaintext = SP800_38A_PLAINTEXT.decode_hex();
+        let iv = SP800_38A_IV.decode_hex();
+        for (key, expected) in vectors {
+            let key = key.decode_hex();
+            let encrypted = encrypt_aes_cbc(&plaintext, &iv, &key).unwrap();
+            assert_eq!(encrypted[..64].to_vec().to_hex(), expected);
+            assert_eq!(decrypt_aes_cbc(&encrypted, &iv, &key).unwrap(), plaintext);
+        }
+    }
+
+    // Our CTR layout (little-endian nonce || little-endian counter) isn't the one
+    // SP 800-38A uses, so these were produced by encrypting the counter blocks
+    // with `openssl enc -aes-*-ecb` and xoring against the SP 800-38A plaintext.
+    #[test]
+    fn test_ctr_known_answers() {
+        let nonce = 0x0706050403020100;
+        let vectors = [
+            (
+                SP800_38A_KEY_128,
+                "19ce20015553385e50b3eb4c2523e4399bd4d3fa4e8864ddaa5f0366b057b5e2415e1c59f4eca8ae",
+            ),
+            (
+                SP800_38A_KEY_192,
+                "93c714da585bf590d0e22ab5144a5d3d01958c5d0711b91cbddc9c59b930dccab9cbdcd67043b871",
+            ),
+            (
+                SP800_38A_KEY_256,
+                "7d07fc3a8635b12eb576a4807656c6025584aabc6173bce4032c90deb992b118055cf8c4f5b386c3",
+            ),
+        ];
+        let plaintext = &SP800_38A_PLAINTEXT.decode_hex()[..40];
+        for (key, expected) in vectors {
+            let key = key.decode_hex();
+            let encrypted = encrypt_aes_ctr(plaintext, &key, nonce).unwrap();
+            assert_eq!(encrypted.to_hex(), expected);
+            assert_eq!(decrypt_aes_ctr(&encrypted, &key, nonce).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_aes_key_block_cipher() {
+        let key = bytes::rand_of_len(24);
+        let cipher = AesKey::new(&key).unwrap();
+        assert_eq!(cipher.size(), KeySize::Aes192);
+        let block = bytes::rand_of_len(16);
+        let encrypted = cipher.encrypt_block(&block).unwrap();
+        assert_eq!(encrypted, encrypt_aes_ecb(&block, &key).unwrap()[..16]);
+        assert_eq!(cipher.decrypt_block(&encrypted).unwrap(), block);
+        assert!(AesKey::new(&[0; 20]).is_err());
+
+        // the cached context survives a clone and can be shared across threads
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        assert_send_sync(&cipher);
+        let cloned = cipher.clone();
+        assert_eq!(cloned.decrypt_block(&encrypted).unwrap(), block);
+        assert!(cipher.encrypt_block(&block[..15]).is_err());
+    }
+
+    #[test]
+    fn test_detect_ecb() {
+        let cipher = ToyCipher::new(&bytes::rand_of_len(8)).unwrap();
+        let encrypted = modes::encrypt_ecb(&cipher, &[b'A'; 16]).unwrap();
+        assert!(detect_ecb(&encrypted, 8));
+        let encrypted = modes::encrypt_cbc(&cipher, &[b'A'; 16], &[0; 8]).unwrap();
+        assert!(!detect_ecb(&encrypted, 8));
+
+        let encrypted = encrypt_aes_ecb(&[b'A'; 32], &bytes::rand_of_len(16)).unwrap();
+        assert!(detect_aes_128_ecb(&encrypted));
+        assert!(!detect_aes_128_ecb(&encrypted[1..]));
+    }
+
+    // A prefix one byte short of a block boundary, ending in the probe's last
+    // byte, lines up a repeated block one byte early
+    #[test]
+    fn test_detect_alignment_with_misleading_prefix() {
+        let seed = 6;
+        let probe = bytes::rand_of_len_from(&mut seeded(seed), 16);
+        let mut oracle = PrefixEcbOracle::with_rng(b"secret".to_vec(), &mut test_rng());
+        oracle.prefix = bytes::rand_of_len_from(&mut test_rng(), 17);
+        oracle.prefix[16] = probe[15];
+
+        let alignment = detect_alignment(16, &oracle, &mut seeded(seed)).unwrap();
+        assert_eq!((alignment.len, alignment.block_idx), (15, 2));
+    }
+
+    #[test]
+    fn test_generic_attacks_with_8_byte_blocks() {
+        let secret = "Rollin' in my 5.0, with my rag-top down"
+            .as_bytes()
+            .to_vec();
+        let mut rng = test_rng();
+        for _ in 0..10 {
+            let cipher = ToyCipher::new(&bytes::rand_of_len_from(&mut rng, 8)).unwrap();
+            let oracle = EcbOracle::with_cipher(cipher.clone(), secret.clone());
+            assert!(oracle.verify(&break_ecb_with_rng(&oracle, &mut rng).unwrap()));
+
+            let oracle =
+                PrefixEcbOracle::with_cipher_and_rng(cipher.clone(), secret.clone(), &mut rng);
+            assert!(oracle.verify(&break_ecb_with_rng(&oracle, &mut rng).unwrap()));
+
+            let oracle =
+                CbcPaddingOracle::with_cipher_and_rng(cipher.clone(), secret.clone(), &mut rng)
+                    .unwrap();
+            let broken = break_cbc_padding_oracle(&oracle, &oracle.iv, &oracle.ciphertext);
+            assert!(oracle.verify(&broken.unwrap()));
+
+            let oracle =
+                CtrEditOracle::with_cipher_and_rng(cipher.clone(), secret.clone(), &mut rng)
+                    .unwrap();
+            let broken = break_ctr_edit(&oracle, &oracle.ciphertext);
+            assert!(oracle.verify(&broken.unwrap()));
+
+            // ";admin=true;" is longer than one 8-byte block
+            let oracle = CbcOracle::with_cipher_and_rng(cipher, &mut rng);
+            assert!(oracle.verify(&break_cbc_bitflip(&oracle).unwrap()).unwrap());
+        }
+
+        // the ciphertext comes from the caller, so it has to be whole blocks
+        let oracle = CbcPaddingOracle::with_rng(secret, &mut rng).unwrap();
+        assert!(break_cbc_padding_oracle(&oracle, &oracle.iv, &[]).is_err());
+        let truncated = &oracle.ciphertext[..oracle.ciphertext.len() - 1];
+        assert!(break_cbc_padding_oracle(&oracle, &oracle.iv, truncated).is_err());
+    }
+
+    // A target that doesn't say what its block size is, standing in for
+    // something outside this crate
+    struct Unhinted<O>(O);
+
+    impl<O> Target for Unhinted<O> {}
+
+    impl<O: EncryptionOracle> EncryptionOracle for Unhinted<O> {
+        fn encrypt(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.0.encrypt(input)
+        }
+    }
+
+    #[test]
+    fn test_attacks_detect_block_size() {
+        let secret = b"no hints here".to_vec();
+        for block_size in [8, 16] {
+            let cipher: Box<dyn BlockCipher> = match block_size {
+                8 => Box::new(ToyCipher::new(&bytes::rand_of_len(8)).unwrap()),
+                _ => Box::new(AesKey::new(&bytes::rand_of_len(16)).unwrap()),
+            };
+            let oracle = Unhinted(PrefixEcbOracle::with_cipher(&cipher, secret.clone()));
+            assert!(oracle.0.verify(&break_ecb(&oracle).unwrap()));
+        }
+
+        let oracle = Unhinted(CbcOracle::new());
+        let forged = break_cbc_bitflip(&oracle).unwrap();
+        assert!(VerificationOracle::verify(&oracle.0, &forged).unwrap());
+    }
+
+    // Shifts where the attacker's input starts within a block
+    struct Prefixed<O>(usize, O);
+
+    impl<O> Target for Prefixed<O> {}
+
+    impl<O: EncryptionOracle> EncryptionOracle for Prefixed<O> {
+        fn encrypt(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+            self.1
+                .encrypt(&[vec![b'x'; self.0], input.to_vec()].concat())
+        }
+    }
+
+    #[test]
+    fn test_cbc_bitflip_with_unaligned_input() {
+        let mut rng = test_rng();
+        for block_size in [8, 16] {
+            for offset in 0..block_size {
+                let cipher: Box<dyn BlockCipher> = match block_size {
+                    8 => Box::new(ToyCipher::new(&bytes::rand_of_len_from(&mut rng, 8)).unwrap()),
+                    _ => Box::new(AesKey::new(&bytes::rand_of_len_from(&mut rng, 16)).unwrap()),
+                };
+                let oracle = Prefixed(offset, CbcOracle::with_cipher_and_rng(cipher, &mut rng));
+                let forged = break_cbc_bitflip(&oracle).unwrap();
+                assert!(VerificationOracle::verify(&oracle.1, &forged).unwrap());
+            }
+        }
+    }
+}
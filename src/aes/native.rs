@@ -0,0 +1,495 @@
+// Pure-Rust AES, following FIPS-197 step by step.
+// Reference: https://nvlpubs.nist.gov/nistpubs/FIPS/NIST.FIPS.197.pdf
+//
+// The state is kept as the 16 input bytes in order, which is FIPS-197's
+// column-major layout: byte `r + 4 * c` is row r, column c.
+use anyhow::{ensure, Result};
+use lazy_static::lazy_static;
+
+use crate::{
+    aes::KeySize,
+    cipher::BlockCipher,
+    padding::{PadPkcs7, UnpadPkcs7},
+};
+
+pub const BLOCK_SIZE: usize = 16;
+
+pub type Block = [u8; BLOCK_SIZE];
+
+lazy_static! {
+    /// The AES S-box: multiplicative inverse in GF(2^8) followed by the affine transform.
+    pub static ref SBOX: [u8; 256] = {
+        let mut sbox = [0; 256];
+        for (x, s) in sbox.iter_mut().enumerate() {
+            let b = ginv(x as u8);
+            *s = b ^ b.rotate_left(1) ^ b.rotate_left(2) ^ b.rotate_left(3) ^ b.rotate_left(4) ^ 0x63;
+        }
+        sbox
+    };
+    pub static ref INV_SBOX: [u8; 256] = {
+        let mut inv = [0; 256];
+        for (x, &s) in SBOX.iter().enumerate() {
+            inv[s as usize] = x as u8;
+        }
+        inv
+    };
+}
+
+/// Multiplication in GF(2^8) modulo x^8 + x^4 + x^3 + x + 1
+pub fn gmul(a: u8, b: u8) -> u8 {
+    let mut a = a;
+    let mut b = b;
+    let mut product = 0;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        a = xtime(a);
+        b >>= 1;
+    }
+    product
+}
+
+fn xtime(a: u8) -> u8 {
+    (a << 1) ^ if a & 0x80 != 0 { 0x1b } else { 0 }
+}
+
+// a^254 == a^-1 in GF(2^8), and maps 0 to 0 as the S-box requires
+fn ginv(a: u8) -> u8 {
+    let mut result = 1;
+    for _ in 0..254 {
+        result = gmul(result, a);
+    }
+    if a == 0 {
+        0
+    } else {
+        result
+    }
+}
+
+pub fn sub_bytes(state: &mut Block) {
+    state.iter_mut().for_each(|b| *b = SBOX[*b as usize]);
+}
+
+pub fn inv_sub_bytes(state: &mut Block) {
+    state.iter_mut().for_each(|b| *b = INV_SBOX[*b as usize]);
+}
+
+/// Row r is rotated left by r positions
+pub fn shift_rows(state: &mut Block) {
+    let old = *state;
+    for r in 0..4 {
+        for c in 0..4 {
+            state[r + 4 * c] = old[r + 4 * ((c + r) % 4)];
+        }
+    }
+}
+
+pub fn inv_shift_rows(state: &mut Block) {
+    let old = *state;
+    for r in 0..4 {
+        for c in 0..4 {
+            state[r + 4 * ((c + r) % 4)] = old[r + 4 * c];
+        }
+    }
+}
+
+pub fn mix_columns(state: &mut Block) {
+    for column in state.chunks_exact_mut(4) {
+        let [a0, a1, a2, a3] = [column[0], column[1], column[2], column[3]];
+        let all = a0 ^ a1 ^ a2 ^ a3;
+        column[0] ^= all ^ xtime(a0 ^ a1);
+        column[1] ^= all ^ xtime(a1 ^ a2);
+        column[2] ^= all ^ xtime(a2 ^ a3);
+        column[3] ^= all ^ xtime(a3 ^ a0);
+    }
+}
+
+// InvMixColumns factors as a cheap preprocessing step followed by MixColumns
+pub fn inv_mix_columns(state: &mut Block) {
+    for column in state.chunks_exact_mut(4) {
+        let u = xtime(xtime(column[0] ^ column[2]));
+        let v = xtime(xtime(column[1] ^ column[3]));
+        column[0] ^= u;
+        column[1] ^= v;
+        column[2] ^= u;
+        column[3] ^= v;
+    }
+    mix_columns(state);
+}
+
+pub fn add_round_key(state: &mut Block, round_key: &Block) {
+    state.iter_mut().zip(round_key).for_each(|(s, k)| *s ^= k);
+}
+
+/// Expands a 16, 24 or 32 byte key into the Nr + 1 round keys
+pub fn expand_key(key: &[u8]) -> Result<Vec<Block>> {
+    let rounds = KeySize::from_key(key)?.rounds();
+    let nk = key.len() / 4;
+    let total_words = 4 * (rounds + 1);
+
+    let mut words: Vec<[u8; 4]> = key
+        .chunks_exact(4)
+        .map(|w| [w[0], w[1], w[2], w[3]])
+        .collect();
+    let mut rcon = 1;
+    for i in nk..total_words {
+        let mut temp = words[i - 1];
+        if i % nk == 0 {
+            temp.rotate_left(1);
+            temp.iter_mut().for_each(|b| *b = SBOX[*b as usize]);
+            temp[0] ^= rcon;
+            rcon = xtime(rcon);
+        } else if nk > 6 && i % nk == 4 {
+            temp.iter_mut().for_each(|b| *b = SBOX[*b as usize]);
+        }
+        let prev = words[i - nk];
+        words.push([
+            prev[0] ^ temp[0],
+            prev[1] ^ temp[1],
+            prev[2] ^ temp[2],
+            prev[3] ^ temp[3],
+        ]);
+    }
+
+    Ok(words
+        .chunks_exact(4)
+        .map(|round| {
+            let mut round_key = [0; BLOCK_SIZE];
+            for (idx, word) in round.iter().enumerate() {
+                round_key[4 * idx..4 * idx + 4].copy_from_slice(word);
+            }
+            round_key
+        })
+        .collect())
+}
+
+/// Runs the AES-128 key schedule backwards from round key `round`, giving
+/// the cipher key. Any one round key determines all the others.
+pub fn invert_key_schedule(round_key: &Block, round: usize) -> Block {
+    let mut words: Vec<[u8; 4]> = vec![[0; 4]; 4 * round];
+    words.extend(round_key.chunks_exact(4).map(|w| [w[0], w[1], w[2], w[3]]));
+    let rcons: Vec<u8> = std::iter::successors(Some(1u8), |&r| Some(xtime(r)))
+        .take(round)
+        .collect();
+    for i in (4..4 * (round + 1)).rev() {
+        let mut temp = words[i - 1];
+        if i % 4 == 0 {
+            temp.rotate_left(1);
+            temp.iter_mut().for_each(|b| *b = SBOX[*b as usize]);
+            temp[0] ^= rcons[i / 4 - 1];
+        }
+        let word = words[i];
+        words[i - 4] = [
+            word[0] ^ temp[0],
+            word[1] ^ temp[1],
+            word[2] ^ temp[2],
+            word[3] ^ temp[3],
+        ];
+    }
+    let mut key = [0; BLOCK_SIZE];
+    for (idx, word) in words[..4].iter().enumerate() {
+        key[4 * idx..4 * idx + 4].copy_from_slice(word);
+    }
+    key
+}
+
+#[derive(Debug, Clone)]
+pub struct Aes {
+    round_keys: Vec<Block>,
+}
+
+impl Aes {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(Aes {
+            round_keys: expand_key(key)?,
+        })
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.round_keys.len() - 1
+    }
+
+    pub fn round_keys(&self) -> &[Block] {
+        &self.round_keys
+    }
+
+    /// AES with only the first `rounds` rounds of the key's schedule, the last
+    /// of which skips MixColumns like a normal final round. For studying
+    /// reduced-round attacks.
+    pub fn with_rounds(key: &[u8], rounds: usize) -> Result<Self> {
+        let mut round_keys = expand_key(key)?;
+        ensure!(
+            (1..round_keys.len()).contains(&rounds),
+            "expected 1 to {} rounds, got {rounds}",
+            round_keys.len() - 1
+        );
+        round_keys.truncate(rounds + 1);
+        Ok(Aes { round_keys })
+    }
+
+    fn encrypt_round(&self, state: &mut Block, round: usize) {
+        sub_bytes(state);
+        shift_rows(state);
+        if round != self.rounds() {
+            mix_columns(state);
+        }
+        add_round_key(state, &self.round_keys[round]);
+    }
+
+    pub fn encrypt_block(&self, block: &mut Block) {
+        self.encrypt_block_with_hook(block, |_, _| {});
+    }
+
+    /// Encrypts with `hook(round, state)` called on the state at the start of
+    /// each round, before its SubBytes. Used to simulate fault injection.
+    pub fn encrypt_block_with_hook(
+        &self,
+        block: &mut Block,
+        mut hook: impl FnMut(usize, &mut Block),
+    ) {
+        add_round_key(block, &self.round_keys[0]);
+        for round in 1..=self.rounds() {
+            hook(round, block);
+            self.encrypt_round(block, round);
+        }
+    }
+
+    /// The state after the initial AddRoundKey and after each round, so the
+    /// last entry is the ciphertext
+    pub fn trace(&self, block: &Block) -> Vec<Block> {
+        let mut state = *block;
+        add_round_key(&mut state, &self.round_keys[0]);
+        let mut states = vec![state];
+        for round in 1..=self.rounds() {
+            self.encrypt_round(&mut state, round);
+            states.push(state);
+        }
+        states
+    }
+
+    pub fn decrypt_block(&self, block: &mut Block) {
+        let rounds = self.rounds();
+        add_round_key(block, &self.round_keys[rounds]);
+        for round in (1..rounds).rev() {
+            inv_shift_rows(block);
+            inv_sub_bytes(block);
+            add_round_key(block, &self.round_keys[round]);
+            inv_mix_columns(block);
+        }
+        inv_shift_rows(block);
+        inv_sub_bytes(block);
+        add_round_key(block, &self.round_keys[0]);
+    }
+}
+
+impl BlockCipher for Aes {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let mut block: Block = block
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("expected len of {BLOCK_SIZE}, got {}", block.len()))?;
+        Aes::encrypt_block(self, &mut block);
+        Ok(block.to_vec())
+    }
+
+    fn decrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let mut block: Block = block
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("expected len of {BLOCK_SIZE}, got {}", block.len()))?;
+        Aes::decrypt_block(self, &mut block);
+        Ok(block.to_vec())
+    }
+}
+
+/// The expanded key, kept by `AesKey` so key expansion happens once per key
+#[derive(Debug, Clone)]
+pub struct Context(Aes);
+
+impl Context {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(Context(Aes::new(key)?))
+    }
+
+    pub fn encrypt_block(&self, block: &Block) -> Result<Block> {
+        let mut block = *block;
+        self.0.encrypt_block(&mut block);
+        Ok(block)
+    }
+
+    pub fn decrypt_block(&self, block: &Block) -> Result<Block> {
+        let mut block = *block;
+        self.0.decrypt_block(&mut block);
+        Ok(block)
+    }
+}
+
+pub fn encrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    let aes = Aes::new(key)?;
+    let mut out = bytes.pad_pkcs7();
+    for chunk in out.chunks_exact_mut(BLOCK_SIZE) {
+        let mut block = [0; BLOCK_SIZE];
+        block.copy_from_slice(chunk);
+        aes.encrypt_block(&mut block);
+        chunk.copy_from_slice(&block);
+    }
+    Ok(out)
+}
+
+pub fn decrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    ensure!(
+        bytes.len().is_multiple_of(BLOCK_SIZE),
+        "expected multiple of {BLOCK_SIZE}, got {}",
+        bytes.len()
+    );
+    let aes = Aes::new(key)?;
+    let mut out = bytes.to_vec();
+    for chunk in out.chunks_exact_mut(BLOCK_SIZE) {
+        let mut block = [0; BLOCK_SIZE];
+        block.copy_from_slice(chunk);
+        aes.decrypt_block(&mut block);
+        chunk.copy_from_slice(&block);
+    }
+    out.validate_unpad_pkcs7()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{
+            native::{
+                expand_key, inv_mix_columns, inv_shift_rows, inv_sub_bytes, mix_columns,
+                shift_rows, sub_bytes, Aes, Block, INV_SBOX, SBOX,
+            },
+            openssl_backend,
+        },
+        hex::{DecodeHex, EncodeHex},
+        utils::bytes,
+    };
+
+    fn block(hex: &str) -> Block {
+        hex.decode_hex().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_sbox() {
+        assert_eq!(SBOX[0x00], 0x63);
+        assert_eq!(SBOX[0x01], 0x7c);
+        assert_eq!(SBOX[0x53], 0xed);
+        assert_eq!(SBOX[0xff], 0x16);
+        for x in 0..=255u8 {
+            assert_eq!(INV_SBOX[SBOX[x as usize] as usize], x);
+        }
+    }
+
+    // FIPS-197 Appendix B, round 1
+    #[test]
+    fn test_round_steps() {
+        let mut state = block("193de3bea0f4e22b9ac68d2ae9f84808");
+        sub_bytes(&mut state);
+        assert_eq!(state, block("d42711aee0bf98f1b8b45de51e415230"));
+        shift_rows(&mut state);
+        assert_eq!(state, block("d4bf5d30e0b452aeb84111f11e2798e5"));
+        mix_columns(&mut state);
+        assert_eq!(state, block("046681e5e0cb199a48f8d37a2806264c"));
+
+        inv_mix_columns(&mut state);
+        inv_shift_rows(&mut state);
+        inv_sub_bytes(&mut state);
+        assert_eq!(state, block("193de3bea0f4e22b9ac68d2ae9f84808"));
+    }
+
+    // FIPS-197 Appendix A.1
+    #[test]
+    fn test_expand_key() {
+        let round_keys = expand_key(&"2b7e151628aed2a6abf7158809cf4f3c".decode_hex()).unwrap();
+        assert_eq!(round_keys.len(), 11);
+        assert_eq!(
+            round_keys[1].to_vec().to_hex(),
+            "a0fafe1788542cb123a339392a6c7605"
+        );
+        assert_eq!(
+            round_keys[10].to_vec().to_hex(),
+            "d014f9a8c9ee2589e13f0cc8b6630ca6"
+        );
+
+        assert!(expand_key(&[0; 15]).is_err());
+    }
+
+    // FIPS-197 Appendix B and C
+    #[test]
+    fn test_fips_197_vectors() {
+        let vectors = [
+            (
+                "2b7e151628aed2a6abf7158809cf4f3c",
+                "3243f6a8885a308d313198a2e0370734",
+                "3925841d02dc09fbdc118597196a0b32",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f",
+                "00112233445566778899aabbccddeeff",
+                "69c4e0d86a7b0430d8cdb78070b4c55a",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f1011121314151617",
+                "00112233445566778899aabbccddeeff",
+                "dda97ca4864cdfe06eaf70a0ec0d7191",
+            ),
+            (
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "00112233445566778899aabbccddeeff",
+                "8ea2b7ca516745bfeafc49904b496089",
+            ),
+        ];
+        for (key, plaintext, ciphertext) in vectors {
+            let aes = Aes::new(&key.decode_hex()).unwrap();
+            let mut state = block(plaintext);
+            aes.encrypt_block(&mut state);
+            assert_eq!(state, block(ciphertext));
+            aes.decrypt_block(&mut state);
+            assert_eq!(state, block(plaintext));
+        }
+    }
+
+    #[test]
+    fn test_reduced_rounds() {
+        let key = bytes::rand_of_len(16);
+        let block: Block = bytes::rand_of_len(16).try_into().unwrap();
+        let full = Aes::new(&key).unwrap();
+        let mut expected = block;
+        full.encrypt_block(&mut expected);
+        assert_eq!(
+            Aes::with_rounds(&key, 10).unwrap().trace(&block)[10],
+            expected
+        );
+
+        let reduced = Aes::with_rounds(&key, 4).unwrap();
+        let trace = reduced.trace(&block);
+        assert_eq!(trace.len(), 5);
+        let mut encrypted = block;
+        reduced.encrypt_block(&mut encrypted);
+        assert_eq!(trace[4], encrypted);
+        reduced.decrypt_block(&mut encrypted);
+        assert_eq!(encrypted, block);
+
+        assert!(Aes::with_rounds(&key, 0).is_err());
+        assert!(Aes::with_rounds(&key, 11).is_err());
+    }
+
+    #[test]
+    fn test_matches_openssl_backend() {
+        for len in 0..50 {
+            let bytes = bytes::rand_of_len(len);
+            let key = bytes::rand_of_len(16);
+            let encrypted = super::encrypt_ecb(&bytes, &key).unwrap();
+            assert_eq!(
+                encrypted,
+                openssl_backend::encrypt_ecb(&bytes, &key).unwrap()
+            );
+            assert_eq!(super::decrypt_ecb(&encrypted, &key).unwrap(), bytes);
+        }
+    }
+}
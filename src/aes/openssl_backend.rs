@@ -0,0 +1,72 @@
+use std::sync::Mutex;
+
+use anyhow::{ensure, Result};
+use openssl::symm::{decrypt, encrypt, Cipher, Crypter, Mode};
+
+use crate::aes::{
+    native::{Block, BLOCK_SIZE},
+    KeySize,
+};
+
+fn ecb_cipher(key: &[u8]) -> Result<Cipher> {
+    Ok(match KeySize::from_key(key)? {
+        KeySize::Aes128 => Cipher::aes_128_ecb(),
+        KeySize::Aes192 => Cipher::aes_192_ecb(),
+        KeySize::Aes256 => Cipher::aes_256_ecb(),
+    })
+}
+
+pub fn encrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    encrypt(ecb_cipher(key)?, key, None, bytes).map_err(anyhow::Error::from)
+}
+
+pub fn decrypt_ecb(bytes: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    decrypt(ecb_cipher(key)?, key, None, bytes).map_err(anyhow::Error::from)
+}
+
+fn block_crypter(key: &[u8], mode: Mode) -> Result<Mutex<Crypter>> {
+    let mut crypter = Crypter::new(ecb_cipher(key)?, mode, key, None)?;
+    crypter.pad(false);
+    Ok(Mutex::new(crypter))
+}
+
+// ECB with padding disabled has no state between calls, so one crypter per
+// direction serves every block. `update` needs `&mut`, hence the mutex.
+fn crypt_block(crypter: &Mutex<Crypter>, block: &Block) -> Result<Block> {
+    let mut crypter = crypter
+        .lock()
+        .map_err(|_| anyhow::anyhow!("crypter mutex poisoned"))?;
+    let mut out = [0; 2 * BLOCK_SIZE];
+    let count = crypter.update(block, &mut out)?;
+    ensure!(
+        count == BLOCK_SIZE,
+        "expected {BLOCK_SIZE} bytes, got {count}"
+    );
+    let mut result = [0; BLOCK_SIZE];
+    result.copy_from_slice(&out[..BLOCK_SIZE]);
+    Ok(result)
+}
+
+/// Keyed OpenSSL contexts, kept by `AesKey` so the cipher is set up once per key
+/// instead of once per block
+pub struct Context {
+    encrypter: Mutex<Crypter>,
+    decrypter: Mutex<Crypter>,
+}
+
+impl Context {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        Ok(Context {
+            encrypter: block_crypter(key, Mode::Encrypt)?,
+            decrypter: block_crypter(key, Mode::Decrypt)?,
+        })
+    }
+
+    pub fn encrypt_block(&self, block: &Block) -> Result<Block> {
+        crypt_block(&self.encrypter, block)
+    }
+
+    pub fn decrypt_block(&self, block: &Block) -> Result<Block> {
+        crypt_block(&self.decrypter, block)
+    }
+}
@@ -0,0 +1,161 @@
+// The Square (integral) attack on 4-round AES.
+// Reference: Daemen, Knudsen & Rijmen, "The Block Cipher Square", and
+// https://www.davidwong.fr/blockbreakers/square.html
+//
+// Take a Λ-set: 256 plaintexts that are equal everywhere except one byte, which
+// runs through every value. After three rounds every byte of the state is
+// "balanced": the xor of that byte over all 256 states is zero. The fourth
+// round has no MixColumns, so each ciphertext byte depends on a single state
+// byte and a single last-round-key byte. Guessing that key byte and undoing
+// SubBytes shows whether the balance property holds, one byte at a time.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::native::{invert_key_schedule, Block, BLOCK_SIZE, INV_SBOX},
+    oracle::ReducedAesOracle,
+    utils::{bytes, random::RandomSource},
+};
+
+const ROUNDS: usize = 4;
+
+/// 256 blocks that share random values everywhere but `active`, which takes
+/// every value once
+pub fn lambda_set(active: usize, rng: &mut impl RandomSource) -> Vec<Block> {
+    let mut base = [0; BLOCK_SIZE];
+    base.copy_from_slice(&bytes::rand_of_len_from(rng, BLOCK_SIZE));
+    (0..=255)
+        .map(|value| {
+            let mut block = base;
+            block[active] = value;
+            block
+        })
+        .collect()
+}
+
+/// Whether every byte xors to zero across `states`
+pub fn is_balanced(states: &[Block]) -> bool {
+    states
+        .iter()
+        .fold([0; BLOCK_SIZE], |mut acc, state| {
+            acc.iter_mut().zip(state).for_each(|(a, s)| *a ^= s);
+            acc
+        })
+        .iter()
+        .all(|&b| b == 0)
+}
+
+// Key bytes for `position` under which the state before the last round is
+// balanced. The right byte always is, a wrong one about 1 time in 256.
+fn candidates(ciphertexts: &[Block], position: usize) -> Vec<u8> {
+    (0..=255)
+        .filter(|&guess: &u8| {
+            ciphertexts
+                .iter()
+                .fold(0, |acc, c| acc ^ INV_SBOX[(c[position] ^ guess) as usize])
+                == 0
+        })
+        .collect()
+}
+
+/// Recovers the round 4 key, querying fresh Λ-sets until every byte position
+/// is down to a single candidate
+pub fn recover_last_round_key(oracle: &ReducedAesOracle) -> Result<Block> {
+    recover_last_round_key_with_rng(oracle, &mut rand::thread_rng())
+}
+
+/// `recover_last_round_key`, drawing the Λ-sets from `rng`
+pub fn recover_last_round_key_with_rng(
+    oracle: &ReducedAesOracle,
+    rng: &mut impl RandomSource,
+) -> Result<Block> {
+    ensure!(
+        oracle.rounds() == ROUNDS,
+        "the attack targets {ROUNDS}-round AES, the oracle has {} rounds",
+        oracle.rounds()
+    );
+    let mut remaining: Vec<Vec<u8>> = vec![(0..=255).collect(); BLOCK_SIZE];
+    for attempt in 0..8 {
+        let mut ciphertexts = vec![];
+        for plaintext in lambda_set(attempt % BLOCK_SIZE, rng) {
+            let mut ciphertext = [0; BLOCK_SIZE];
+            ciphertext.copy_from_slice(&oracle.encrypt(&plaintext)?);
+            ciphertexts.push(ciphertext);
+        }
+        for (position, remaining) in remaining.iter_mut().enumerate() {
+            let found = candidates(&ciphertexts, position);
+            remaining.retain(|k| found.contains(k));
+        }
+        if remaining.iter().all(|r| r.len() == 1) {
+            let mut key = [0; BLOCK_SIZE];
+            key.iter_mut().zip(&remaining).for_each(|(k, r)| *k = r[0]);
+            return Ok(key);
+        }
+        if remaining.iter().any(|r| r.is_empty()) {
+            bail!("no key byte satisfied the balance property");
+        }
+    }
+    bail!("could not narrow down the last round key")
+}
+
+/// Recovers the AES-128 key of a 4-round oracle
+pub fn break_square(oracle: &ReducedAesOracle) -> Result<Vec<u8>> {
+    break_square_with_rng(oracle, &mut rand::thread_rng())
+}
+
+pub fn break_square_with_rng(
+    oracle: &ReducedAesOracle,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<u8>> {
+    let last_round_key = recover_last_round_key_with_rng(oracle, rng)?;
+    Ok(invert_key_schedule(&last_round_key, ROUNDS).to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{
+            native::{expand_key, invert_key_schedule, Aes},
+            square::{
+                break_square_with_rng, is_balanced, lambda_set, recover_last_round_key_with_rng,
+            },
+        },
+        oracle::ReducedAesOracle,
+        utils::{bytes, random::test_rng},
+    };
+
+    #[test]
+    fn test_balance_property() {
+        let mut rng = test_rng();
+        let aes = Aes::with_rounds(&bytes::rand_of_len_from(&mut rng, 16), 4).unwrap();
+        let traces: Vec<_> = lambda_set(5, &mut rng)
+            .iter()
+            .map(|p| aes.trace(p))
+            .collect();
+        let after_round = |round: usize| -> Vec<_> { traces.iter().map(|t| t[round]).collect() };
+        // balanced through round 3, and lost after round 4
+        for round in 0..=3 {
+            assert!(is_balanced(&after_round(round)));
+        }
+        assert!(!is_balanced(&after_round(4)));
+    }
+
+    #[test]
+    fn test_invert_key_schedule() {
+        let key = bytes::rand_of_len(16);
+        let round_keys = expand_key(&key).unwrap();
+        for (round, round_key) in round_keys.iter().enumerate() {
+            assert_eq!(invert_key_schedule(round_key, round).to_vec(), key);
+        }
+    }
+
+    #[test]
+    fn test_square_attack() {
+        let mut rng = test_rng();
+        let oracle = ReducedAesOracle::with_rng(4, &mut rng).unwrap();
+        let key = break_square_with_rng(&oracle, &mut rng).unwrap();
+        assert!(oracle.verify(&key));
+
+        let oracle = ReducedAesOracle::with_rng(5, &mut rng).unwrap();
+        assert!(recover_last_round_key_with_rng(&oracle, &mut rng).is_err());
+    }
+}
@@ -0,0 +1,472 @@
+// A self-describing ciphertext container, so stored ciphertexts carry the
+// parameters needed to decrypt them.
+//
+// Layout, all lengths single bytes:
+//   magic "CPCT" || version || algorithm || mode || padding
+//   || IV length || IV || tag length || tag || ciphertext
+// For GCM everything before the tag length is authenticated as AAD, so the
+// header can't be swapped out from under the tag.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::{AesKey, Mode},
+    cipher::BlockCipher,
+    des::{Des, TripleDes},
+    gcm, modes,
+    utils::{bytes, random::RandomSource},
+};
+
+pub const MAGIC: &[u8; 4] = b"CPCT";
+pub const VERSION: u8 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Aes128,
+    Aes192,
+    Aes256,
+    Des,
+    TripleDes,
+}
+
+impl Algorithm {
+    pub fn key_len(&self) -> usize {
+        match self {
+            Algorithm::Aes128 => 16,
+            Algorithm::Aes192 => 24,
+            Algorithm::Aes256 => 32,
+            Algorithm::Des => 8,
+            Algorithm::TripleDes => 24,
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        match self {
+            Algorithm::Des | Algorithm::TripleDes => 8,
+            _ => 16,
+        }
+    }
+
+    fn cipher(&self, key: &[u8]) -> Result<Box<dyn BlockCipher>> {
+        ensure!(
+            key.len() == self.key_len(),
+            "{self:?} needs a {} byte key, got {}",
+            self.key_len(),
+            key.len()
+        );
+        Ok(match self {
+            Algorithm::Des => Box::new(Des::new(key)?),
+            Algorithm::TripleDes => Box::new(TripleDes::new(key)?),
+            _ => Box::new(AesKey::new(key)?),
+        })
+    }
+
+    fn to_byte(self) -> u8 {
+        match self {
+            Algorithm::Aes128 => 1,
+            Algorithm::Aes192 => 2,
+            Algorithm::Aes256 => 3,
+            Algorithm::Des => 4,
+            Algorithm::TripleDes => 5,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self> {
+        Ok(match byte {
+            1 => Algorithm::Aes128,
+            2 => Algorithm::Aes192,
+            3 => Algorithm::Aes256,
+            4 => Algorithm::Des,
+            5 => Algorithm::TripleDes,
+            _ => bail!("unknown algorithm {byte}"),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Padding {
+    None = 0,
+    Pkcs7 = 1,
+}
+
+impl Padding {
+    /// The padding each mode uses in `modes`
+    pub fn for_mode(mode: Mode) -> Self {
+        match mode {
+            Mode::ECB | Mode::CBC | Mode::PCBC => Padding::Pkcs7,
+            _ => Padding::None,
+        }
+    }
+}
+
+fn mode_to_byte(mode: Mode) -> u8 {
+    match mode {
+        Mode::ECB => 1,
+        Mode::CBC => 2,
+        Mode::CTR => 3,
+        Mode::CFB => 4,
+        Mode::CFB8 => 5,
+        Mode::OFB => 6,
+        Mode::PCBC => 7,
+        Mode::GCM => 8,
+    }
+}
+
+fn mode_from_byte(byte: u8) -> Result<Mode> {
+    Ok(match byte {
+        1 => Mode::ECB,
+        2 => Mode::CBC,
+        3 => Mode::CTR,
+        4 => Mode::CFB,
+        5 => Mode::CFB8,
+        6 => Mode::OFB,
+        7 => Mode::PCBC,
+        8 => Mode::GCM,
+        _ => bail!("unknown mode {byte}"),
+    })
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub algorithm: Algorithm,
+    pub mode: Mode,
+    pub padding: Padding,
+    /// The IV, or for CTR the little-endian nonce (half a block)
+    pub iv: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+impl Header {
+    fn iv_len(algorithm: Algorithm, mode: Mode) -> usize {
+        match mode {
+            Mode::ECB => 0,
+            Mode::CTR => algorithm.block_size() / 2,
+            Mode::GCM => 12,
+            _ => algorithm.block_size(),
+        }
+    }
+
+    fn tag_len(mode: Mode) -> usize {
+        match mode {
+            Mode::GCM => gcm::TAG_LEN,
+            _ => 0,
+        }
+    }
+
+    /// Checks that the fields are consistent with each other
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.mode != Mode::GCM || self.algorithm.block_size() == 16,
+            "GCM needs a 16 byte block cipher, not {:?}",
+            self.algorithm
+        );
+        ensure!(
+            self.padding == Padding::for_mode(self.mode),
+            "{:?} does not use {:?} padding",
+            self.mode,
+            self.padding
+        );
+        let iv_len = Self::iv_len(self.algorithm, self.mode);
+        ensure!(
+            self.iv.len() == iv_len,
+            "{:?} with {:?} needs a {iv_len} byte IV, got {}",
+            self.mode,
+            self.algorithm,
+            self.iv.len()
+        );
+        let tag_len = Self::tag_len(self.mode);
+        ensure!(
+            self.tag.len() == tag_len,
+            "{:?} needs a {tag_len} byte tag, got {}",
+            self.mode,
+            self.tag.len()
+        );
+        Ok(())
+    }
+
+    // Everything up to the tag, which is the GCM AAD
+    fn authenticated_bytes(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.extend([
+            VERSION,
+            self.algorithm.to_byte(),
+            mode_to_byte(self.mode),
+            self.padding as u8,
+            self.iv.len() as u8,
+        ]);
+        out.extend(&self.iv);
+        out
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.authenticated_bytes();
+        out.push(self.tag.len() as u8);
+        out.extend(&self.tag);
+        out
+    }
+
+    /// Parses and validates a header, returning it and the remaining bytes
+    pub fn parse(bytes: &[u8]) -> Result<(Self, &[u8])> {
+        let mut reader = Reader(bytes);
+        ensure!(reader.take(4)? == MAGIC, "not a container: bad magic");
+        let version = reader.byte()?;
+        ensure!(
+            version == VERSION,
+            "unsupported container version {version}"
+        );
+        let algorithm = Algorithm::from_byte(reader.byte()?)?;
+        let mode = mode_from_byte(reader.byte()?)?;
+        let padding = match reader.byte()? {
+            0 => Padding::None,
+            1 => Padding::Pkcs7,
+            byte => bail!("unknown padding {byte}"),
+        };
+        let iv_len = reader.byte()? as usize;
+        let iv = reader.take(iv_len)?.to_vec();
+        let tag_len = reader.byte()? as usize;
+        let tag = reader.take(tag_len)?.to_vec();
+        let header = Header {
+            algorithm,
+            mode,
+            padding,
+            iv,
+            tag,
+        };
+        header.validate()?;
+        Ok((header, reader.0))
+    }
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8]> {
+        ensure!(
+            self.0.len() >= len,
+            "header truncated: wanted {len} bytes, {} left",
+            self.0.len()
+        );
+        let (taken, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Container {
+    pub header: Header,
+    pub ciphertext: Vec<u8>,
+}
+
+impl Container {
+    /// Encrypts `plaintext` under a random IV or nonce
+    pub fn encrypt(algorithm: Algorithm, mode: Mode, key: &[u8], plaintext: &[u8]) -> Result<Self> {
+        Container::encrypt_with_rng(algorithm, mode, key, plaintext, &mut rand::thread_rng())
+    }
+
+    /// `encrypt`, drawing the IV or nonce from `rng`
+    pub fn encrypt_with_rng(
+        algorithm: Algorithm,
+        mode: Mode,
+        key: &[u8],
+        plaintext: &[u8],
+        rng: &mut impl RandomSource,
+    ) -> Result<Self> {
+        let cipher = algorithm.cipher(key)?;
+        let mut header = Header {
+            algorithm,
+            mode,
+            padding: Padding::for_mode(mode),
+            iv: bytes::rand_of_len_from(rng, Header::iv_len(algorithm, mode)),
+            tag: vec![],
+        };
+        let iv = &header.iv;
+        let ciphertext = match mode {
+            Mode::ECB => modes::encrypt_ecb(&cipher, plaintext)?,
+            Mode::CBC => modes::encrypt_cbc(&cipher, plaintext, iv)?,
+            Mode::CTR => modes::ctr(&cipher, plaintext, nonce(iv))?,
+            Mode::CFB => modes::encrypt_cfb(&cipher, plaintext, iv)?,
+            Mode::CFB8 => modes::encrypt_cfb8(&cipher, plaintext, iv)?,
+            Mode::OFB => modes::ofb(&cipher, plaintext, iv)?,
+            Mode::PCBC => modes::encrypt_pcbc(&cipher, plaintext, iv)?,
+            Mode::GCM => {
+                let aad = header.authenticated_bytes();
+                let (ciphertext, tag) = gcm::encrypt(&cipher, iv, plaintext, &aad)?;
+                header.tag = tag;
+                ciphertext
+            }
+        };
+        header.validate()?;
+        Ok(Container { header, ciphertext })
+    }
+
+    pub fn decrypt(&self, key: &[u8]) -> Result<Vec<u8>> {
+        let header = &self.header;
+        header.validate()?;
+        let cipher = header.algorithm.cipher(key)?;
+        let (iv, ciphertext) = (&header.iv, &self.ciphertext);
+        match header.mode {
+            Mode::ECB => modes::decrypt_ecb(&cipher, ciphertext),
+            Mode::CBC => modes::decrypt_cbc(&cipher, ciphertext, iv),
+            Mode::CTR => modes::ctr(&cipher, ciphertext, nonce(iv)),
+            Mode::CFB => modes::decrypt_cfb(&cipher, ciphertext, iv),
+            Mode::CFB8 => modes::decrypt_cfb8(&cipher, ciphertext, iv),
+            Mode::OFB => modes::ofb(&cipher, ciphertext, iv),
+            Mode::PCBC => modes::decrypt_pcbc(&cipher, ciphertext, iv),
+            Mode::GCM => {
+                let aad = header.authenticated_bytes();
+                gcm::decrypt(&cipher, iv, ciphertext, &aad, &header.tag)
+            }
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = self.header.to_bytes();
+        out.extend(&self.ciphertext);
+        out
+    }
+
+    pub fn parse(bytes: &[u8]) -> Result<Self> {
+        let (header, ciphertext) = Header::parse(bytes)?;
+        Ok(Container {
+            header,
+            ciphertext: ciphertext.to_vec(),
+        })
+    }
+}
+
+fn nonce(bytes: &[u8]) -> u64 {
+    let mut nonce = [0; 8];
+    nonce[..bytes.len()].copy_from_slice(bytes);
+    u64::from_le_bytes(nonce)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{self, AesError, Mode},
+        container::{Algorithm, Container, Header, Padding},
+        utils::{bytes, random::seeded},
+    };
+
+    const ALGORITHMS: [Algorithm; 5] = [
+        Algorithm::Aes128,
+        Algorithm::Aes192,
+        Algorithm::Aes256,
+        Algorithm::Des,
+        Algorithm::TripleDes,
+    ];
+    const MODES: [Mode; 8] = [
+        Mode::ECB,
+        Mode::CBC,
+        Mode::CTR,
+        Mode::CFB,
+        Mode::CFB8,
+        Mode::OFB,
+        Mode::PCBC,
+        Mode::GCM,
+    ];
+
+    #[test]
+    fn test_container_roundtrip() {
+        for algorithm in ALGORITHMS {
+            let key = bytes::rand_of_len(algorithm.key_len());
+            for mode in MODES {
+                let plaintext = bytes::rand_of_len(37);
+                let container = Container::encrypt(algorithm, mode, &key, &plaintext);
+                if mode == Mode::GCM && algorithm.block_size() == 8 {
+                    assert!(container.is_err());
+                    continue;
+                }
+                let serialized = container.unwrap().to_bytes();
+                // a tool needs nothing but the key to decrypt it
+                let parsed = Container::parse(&serialized).unwrap();
+                assert_eq!(parsed.header.algorithm, algorithm);
+                assert_eq!(parsed.header.mode, mode);
+                assert_eq!(parsed.decrypt(&key).unwrap(), plaintext);
+            }
+        }
+        // the IV comes from the injected RNG
+        let encrypt = |seed| {
+            Container::encrypt_with_rng(
+                Algorithm::Aes128,
+                Mode::CBC,
+                &[0; 16],
+                b"hi",
+                &mut seeded(seed),
+            )
+            .unwrap()
+            .to_bytes()
+        };
+        assert_eq!(encrypt(1), encrypt(1));
+        assert_ne!(encrypt(1), encrypt(2));
+    }
+
+    #[test]
+    fn test_container_matches_modes() {
+        let key = bytes::rand_of_len(16);
+        let container = Container::encrypt(Algorithm::Aes128, Mode::CBC, &key, b"hello").unwrap();
+        assert_eq!(container.header.padding, Padding::Pkcs7);
+        assert_eq!(
+            container.ciphertext,
+            aes::encrypt_aes_cbc(b"hello", &container.header.iv, &key).unwrap()
+        );
+
+        let container = Container::encrypt(Algorithm::Aes128, Mode::CTR, &key, b"hello").unwrap();
+        let nonce = u64::from_le_bytes(container.header.iv.clone().try_into().unwrap());
+        assert_eq!(
+            container.ciphertext,
+            aes::encrypt_aes_ctr(b"hello", &key, nonce).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_container_rejects_malformed_headers() {
+        let key = bytes::rand_of_len(16);
+        let container = Container::encrypt(Algorithm::Aes128, Mode::CBC, &key, b"hello").unwrap();
+        let serialized = container.to_bytes();
+
+        // every truncation inside the header fails to parse
+        let header_len = container.header.to_bytes().len();
+        for len in 0..header_len {
+            assert!(Container::parse(&serialized[..len]).is_err());
+        }
+        // bad magic, version, algorithm, mode, padding and IV length
+        for (idx, value) in [(0, b'X'), (4, 2), (5, 9), (6, 0), (7, 0), (8, 8)] {
+            let mut bad = serialized.clone();
+            bad[idx] = value;
+            assert!(Container::parse(&bad).is_err(), "byte {idx}");
+        }
+        // a tag on a mode that has none
+        let mut bad = container.header.clone();
+        bad.tag = vec![0; 16];
+        assert!(Container::parse(&bad.to_bytes()).is_err());
+        assert!(Container::parse(
+            &Header {
+                mode: Mode::GCM,
+                ..container.header.clone()
+            }
+            .to_bytes()
+        )
+        .is_err());
+
+        assert!(container.decrypt(&key[..8]).is_err());
+    }
+
+    #[test]
+    fn test_gcm_container_authenticates_header() {
+        let key = bytes::rand_of_len(32);
+        let container = Container::encrypt(Algorithm::Aes256, Mode::GCM, &key, b"hello").unwrap();
+        let mut tampered = container.clone();
+        tampered.header.iv[0] ^= 1;
+        let err = tampered.decrypt(&key).unwrap_err();
+        assert!(matches!(err.downcast_ref(), Some(AesError::InvalidTag(_))));
+
+        let mut tampered = container.clone();
+        tampered.ciphertext[0] ^= 1;
+        assert!(tampered.decrypt(&key).is_err());
+    }
+}
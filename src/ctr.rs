@@ -0,0 +1,278 @@
+// Counter mode with a configurable counter block layout and random access.
+// References: NIST SP 800-38A section 6.5, RFC 3686, NIST SP 800-38D section 6.5
+use anyhow::{ensure, Result};
+
+use crate::{cipher::BlockCipher, xor::Xor};
+
+/// How the nonce and the block counter are laid out in the counter block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CounterLayout {
+    /// Little-endian nonce followed by a little-endian block counter, each
+    /// taking half of the block. This is the cryptopals layout.
+    LittleEndian64 { nonce: u64 },
+    /// A 96-bit nonce followed by a big-endian 32-bit counter that wraps
+    /// modulo 2^32, as used by GCM and RFC 3686 (nonce || IV || counter).
+    BigEndian96 { nonce: [u8; 12] },
+    /// The whole block is a big-endian counter starting from `initial`, as in
+    /// the SP 800-38A examples.
+    BigEndian128 { initial: [u8; 16] },
+    /// The first 32 bits of `initial` are a little-endian counter that wraps
+    /// modulo 2^32 and the rest stays fixed, as used by AES-GCM-SIV.
+    LittleEndian32 { initial: [u8; 16] },
+}
+
+#[derive(Debug, Clone)]
+pub struct Ctr<C> {
+    cipher: C,
+    layout: CounterLayout,
+    initial_counter: u64,
+}
+
+impl<C: BlockCipher> Ctr<C> {
+    pub fn new(cipher: C, layout: CounterLayout) -> Result<Self> {
+        let block_size = cipher.block_size();
+        match layout {
+            CounterLayout::LittleEndian64 { nonce } => {
+                let half = block_size / 2;
+                ensure!(
+                    half >= 8 || nonce >> (8 * half) == 0,
+                    "nonce {nonce} does not fit in {half} bytes"
+                );
+            }
+            CounterLayout::BigEndian96 { .. }
+            | CounterLayout::BigEndian128 { .. }
+            | CounterLayout::LittleEndian32 { .. } => {
+                ensure!(
+                    block_size == 16,
+                    "{layout:?} requires a 16 byte block cipher, got {block_size}"
+                );
+            }
+        }
+        Ok(Ctr {
+            cipher,
+            layout,
+            initial_counter: 0,
+        })
+    }
+
+    /// The counter value of the first block, e.g. 1 for RFC 3686 or 2 for the
+    /// GCM payload. Defaults to 0.
+    pub fn with_initial_counter(mut self, counter: u64) -> Self {
+        self.initial_counter = counter;
+        self
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.cipher.block_size()
+    }
+
+    /// The counter block for the `idx`th block of the keystream
+    pub fn counter_block(&self, idx: u64) -> Vec<u8> {
+        let counter = self.initial_counter.wrapping_add(idx);
+        match self.layout {
+            CounterLayout::LittleEndian64 { nonce } => {
+                let block_size = self.block_size();
+                let half = block_size / 2;
+                let len = half.min(8);
+                let mut block = vec![0; block_size];
+                block[..len].copy_from_slice(&nonce.to_le_bytes()[..len]);
+                block[half..half + len].copy_from_slice(&counter.to_le_bytes()[..len]);
+                block
+            }
+            CounterLayout::BigEndian96 { nonce } => {
+                let mut block = nonce.to_vec();
+                block.extend((counter as u32).to_be_bytes());
+                block
+            }
+            CounterLayout::BigEndian128 { initial } => u128::from_be_bytes(initial)
+                .wrapping_add(counter as u128)
+                .to_be_bytes()
+                .to_vec(),
+            CounterLayout::LittleEndian32 { initial } => {
+                let start = u32::from_le_bytes([initial[0], initial[1], initial[2], initial[3]]);
+                let mut block = start.wrapping_add(counter as u32).to_le_bytes().to_vec();
+                block.extend(&initial[4..]);
+                block
+            }
+        }
+    }
+
+    /// `len` bytes of keystream starting at byte `offset`. Only the blocks that
+    /// overlap the range are encrypted.
+    pub fn keystream(&self, offset: usize, len: usize) -> Result<Vec<u8>> {
+        let block_size = self.block_size();
+        let first = offset / block_size;
+        let last = (offset + len).div_ceil(block_size);
+        let mut keystream = vec![];
+        for idx in first..last {
+            keystream.extend(self.cipher.encrypt_block(&self.counter_block(idx as u64))?);
+        }
+        let skip = offset - first * block_size;
+        Ok(keystream[skip..skip + len].to_vec())
+    }
+
+    /// Encrypts or decrypts `bytes` from the start of the keystream
+    pub fn apply(&self, bytes: &[u8]) -> Result<Vec<u8>> {
+        self.apply_at(0, bytes)
+    }
+
+    /// Encrypts or decrypts `bytes` as if they sat at byte `offset` of the
+    /// message, so any slice can be processed without touching the rest.
+    pub fn apply_at(&self, offset: usize, bytes: &[u8]) -> Result<Vec<u8>> {
+        Ok(bytes.xor(&self.keystream(offset, bytes.len())?))
+    }
+
+    /// Replaces the plaintext at `offset` with `newtext`, re-encrypting only
+    /// the affected blocks. The ciphertext grows if `newtext` runs past its end.
+    pub fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> Result<Vec<u8>> {
+        ensure!(
+            offset <= ciphertext.len(),
+            "offset {offset} is past the end of the {} byte ciphertext",
+            ciphertext.len()
+        );
+        let mut edited = ciphertext[..offset].to_vec();
+        edited.extend(self.apply_at(offset, newtext)?);
+        if let Some(rest) = ciphertext.get(offset + newtext.len()..) {
+            edited.extend(rest);
+        }
+        Ok(edited)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        aes::AesKey,
+        cipher::ToyCipher,
+        ctr::{CounterLayout, Ctr},
+        hex::{DecodeHex, EncodeHex},
+        utils::bytes,
+    };
+
+    fn nonce96(hex: &str) -> [u8; 12] {
+        hex.decode_hex().try_into().unwrap()
+    }
+
+    #[test]
+    fn test_rfc3686_vectors() {
+        // (key, nonce || IV, plaintext, ciphertext), RFC 3686 section 6
+        let vectors = [
+            (
+                "ae6852f8121067cc4bf7a5765577f39e",
+                "000000300000000000000000",
+                "53696e676c6520626c6f636b206d7367",
+                "e4095d4fb7a7b3792d6175a3261311b8",
+            ),
+            (
+                "7e24067817fae0d743d6ce1f32539163",
+                "006cb6dbc0543b59da48d90b",
+                "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f",
+                "5104a106168a72d9790d41ee8edad388eb2e1efc46da57c8fce630df9141be28",
+            ),
+        ];
+        for (key, nonce, plaintext, expected) in vectors {
+            let cipher = AesKey::new(&key.decode_hex()).unwrap();
+            let layout = CounterLayout::BigEndian96 {
+                nonce: nonce96(nonce),
+            };
+            let ctr = Ctr::new(cipher, layout).unwrap().with_initial_counter(1);
+            let encrypted = ctr.apply(&plaintext.decode_hex()).unwrap();
+            assert_eq!(encrypted.to_hex(), expected);
+            assert_eq!(ctr.apply(&encrypted).unwrap().to_hex(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_sp800_38a_vector() {
+        // NIST SP 800-38A F.5.1, CTR-AES128.Encrypt
+        let cipher = AesKey::new(&"2b7e151628aed2a6abf7158809cf4f3c".decode_hex()).unwrap();
+        let layout = CounterLayout::BigEndian128 {
+            initial: "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff"
+                .decode_hex()
+                .try_into()
+                .unwrap(),
+        };
+        let ctr = Ctr::new(cipher, layout).unwrap();
+        let plaintext = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710".decode_hex();
+        assert_eq!(
+            ctr.apply(&plaintext).unwrap().to_hex(),
+            "874d6191b620e3261bef6864990db6ce9806f66b7970fdff8617187bb9fffdff5ae4df3edbd5d35e5b4f09020db03eab1e031dda2fbe03d1792170a0f3009cee"
+        );
+    }
+
+    #[test]
+    fn test_counter_wraps() {
+        let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+        let nonce = nonce96("0102030405060708090a0b0c");
+        let ctr = Ctr::new(cipher.clone(), CounterLayout::BigEndian96 { nonce })
+            .unwrap()
+            .with_initial_counter(u32::MAX as u64);
+        assert_eq!(ctr.counter_block(0)[12..], [0xff; 4]);
+        assert_eq!(ctr.counter_block(1), [&nonce[..], &[0; 4]].concat());
+
+        let ctr = Ctr::new(
+            &cipher,
+            CounterLayout::BigEndian128 {
+                initial: [0xff; 16],
+            },
+        )
+        .unwrap();
+        assert_eq!(ctr.counter_block(1), vec![0; 16]);
+
+        let mut initial = [0xab; 16];
+        initial[..4].copy_from_slice(&[0xff; 4]);
+        let ctr = Ctr::new(&cipher, CounterLayout::LittleEndian32 { initial }).unwrap();
+        assert_eq!(ctr.counter_block(0), initial);
+        assert_eq!(ctr.counter_block(1)[..4], [0; 4]);
+        assert_eq!(ctr.counter_block(1)[4..], initial[4..]);
+    }
+
+    #[test]
+    fn test_seek_and_edit() {
+        let mut rng = rand::thread_rng();
+        let layouts = [
+            CounterLayout::LittleEndian64 { nonce: 3 },
+            CounterLayout::BigEndian96 {
+                nonce: nonce96("000000000000000000000000"),
+            },
+        ];
+        for layout in layouts {
+            let ctr = Ctr::new(AesKey::new(&bytes::rand_of_len(16)).unwrap(), layout).unwrap();
+            let plaintext = bytes::rand_of_len(100);
+            let ciphertext = ctr.apply(&plaintext).unwrap();
+            for _ in 0..20 {
+                let start = rng.gen_range(0..=100);
+                let end = rng.gen_range(start..=100);
+                assert_eq!(
+                    ctr.apply_at(start, &plaintext[start..end]).unwrap(),
+                    ciphertext[start..end]
+                );
+
+                let newtext = bytes::rand_of_len(rng.gen_range(0..40));
+                let edited = ctr.edit(&ciphertext, start, &newtext).unwrap();
+                let mut expected = plaintext.clone();
+                expected.splice(
+                    start..(start + newtext.len()).min(100),
+                    newtext.iter().copied(),
+                );
+                assert_eq!(ctr.apply(&edited).unwrap(), expected);
+            }
+            assert!(ctr.edit(&ciphertext, 101, b"x").is_err());
+        }
+    }
+
+    #[test]
+    fn test_layout_errors() {
+        let toy = ToyCipher::new(&bytes::rand_of_len(8)).unwrap();
+        assert!(Ctr::new(&toy, CounterLayout::LittleEndian64 { nonce: 1 << 40 }).is_err());
+        assert!(Ctr::new(&toy, CounterLayout::BigEndian96 { nonce: [0; 12] }).is_err());
+        let ctr = Ctr::new(&toy, CounterLayout::LittleEndian64 { nonce: 1 }).unwrap();
+        let plaintext = bytes::rand_of_len(20);
+        assert_eq!(
+            ctr.apply(&ctr.apply_at(0, &plaintext).unwrap()).unwrap(),
+            plaintext
+        );
+    }
+}
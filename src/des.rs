@@ -0,0 +1,436 @@
+// DES and Triple-DES, an 8-byte block target for the generic attacks.
+// References: FIPS 46-3, NIST SP 800-67 Rev. 2, NIST SP 800-17
+//
+// The tables below number bits from 1 at the most significant bit, as the
+// standard does. Blocks and keys are handled as big-endian u64s.
+use anyhow::{ensure, Result};
+
+use crate::cipher::BlockCipher;
+
+pub const BLOCK_SIZE: usize = 8;
+
+const IP: [u8; 64] = [
+    58, 50, 42, 34, 26, 18, 10, 2, 60, 52, 44, 36, 28, 20, 12, 4, 62, 54, 46, 38, 30, 22, 14, 6,
+    64, 56, 48, 40, 32, 24, 16, 8, 57, 49, 41, 33, 25, 17, 9, 1, 59, 51, 43, 35, 27, 19, 11, 3, 61,
+    53, 45, 37, 29, 21, 13, 5, 63, 55, 47, 39, 31, 23, 15, 7,
+];
+
+const FP: [u8; 64] = [
+    40, 8, 48, 16, 56, 24, 64, 32, 39, 7, 47, 15, 55, 23, 63, 31, 38, 6, 46, 14, 54, 22, 62, 30,
+    37, 5, 45, 13, 53, 21, 61, 29, 36, 4, 44, 12, 52, 20, 60, 28, 35, 3, 43, 11, 51, 19, 59, 27,
+    34, 2, 42, 10, 50, 18, 58, 26, 33, 1, 41, 9, 49, 17, 57, 25,
+];
+
+const E: [u8; 48] = [
+    32, 1, 2, 3, 4, 5, 4, 5, 6, 7, 8, 9, 8, 9, 10, 11, 12, 13, 12, 13, 14, 15, 16, 17, 16, 17, 18,
+    19, 20, 21, 20, 21, 22, 23, 24, 25, 24, 25, 26, 27, 28, 29, 28, 29, 30, 31, 32, 1,
+];
+
+const P: [u8; 32] = [
+    16, 7, 20, 21, 29, 12, 28, 17, 1, 15, 23, 26, 5, 18, 31, 10, 2, 8, 24, 14, 32, 27, 3, 9, 19,
+    13, 30, 6, 22, 11, 4, 25,
+];
+
+const PC1: [u8; 56] = [
+    57, 49, 41, 33, 25, 17, 9, 1, 58, 50, 42, 34, 26, 18, 10, 2, 59, 51, 43, 35, 27, 19, 11, 3, 60,
+    52, 44, 36, 63, 55, 47, 39, 31, 23, 15, 7, 62, 54, 46, 38, 30, 22, 14, 6, 61, 53, 45, 37, 29,
+    21, 13, 5, 28, 20, 12, 4,
+];
+
+const PC2: [u8; 48] = [
+    14, 17, 11, 24, 1, 5, 3, 28, 15, 6, 21, 10, 23, 19, 12, 4, 26, 8, 16, 7, 27, 20, 13, 2, 41, 52,
+    31, 37, 47, 55, 30, 40, 51, 45, 33, 48, 44, 49, 39, 56, 34, 53, 46, 42, 50, 36, 29, 32,
+];
+
+const SHIFTS: [u32; 16] = [1, 1, 2, 2, 2, 2, 2, 2, 1, 2, 2, 2, 2, 2, 2, 1];
+
+const SBOXES: [[u8; 64]; 8] = [
+    [
+        14, 4, 13, 1, 2, 15, 11, 8, 3, 10, 6, 12, 5, 9, 0, 7, 0, 15, 7, 4, 14, 2, 13, 1, 10, 6, 12,
+        11, 9, 5, 3, 8, 4, 1, 14, 8, 13, 6, 2, 11, 15, 12, 9, 7, 3, 10, 5, 0, 15, 12, 8, 2, 4, 9,
+        1, 7, 5, 11, 3, 14, 10, 0, 6, 13,
+    ],
+    [
+        15, 1, 8, 14, 6, 11, 3, 4, 9, 7, 2, 13, 12, 0, 5, 10, 3, 13, 4, 7, 15, 2, 8, 14, 12, 0, 1,
+        10, 6, 9, 11, 5, 0, 14, 7, 11, 10, 4, 13, 1, 5, 8, 12, 6, 9, 3, 2, 15, 13, 8, 10, 1, 3, 15,
+        4, 2, 11, 6, 7, 12, 0, 5, 14, 9,
+    ],
+    [
+        10, 0, 9, 14, 6, 3, 15, 5, 1, 13, 12, 7, 11, 4, 2, 8, 13, 7, 0, 9, 3, 4, 6, 10, 2, 8, 5,
+        14, 12, 11, 15, 1, 13, 6, 4, 9, 8, 15, 3, 0, 11, 1, 2, 12, 5, 10, 14, 7, 1, 10, 13, 0, 6,
+        9, 8, 7, 4, 15, 14, 3, 11, 5, 2, 12,
+    ],
+    [
+        7, 13, 14, 3, 0, 6, 9, 10, 1, 2, 8, 5, 11, 12, 4, 15, 13, 8, 11, 5, 6, 15, 0, 3, 4, 7, 2,
+        12, 1, 10, 14, 9, 10, 6, 9, 0, 12, 11, 7, 13, 15, 1, 3, 14, 5, 2, 8, 4, 3, 15, 0, 6, 10, 1,
+        13, 8, 9, 4, 5, 11, 12, 7, 2, 14,
+    ],
+    [
+        2, 12, 4, 1, 7, 10, 11, 6, 8, 5, 3, 15, 13, 0, 14, 9, 14, 11, 2, 12, 4, 7, 13, 1, 5, 0, 15,
+        10, 3, 9, 8, 6, 4, 2, 1, 11, 10, 13, 7, 8, 15, 9, 12, 5, 6, 3, 0, 14, 11, 8, 12, 7, 1, 14,
+        2, 13, 6, 15, 0, 9, 10, 4, 5, 3,
+    ],
+    [
+        12, 1, 10, 15, 9, 2, 6, 8, 0, 13, 3, 4, 14, 7, 5, 11, 10, 15, 4, 2, 7, 12, 9, 5, 6, 1, 13,
+        14, 0, 11, 3, 8, 9, 14, 15, 5, 2, 8, 12, 3, 7, 0, 4, 10, 1, 13, 11, 6, 4, 3, 2, 12, 9, 5,
+        15, 10, 11, 14, 1, 7, 6, 0, 8, 13,
+    ],
+    [
+        4, 11, 2, 14, 15, 0, 8, 13, 3, 12, 9, 7, 5, 10, 6, 1, 13, 0, 11, 7, 4, 9, 1, 10, 14, 3, 5,
+        12, 2, 15, 8, 6, 1, 4, 11, 13, 12, 3, 7, 14, 10, 15, 6, 8, 0, 5, 9, 2, 6, 11, 13, 8, 1, 4,
+        10, 7, 9, 5, 0, 15, 14, 2, 3, 12,
+    ],
+    [
+        13, 2, 8, 4, 6, 15, 11, 1, 10, 9, 3, 14, 5, 0, 12, 7, 1, 15, 13, 8, 10, 3, 7, 4, 12, 5, 6,
+        11, 0, 14, 9, 2, 7, 11, 4, 1, 9, 12, 14, 2, 0, 6, 10, 13, 15, 3, 5, 8, 2, 1, 14, 7, 4, 10,
+        8, 13, 15, 12, 9, 0, 3, 5, 6, 11,
+    ],
+];
+
+/// The four weak keys, for which encryption is its own inverse
+pub const WEAK_KEYS: [[u8; 8]; 4] = [
+    [0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01],
+    [0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xfe, 0xfe],
+    [0xe0, 0xe0, 0xe0, 0xe0, 0xf1, 0xf1, 0xf1, 0xf1],
+    [0x1f, 0x1f, 0x1f, 0x1f, 0x0e, 0x0e, 0x0e, 0x0e],
+];
+
+/// The six pairs of semi-weak keys: encrypting under one of a pair decrypts
+/// under the other
+pub const SEMI_WEAK_KEY_PAIRS: [([u8; 8], [u8; 8]); 6] = [
+    (
+        [0x01, 0xfe, 0x01, 0xfe, 0x01, 0xfe, 0x01, 0xfe],
+        [0xfe, 0x01, 0xfe, 0x01, 0xfe, 0x01, 0xfe, 0x01],
+    ),
+    (
+        [0x1f, 0xe0, 0x1f, 0xe0, 0x0e, 0xf1, 0x0e, 0xf1],
+        [0xe0, 0x1f, 0xe0, 0x1f, 0xf1, 0x0e, 0xf1, 0x0e],
+    ),
+    (
+        [0x01, 0xe0, 0x01, 0xe0, 0x01, 0xf1, 0x01, 0xf1],
+        [0xe0, 0x01, 0xe0, 0x01, 0xf1, 0x01, 0xf1, 0x01],
+    ),
+    (
+        [0x1f, 0xfe, 0x1f, 0xfe, 0x0e, 0xfe, 0x0e, 0xfe],
+        [0xfe, 0x1f, 0xfe, 0x1f, 0xfe, 0x0e, 0xfe, 0x0e],
+    ),
+    (
+        [0x01, 0x1f, 0x01, 0x1f, 0x01, 0x0e, 0x01, 0x0e],
+        [0x1f, 0x01, 0x1f, 0x01, 0x0e, 0x01, 0x0e, 0x01],
+    ),
+    (
+        [0xe0, 0xfe, 0xe0, 0xfe, 0xf1, 0xfe, 0xf1, 0xfe],
+        [0xfe, 0xe0, 0xfe, 0xe0, 0xfe, 0xf1, 0xfe, 0xf1],
+    ),
+];
+
+/// Whether every byte of `key` has odd parity, as FIPS 46-3 asks of the low
+/// bit of each byte. DES itself ignores those bits.
+pub fn has_odd_parity(key: &[u8]) -> bool {
+    key.iter().all(|b| b.count_ones() % 2 == 1)
+}
+
+/// `key` with the low bit of each byte set to give it odd parity
+pub fn set_odd_parity(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .map(|&b| {
+            let high = b & 0xfe;
+            high | ((high.count_ones() as u8 + 1) % 2)
+        })
+        .collect()
+}
+
+fn parity_adjusted(key: &[u8]) -> [u8; 8] {
+    let mut adjusted = [0; 8];
+    adjusted.copy_from_slice(&set_odd_parity(key));
+    adjusted
+}
+
+/// Whether `key` is one of the weak keys, ignoring parity bits
+pub fn is_weak_key(key: &[u8]) -> bool {
+    key.len() == 8 && WEAK_KEYS.contains(&parity_adjusted(key))
+}
+
+/// Whether `key` is one of the semi-weak keys, ignoring parity bits
+pub fn is_semi_weak_key(key: &[u8]) -> bool {
+    key.len() == 8 && {
+        let key = parity_adjusted(key);
+        SEMI_WEAK_KEY_PAIRS
+            .iter()
+            .any(|(a, b)| *a == key || *b == key)
+    }
+}
+
+// Gathers the bits of the `width`-bit `input` listed in `table` into the low
+// `table.len()` bits of the output
+fn permute(input: u64, width: u32, table: &[u8]) -> u64 {
+    table.iter().fold(0, |out, &bit| {
+        (out << 1) | ((input >> (width - bit as u32)) & 1)
+    })
+}
+
+fn feistel(half: u32, subkey: u64) -> u32 {
+    let expanded = permute(half as u64, 32, &E) ^ subkey;
+    let mut substituted = 0u32;
+    for (i, sbox) in SBOXES.iter().enumerate() {
+        let six = ((expanded >> (42 - 6 * i)) & 0x3f) as usize;
+        // the outer bits pick the row, the inner four the column
+        let row = ((six & 0x20) >> 4) | (six & 1);
+        let column = (six >> 1) & 0xf;
+        substituted = (substituted << 4) | sbox[row * 16 + column] as u32;
+    }
+    permute(substituted as u64, 32, &P) as u32
+}
+
+fn to_u64(block: &[u8]) -> Result<u64> {
+    ensure!(
+        block.len() == BLOCK_SIZE,
+        "expected len of {BLOCK_SIZE}, got {}",
+        block.len()
+    );
+    Ok(u64::from_be_bytes(block.try_into()?))
+}
+
+#[derive(Debug, Clone)]
+pub struct Des {
+    subkeys: [u64; 16],
+}
+
+impl Des {
+    /// Parity bits are ignored, and weak keys are accepted; check them with
+    /// `has_odd_parity` and `is_weak_key` where that matters.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        ensure!(key.len() == 8, "expected 8 byte key, got {}", key.len());
+        let permuted = permute(to_u64(key)?, 64, &PC1);
+        let (mut c, mut d) = ((permuted >> 28) as u32, (permuted & 0xfff_ffff) as u32);
+        let mut subkeys = [0; 16];
+        for (subkey, shift) in subkeys.iter_mut().zip(SHIFTS) {
+            c = ((c << shift) | (c >> (28 - shift))) & 0xfff_ffff;
+            d = ((d << shift) | (d >> (28 - shift))) & 0xfff_ffff;
+            *subkey = permute(((c as u64) << 28) | d as u64, 56, &PC2);
+        }
+        Ok(Des { subkeys })
+    }
+
+    fn crypt(&self, block: u64, subkeys: impl Iterator<Item = u64>) -> u64 {
+        let permuted = permute(block, 64, &IP);
+        let (mut left, mut right) = ((permuted >> 32) as u32, permuted as u32);
+        for subkey in subkeys {
+            (left, right) = (right, left ^ feistel(right, subkey));
+        }
+        // the halves are swapped back before the final permutation
+        permute(((right as u64) << 32) | left as u64, 64, &FP)
+    }
+
+    fn encrypt_u64(&self, block: u64) -> u64 {
+        self.crypt(block, self.subkeys.iter().copied())
+    }
+
+    fn decrypt_u64(&self, block: u64) -> u64 {
+        self.crypt(block, self.subkeys.iter().rev().copied())
+    }
+}
+
+impl BlockCipher for Des {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.encrypt_u64(to_u64(block)?).to_be_bytes().to_vec())
+    }
+
+    fn decrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        Ok(self.decrypt_u64(to_u64(block)?).to_be_bytes().to_vec())
+    }
+}
+
+/// Triple-DES in EDE form: E_K3(D_K2(E_K1(P))). A 16 byte key is the two-key
+/// variant (EDE2, K3 = K1) and a 24 byte key the three-key variant (EDE3).
+#[derive(Debug, Clone)]
+pub struct TripleDes {
+    keys: [Des; 3],
+}
+
+impl TripleDes {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        ensure!(
+            key.len() == 16 || key.len() == 24,
+            "expected 16 or 24 byte key, got {}",
+            key.len()
+        );
+        let k1 = Des::new(&key[..8])?;
+        let k2 = Des::new(&key[8..16])?;
+        let k3 = match key.get(16..) {
+            Some(k3) if !k3.is_empty() => Des::new(k3)?,
+            _ => k1.clone(),
+        };
+        Ok(TripleDes { keys: [k1, k2, k3] })
+    }
+}
+
+impl BlockCipher for TripleDes {
+    fn block_size(&self) -> usize {
+        BLOCK_SIZE
+    }
+
+    fn encrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let [k1, k2, k3] = &self.keys;
+        let encrypted = k3.encrypt_u64(k2.decrypt_u64(k1.encrypt_u64(to_u64(block)?)));
+        Ok(encrypted.to_be_bytes().to_vec())
+    }
+
+    fn decrypt_block(&self, block: &[u8]) -> Result<Vec<u8>> {
+        let [k1, k2, k3] = &self.keys;
+        let decrypted = k1.decrypt_u64(k2.encrypt_u64(k3.decrypt_u64(to_u64(block)?)));
+        Ok(decrypted.to_be_bytes().to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{break_cbc_padding_oracle, break_ecb, detect_ecb},
+        cipher::BlockCipher,
+        des::{
+            has_odd_parity, is_semi_weak_key, is_weak_key, set_odd_parity, Des, TripleDes,
+            SEMI_WEAK_KEY_PAIRS, WEAK_KEYS,
+        },
+        hex::{DecodeHex, EncodeHex},
+        modes,
+        oracle::{CbcPaddingOracle, EcbOracle, PrefixEcbOracle},
+        utils::bytes,
+    };
+
+    #[test]
+    fn test_des_vectors() {
+        // (key, plaintext, ciphertext): the FIPS 46 worked example, then NIST
+        // SP 800-17 variable plaintext, variable key and S-box tests
+        let vectors = [
+            ("133457799bbcdff1", "0123456789abcdef", "85e813540f0ab405"),
+            ("0101010101010101", "8000000000000000", "95f8a5e5dd31d900"),
+            ("0101010101010101", "0000000000000001", "166b40b44aba4bd6"),
+            ("8001010101010101", "0000000000000000", "95a8d72813daa94d"),
+            ("7ca110454a1a6e57", "01a1d6d039776742", "690f5b0d9a26939b"),
+        ];
+        for (key, plaintext, expected) in vectors {
+            let des = Des::new(&key.decode_hex()).unwrap();
+            let encrypted = des.encrypt_block(&plaintext.decode_hex()).unwrap();
+            assert_eq!(encrypted.to_hex(), expected);
+            assert_eq!(des.decrypt_block(&encrypted).unwrap().to_hex(), plaintext);
+        }
+        assert!(Des::new(&[0; 7]).is_err());
+        assert!(Des::new(&[0; 8]).unwrap().encrypt_block(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_triple_des_vectors() {
+        // NIST SP 800-67 Rev. 2 example, then the same with K3 = K1
+        let plaintext = "5468652071756663".decode_hex();
+        let vectors = [
+            (
+                "0123456789abcdef23456789abcdef01456789abcdef0123",
+                "a826fd8ce53b855f",
+            ),
+            ("0123456789abcdef23456789abcdef01", "c44862f70cf2fbdc"),
+        ];
+        for (key, expected) in vectors {
+            let tdes = TripleDes::new(&key.decode_hex()).unwrap();
+            let encrypted = tdes.encrypt_block(&plaintext).unwrap();
+            assert_eq!(encrypted.to_hex(), expected);
+            assert_eq!(tdes.decrypt_block(&encrypted).unwrap(), plaintext);
+        }
+
+        // with all three keys equal, 3DES collapses to single DES
+        let key = bytes::rand_of_len(8);
+        let block = bytes::rand_of_len(8);
+        assert_eq!(
+            TripleDes::new(&key.repeat(3))
+                .unwrap()
+                .encrypt_block(&block)
+                .unwrap(),
+            Des::new(&key).unwrap().encrypt_block(&block).unwrap()
+        );
+        assert!(TripleDes::new(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn test_triple_des_matches_openssl() {
+        use openssl::{
+            nid::Nid,
+            symm::{encrypt, Cipher},
+        };
+        let ede2 = Cipher::from_nid(Nid::DES_EDE_CBC).unwrap();
+        for (cipher, key_len) in [(Cipher::des_ede3_cbc(), 24), (ede2, 16)] {
+            let key = bytes::rand_of_len(key_len);
+            let iv = bytes::rand_of_len(8);
+            let plaintext = bytes::rand_of_len(100);
+            let expected = encrypt(cipher, &key, Some(&iv), &plaintext).unwrap();
+            let tdes = TripleDes::new(&key).unwrap();
+            assert_eq!(
+                modes::encrypt_cbc(&tdes, &plaintext, &iv).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_parity_and_weak_keys() {
+        let key = "133457799bbcdff1".decode_hex();
+        assert!(has_odd_parity(&key));
+        let stripped: Vec<u8> = key.iter().map(|b| b & 0xfe).collect();
+        assert!(!has_odd_parity(&stripped));
+        assert_eq!(set_odd_parity(&stripped), key);
+        // the parity bits make no difference to the cipher
+        let block = bytes::rand_of_len(8);
+        assert_eq!(
+            Des::new(&stripped).unwrap().encrypt_block(&block).unwrap(),
+            Des::new(&key).unwrap().encrypt_block(&block).unwrap()
+        );
+
+        for key in WEAK_KEYS {
+            assert!(has_odd_parity(&key));
+            assert!(is_weak_key(&key));
+            let des = Des::new(&key).unwrap();
+            let encrypted = des.encrypt_block(&block).unwrap();
+            assert_eq!(des.encrypt_block(&encrypted).unwrap(), block);
+        }
+        assert!(is_weak_key(&[0; 8]));
+
+        for (a, b) in SEMI_WEAK_KEY_PAIRS {
+            assert!(is_semi_weak_key(&a) && is_semi_weak_key(&b));
+            assert!(!is_weak_key(&a));
+            let encrypted = Des::new(&a).unwrap().encrypt_block(&block).unwrap();
+            assert_eq!(
+                Des::new(&b).unwrap().encrypt_block(&encrypted).unwrap(),
+                block
+            );
+        }
+        assert!(!is_weak_key(&key) && !is_semi_weak_key(&key));
+    }
+
+    #[test]
+    fn test_generic_attacks_with_des() {
+        let secret = "Rollin' in my 5.0, with my rag-top down"
+            .as_bytes()
+            .to_vec();
+        let ciphers: [Box<dyn BlockCipher>; 2] = [
+            Box::new(Des::new(&bytes::rand_of_len(8)).unwrap()),
+            Box::new(TripleDes::new(&bytes::rand_of_len(24)).unwrap()),
+        ];
+        for cipher in ciphers {
+            let encrypted = modes::encrypt_ecb(&cipher, &[b'A'; 32]).unwrap();
+            assert!(detect_ecb(&encrypted, 8));
+
+            let oracle = EcbOracle::with_cipher(&cipher, secret.clone());
+            assert!(oracle.verify(&break_ecb(&oracle).unwrap()));
+
+            let oracle = PrefixEcbOracle::with_cipher(&cipher, secret.clone());
+            assert!(oracle.verify(&break_ecb(&oracle).unwrap()));
+
+            let oracle = CbcPaddingOracle::with_cipher(&cipher, secret.clone()).unwrap();
+            let broken = break_cbc_padding_oracle(&oracle, &oracle.iv, &oracle.ciphertext);
+            assert!(oracle.verify(&broken.unwrap()));
+        }
+    }
+}
@@ -0,0 +1,562 @@
+// Working out how an unknown target encrypts: block size, block or stream
+// cipher, fixed or random IVs, and the mode.
+//
+// With oracle access the questions are answered by experiment: the ciphertext
+// length as the input grows gives the block size, encrypting the same input
+// twice shows whether the IV changes, repeated input blocks expose ECB, and
+// flipping one input byte shows how far a change propagates. From ciphertexts
+// alone only the lengths and repeated blocks are available, so the verdicts
+// are weaker.
+use std::{collections::BTreeSet, fmt};
+
+use anyhow::Result;
+
+use crate::{
+    aes::{detect_ecb, Mode},
+    oracle::EncryptionOracle,
+};
+
+// The block sizes considered when only ciphertexts are available
+const BLOCK_SIZES: [usize; 2] = [16, 8];
+// Inputs up to this long are tried when looking for the block size
+const MAX_BLOCK_SIZE: usize = 32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CipherKind {
+    Block,
+    Stream,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IvBehaviour {
+    /// Encryption is deterministic: the IV or nonce is fixed, or there is none
+    Fixed,
+    Random,
+}
+
+/// A conclusion, how sure we are of it between 0 and 1, and why
+#[derive(Debug, Clone, PartialEq)]
+pub struct Verdict<T> {
+    pub value: T,
+    pub confidence: f64,
+    pub evidence: Vec<String>,
+}
+
+impl<T> Verdict<T> {
+    fn new(value: T, confidence: f64, evidence: impl Into<String>) -> Self {
+        Verdict {
+            value,
+            confidence,
+            evidence: vec![evidence.into()],
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Display for Verdict<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} ({:.0}%): {}",
+            self.value,
+            100.0 * self.confidence,
+            self.evidence.join("; ")
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fingerprint {
+    /// `None` for a stream cipher, or a mode that doesn't pad
+    pub block_size: Verdict<Option<usize>>,
+    pub kind: Verdict<CipherKind>,
+    pub iv: Verdict<IvBehaviour>,
+    /// OFB can't be told apart from CTR and PCBC can't be told apart from
+    /// CBC, so those report CTR and CBC.
+    pub mode: Verdict<Mode>,
+}
+
+impl fmt::Display for Fingerprint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "block size: {}", self.block_size)?;
+        writeln!(f, "kind: {}", self.kind)?;
+        writeln!(f, "iv: {}", self.iv)?;
+        write!(f, "mode: {}", self.mode)
+    }
+}
+
+fn kind_from_block_size(block_size: &Verdict<Option<usize>>) -> Verdict<CipherKind> {
+    let kind = match block_size.value {
+        Some(_) => CipherKind::Block,
+        None => CipherKind::Stream,
+    };
+    Verdict {
+        value: kind,
+        confidence: block_size.confidence,
+        evidence: block_size.evidence.clone(),
+    }
+}
+
+/// Fingerprints a target that will encrypt chosen input, possibly wrapped in
+/// its own prefix and suffix
+pub fn fingerprint_oracle(oracle: &impl EncryptionOracle) -> Result<Fingerprint> {
+    let block_size = oracle_block_size(oracle)?;
+    let kind = kind_from_block_size(&block_size);
+
+    let probe = vec![b'A'; 4 * MAX_BLOCK_SIZE];
+    let iv = if oracle.encrypt(&probe)? == oracle.encrypt(&probe)? {
+        Verdict::new(
+            IvBehaviour::Fixed,
+            1.0,
+            "encrypting the same input twice gave the same ciphertext",
+        )
+    } else {
+        Verdict::new(
+            IvBehaviour::Random,
+            1.0,
+            "encrypting the same input twice gave different ciphertexts",
+        )
+    };
+
+    let mode = match block_size.value {
+        Some(size) => block_mode(oracle, size, iv.value)?,
+        None => stream_mode(oracle, iv.value)?,
+    };
+    Ok(Fingerprint {
+        block_size,
+        kind,
+        iv,
+        mode,
+    })
+}
+
+fn oracle_block_size(oracle: &impl EncryptionOracle) -> Result<Verdict<Option<usize>>> {
+    let lengths = (0..=2 * MAX_BLOCK_SIZE)
+        .map(|len| Ok(oracle.encrypt(&vec![b'A'; len])?.len()))
+        .collect::<Result<Vec<usize>>>()?;
+    let steps: Vec<usize> = lengths
+        .windows(2)
+        .map(|w| w[1].saturating_sub(w[0]))
+        .filter(|&step| step > 0)
+        .collect();
+
+    Ok(if steps.iter().all(|&step| step == 1) {
+        Verdict::new(
+            None,
+            1.0,
+            format!(
+                "ciphertext grew by one byte per input byte over {} inputs",
+                lengths.len()
+            ),
+        )
+    } else if steps.iter().all(|&step| step == steps[0]) {
+        let size = steps[0];
+        let confidence = if steps.len() > 1 { 1.0 } else { 0.8 };
+        Verdict::new(
+            Some(size),
+            confidence,
+            format!("ciphertext grew in {} steps of {size} bytes", steps.len()),
+        )
+    } else {
+        Verdict::new(
+            steps.iter().copied().min(),
+            0.3,
+            format!("ciphertext grew in uneven steps {steps:?}"),
+        )
+    })
+}
+
+// Input bytes that differ only at `position`, long enough to fill four
+// aligned blocks whatever the prefix
+fn probe_pair(position: usize, value: u8) -> (Vec<u8>, Vec<u8>) {
+    probe_pair_of_len(4 * MAX_BLOCK_SIZE, position, value)
+}
+
+fn probe_pair_of_len(len: usize, position: usize, value: u8) -> (Vec<u8>, Vec<u8>) {
+    let a = vec![b'A'; len];
+    let mut b = a.clone();
+    b[position] = value;
+    (a, b)
+}
+
+// The ciphertext positions that change when input byte `position` of `len`
+// does, over a few different changes so a byte that stays the same by chance
+// still shows, along with the ciphertext length
+fn changed_positions(
+    oracle: &impl EncryptionOracle,
+    len: usize,
+    position: usize,
+) -> Result<(Vec<usize>, usize)> {
+    let mut changed = BTreeSet::new();
+    let mut ciphertext_len = 0;
+    for value in [b'B', b'C', b'D'] {
+        let (a, b) = probe_pair_of_len(len, position, value);
+        let (a, b) = (oracle.encrypt(&a)?, oracle.encrypt(&b)?);
+        changed.extend((0..a.len().min(b.len())).filter(|&i| a[i] != b[i]));
+        ciphertext_len = a.len();
+    }
+    Ok((changed.into_iter().collect(), ciphertext_len))
+}
+
+// The width of the run of changed bytes that ends the ciphertext, if there is
+// one and something unchanged separates it from the earlier changes
+fn changed_tail(changed: &[usize], len: usize) -> Option<usize> {
+    if changed.last() != Some(&(len - 1)) {
+        return None;
+    }
+    let run = changed
+        .iter()
+        .rev()
+        .zip((0..len).rev())
+        .take_while(|(&i, j)| i == *j)
+        .count();
+    (run < changed.len() && changed[changed.len() - run - 1] + 1 < len - run).then_some(run)
+}
+
+// An authentication tag changes whatever is edited, and stays the same width
+// however long the message. CFB also garbles everything to the end, but from
+// just after the change, so its tail grows with the input.
+fn tag_width(oracle: &impl EncryptionOracle) -> Result<Option<usize>> {
+    let len = 4 * MAX_BLOCK_SIZE;
+    let (changed, ciphertext_len) = changed_positions(oracle, len, 0)?;
+    let Some(width) = changed_tail(&changed, ciphertext_len) else {
+        return Ok(None);
+    };
+    let (changed, ciphertext_len) = changed_positions(oracle, len + MAX_BLOCK_SIZE + 1, 0)?;
+    Ok((changed_tail(&changed, ciphertext_len) == Some(width)).then_some(width))
+}
+
+fn block_mode(
+    oracle: &impl EncryptionOracle,
+    block_size: usize,
+    iv: IvBehaviour,
+) -> Result<Verdict<Mode>> {
+    let encrypted = oracle.encrypt(&[b'A'; 4 * MAX_BLOCK_SIZE])?;
+    if detect_ecb(&encrypted, block_size) {
+        return Ok(Verdict::new(
+            Mode::ECB,
+            1.0,
+            "identical input blocks encrypted to identical ciphertext blocks",
+        ));
+    }
+    let mut verdict = Verdict::new(
+        Mode::CBC,
+        0.7,
+        "padded to whole blocks, but identical input blocks did not repeat",
+    );
+    if iv == IvBehaviour::Fixed {
+        let (a, b) = probe_pair(0, b'B');
+        let (a, b) = (oracle.encrypt(&a)?, oracle.encrypt(&b)?);
+        let changed: Vec<bool> = a
+            .chunks(block_size)
+            .zip(b.chunks(block_size))
+            .map(|(x, y)| x != y)
+            .skip_while(|&changed| !changed)
+            .collect();
+        if !changed.is_empty() && changed.iter().all(|&changed| changed) {
+            verdict.confidence = 0.9;
+            verdict
+                .evidence
+                .push("a one byte change garbled every block after it (CBC or PCBC)".into());
+        }
+    }
+    Ok(verdict)
+}
+
+fn stream_mode(oracle: &impl EncryptionOracle, iv: IvBehaviour) -> Result<Verdict<Mode>> {
+    if iv == IvBehaviour::Random {
+        return Ok(Verdict::new(
+            Mode::CTR,
+            0.5,
+            "unpadded with a random IV: CTR, OFB, CFB or GCM, which can't be told apart without a repeated IV",
+        ));
+    }
+    if let Some(width) = tag_width(oracle)? {
+        return Ok(Verdict::new(
+            Mode::GCM,
+            0.9,
+            format!(
+                "a one byte change also garbled the last {width} bytes, whatever the \
+                 input length: an authentication tag (GCM)"
+            ),
+        ));
+    }
+    // flip input byte 0, then byte 1: at most one of them ends a CFB block
+    let mut spreads_to_next_byte = true;
+    for position in [0, 1] {
+        let (changed, _) = changed_positions(oracle, 4 * MAX_BLOCK_SIZE, position)?;
+        match changed.as_slice() {
+            [] => {
+                return Ok(Verdict::new(
+                    Mode::CTR,
+                    0.3,
+                    "changing the input did not change the ciphertext",
+                ))
+            }
+            [_] => {
+                return Ok(Verdict::new(
+                    Mode::CTR,
+                    0.95,
+                    "a one byte change stayed in one byte, so the keystream doesn't depend on the data (CTR or OFB)",
+                ))
+            }
+            [first, second, ..] => spreads_to_next_byte &= *second == first + 1,
+        }
+    }
+    Ok(if spreads_to_next_byte {
+        Verdict::new(
+            Mode::CFB8,
+            0.9,
+            "a one byte change garbled the very next byte and everything after",
+        )
+    } else {
+        Verdict::new(
+            Mode::CFB,
+            0.9,
+            "a one byte change skipped the rest of its block, then garbled everything after",
+        )
+    })
+}
+
+/// Fingerprints from a collection of ciphertexts alone
+pub fn fingerprint_ciphertexts<T: AsRef<[u8]>>(ciphertexts: &[T]) -> Fingerprint {
+    let ciphertexts: Vec<&[u8]> = ciphertexts.iter().map(|c| c.as_ref()).collect();
+    let n = ciphertexts.len() as i32;
+
+    let block_size = match BLOCK_SIZES
+        .iter()
+        .find(|&&size| ciphertexts.iter().all(|c| c.len().is_multiple_of(size)))
+    {
+        Some(&size) if n > 0 => Verdict::new(
+            Some(size),
+            1.0 - (size as f64).powi(-n),
+            format!("all {n} lengths are multiples of {size}"),
+        ),
+        _ => Verdict::new(
+            None,
+            if n > 0 { 0.9 } else { 0.0 },
+            "lengths are not all multiples of a block size",
+        ),
+    };
+    let kind = kind_from_block_size(&block_size);
+    let prefix_len = block_size.value.unwrap_or(16);
+
+    let shared_prefix = ciphertexts.iter().enumerate().any(|(i, a)| {
+        ciphertexts[i + 1..].iter().any(|b| {
+            a.len() >= prefix_len && b.len() >= prefix_len && a[..prefix_len] == b[..prefix_len]
+        })
+    });
+    let iv = if shared_prefix {
+        Verdict::new(
+            IvBehaviour::Fixed,
+            0.9,
+            format!("two ciphertexts start with the same {prefix_len} bytes"),
+        )
+    } else {
+        Verdict::new(
+            IvBehaviour::Random,
+            if n > 1 { 0.5 } else { 0.0 },
+            format!("no two of the {n} ciphertexts share a first block"),
+        )
+    };
+
+    let mode = match block_size.value {
+        Some(size) if ciphertexts.iter().any(|c| detect_ecb(c, size)) => {
+            Verdict::new(Mode::ECB, 0.99, "a ciphertext contains a repeated block")
+        }
+        Some(_) => Verdict::new(
+            Mode::CBC,
+            0.6,
+            "block-aligned with no repeated blocks (ECB of non-repeating data looks the same)",
+        ),
+        None => Verdict::new(Mode::CTR, 0.6, "unpadded, so a stream mode"),
+    };
+    Fingerprint {
+        block_size,
+        kind,
+        iv,
+        mode,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{AesKey, Mode},
+        cipher::{BlockCipher, ToyCipher},
+        fingerprint::{fingerprint_ciphertexts, fingerprint_oracle, CipherKind, IvBehaviour},
+        gcm, modes,
+        oracle::{self, CbcOracle, EcbOracle, EncryptionOracle, PrefixEcbOracle, Target},
+        utils::{bytes, random::test_rng},
+    };
+
+    // wraps the input in a prefix and suffix and encrypts it in `mode`, with a
+    // fresh IV prepended each time if `random_iv`
+    struct ModeOracle<C> {
+        cipher: C,
+        mode: Mode,
+        random_iv: bool,
+        iv: Vec<u8>,
+    }
+
+    impl<C: BlockCipher> ModeOracle<C> {
+        fn new(cipher: C, mode: Mode, random_iv: bool) -> Self {
+            let iv = bytes::rand_of_len(cipher.block_size());
+            ModeOracle {
+                cipher,
+                mode,
+                random_iv,
+                iv,
+            }
+        }
+    }
+
+    impl<C> Target for ModeOracle<C> {}
+
+    impl<C: BlockCipher> EncryptionOracle for ModeOracle<C> {
+        fn encrypt(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+            let mut plaintext = b"user=".to_vec();
+            plaintext.extend(input);
+            plaintext.extend(b";role=guest");
+            let iv = match self.random_iv {
+                true => bytes::rand_of_len(self.cipher.block_size()),
+                false => self.iv.clone(),
+            };
+            let nonce = u32::from_le_bytes(iv[..4].try_into()?) as u64;
+            let encrypted = match self.mode {
+                Mode::ECB => modes::encrypt_ecb(&self.cipher, &plaintext)?,
+                Mode::CBC => modes::encrypt_cbc(&self.cipher, &plaintext, &iv)?,
+                Mode::PCBC => modes::encrypt_pcbc(&self.cipher, &plaintext, &iv)?,
+                Mode::CTR => modes::ctr(&self.cipher, &plaintext, nonce)?,
+                Mode::OFB => modes::ofb(&self.cipher, &plaintext, &iv)?,
+                Mode::CFB => modes::encrypt_cfb(&self.cipher, &plaintext, &iv)?,
+                Mode::CFB8 => modes::encrypt_cfb8(&self.cipher, &plaintext, &iv)?,
+                Mode::GCM => {
+                    let (ciphertext, tag) = gcm::encrypt(&self.cipher, &iv[..12], &plaintext, b"")?;
+                    [ciphertext, tag].concat()
+                }
+            };
+            Ok(match self.random_iv && self.mode != Mode::ECB {
+                true => [iv, encrypted].concat(),
+                false => encrypted,
+            })
+        }
+    }
+
+    fn check_modes(cipher: impl BlockCipher + Clone) {
+        let block_size = cipher.block_size();
+        // (mode, expected verdict, padded)
+        let mut cases = vec![
+            (Mode::ECB, Mode::ECB, true),
+            (Mode::CBC, Mode::CBC, true),
+            (Mode::PCBC, Mode::CBC, true),
+            (Mode::CTR, Mode::CTR, false),
+            (Mode::OFB, Mode::CTR, false),
+            (Mode::CFB, Mode::CFB, false),
+            (Mode::CFB8, Mode::CFB8, false),
+        ];
+        // GCM is only defined for 16-byte blocks
+        if block_size == 16 {
+            cases.push((Mode::GCM, Mode::GCM, false));
+        }
+        for (mode, expected, padded) in cases {
+            let fingerprint =
+                fingerprint_oracle(&ModeOracle::new(cipher.clone(), mode, false)).unwrap();
+            assert_eq!(fingerprint.mode.value, expected, "{mode:?}\n{fingerprint}");
+            assert!(
+                fingerprint.mode.confidence >= 0.9,
+                "{mode:?}\n{fingerprint}"
+            );
+            assert_eq!(fingerprint.iv.value, IvBehaviour::Fixed);
+            let (size, kind) = match padded {
+                true => (Some(block_size), CipherKind::Block),
+                false => (None, CipherKind::Stream),
+            };
+            assert_eq!(fingerprint.block_size.value, size);
+            assert_eq!(fingerprint.kind.value, kind);
+
+            let fingerprint =
+                fingerprint_oracle(&ModeOracle::new(cipher.clone(), mode, true)).unwrap();
+            assert_eq!(fingerprint.block_size.value, size);
+            if mode != Mode::ECB {
+                assert_eq!(fingerprint.iv.value, IvBehaviour::Random);
+                if padded {
+                    assert_eq!(fingerprint.mode.value, Mode::CBC);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_fingerprint_modes() {
+        check_modes(AesKey::new(&bytes::rand_of_len(16)).unwrap());
+        check_modes(ToyCipher::new(&bytes::rand_of_len(8)).unwrap());
+    }
+
+    #[test]
+    fn test_fingerprint_existing_oracles() {
+        let secret = b"some secret".to_vec();
+        let fingerprint = fingerprint_oracle(&EcbOracle::new(secret.clone())).unwrap();
+        assert_eq!(fingerprint.block_size.value, Some(16));
+        assert_eq!(fingerprint.mode.value, Mode::ECB);
+
+        let toy = ToyCipher::new(&bytes::rand_of_len(8)).unwrap();
+        let fingerprint = fingerprint_oracle(&PrefixEcbOracle::with_cipher(toy, secret)).unwrap();
+        assert_eq!(fingerprint.block_size.value, Some(8));
+        assert_eq!(fingerprint.mode.value, Mode::ECB);
+
+        let fingerprint = fingerprint_oracle(&CbcOracle::new()).unwrap();
+        assert_eq!(fingerprint.mode.value, Mode::CBC);
+        assert_eq!(fingerprint.iv.value, IvBehaviour::Fixed);
+        assert!(!fingerprint.mode.evidence.is_empty());
+    }
+
+    #[test]
+    fn test_fingerprint_ciphertexts() {
+        let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+        let iv = bytes::rand_of_len(16);
+        let plaintexts: Vec<Vec<u8>> = (0..5).map(|i| bytes::rand_of_len(10 + 7 * i)).collect();
+
+        let encrypted: Vec<Vec<u8>> = plaintexts
+            .iter()
+            .map(|p| modes::encrypt_cbc(&cipher, p, &bytes::rand_of_len(16)).unwrap())
+            .collect();
+        let fingerprint = fingerprint_ciphertexts(&encrypted);
+        assert_eq!(fingerprint.block_size.value, Some(16));
+        assert!(fingerprint.block_size.confidence > 0.99);
+        assert_eq!(fingerprint.mode.value, Mode::CBC);
+        assert_eq!(fingerprint.iv.value, IvBehaviour::Random);
+
+        // a fixed IV shows up as a shared first block when messages share a prefix
+        let encrypted: Vec<Vec<u8>> = plaintexts
+            .iter()
+            .map(|p| modes::encrypt_cbc(&cipher, &[&[0; 16], &p[..]].concat(), &iv).unwrap())
+            .collect();
+        assert_eq!(
+            fingerprint_ciphertexts(&encrypted).iv.value,
+            IvBehaviour::Fixed
+        );
+
+        let encrypted: Vec<Vec<u8>> = plaintexts
+            .iter()
+            .map(|p| modes::ctr(&cipher, p, 0).unwrap())
+            .collect();
+        let fingerprint = fingerprint_ciphertexts(&encrypted);
+        assert_eq!(fingerprint.kind.value, CipherKind::Stream);
+        assert_eq!(fingerprint.mode.value, Mode::CTR);
+
+        let encrypted = modes::encrypt_ecb(&cipher, &[b'A'; 48]).unwrap();
+        assert_eq!(fingerprint_ciphertexts(&[encrypted]).mode.value, Mode::ECB);
+        // never panics, whatever the lengths
+        fingerprint_ciphertexts(&[vec![1; 15]]);
+        fingerprint_ciphertexts::<Vec<u8>>(&[]);
+    }
+
+    #[test]
+    fn test_guess() {
+        let mut rng = test_rng();
+        for _ in 0..20 {
+            let oracle = oracle::encrypt_with_rng(&[100; 48], &mut rng).unwrap();
+            assert!(oracle.verify(&oracle::guess(&oracle)));
+        }
+    }
+}
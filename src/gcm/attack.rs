@@ -0,0 +1,206 @@
+// The "forbidden attack" on GCM nonce reuse.
+// Reference: Joux, "Authentication Failures in NIST version of GCM", and
+// https://toadstyle.org/cryptopals/63.txt
+//
+// Each tag is T = GHASH_H(A, C) + E(J0), and GHASH is a polynomial in H whose
+// coefficients are the message blocks. Two messages under the same nonce share
+// E(J0), so adding their tag polynomials cancels it and leaves a polynomial with
+// H as a root. Factoring it over GF(2^128) gives a short list of candidates.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    gcm::{
+        gf128::{Gf128, Poly},
+        ghash, ghash_blocks, TAG_LEN,
+    },
+    utils::random::RandomSource,
+};
+
+#[derive(Debug, Clone)]
+pub struct GcmMessage {
+    pub aad: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub tag: Vec<u8>,
+}
+
+/// The polynomial B1*X^n + ... + Bn*X + T, which evaluates to E(J0) at X = H
+pub fn tag_poly(message: &GcmMessage) -> Result<Poly> {
+    ensure!(
+        message.tag.len() == TAG_LEN,
+        "expected a full {TAG_LEN} byte tag, got {}",
+        message.tag.len()
+    );
+    let mut coefficients = vec![Gf128::from_block(&message.tag)?];
+    coefficients.extend(
+        ghash_blocks(&message.aad, &message.ciphertext)
+            .into_iter()
+            .rev(),
+    );
+    Ok(Poly::new(coefficients))
+}
+
+/// The distinct roots of `f` in GF(2^128)
+pub fn roots(f: &Poly) -> Result<Vec<Gf128>> {
+    roots_with_rng(f, &mut rand::thread_rng())
+}
+
+/// `roots`, drawing the splitting elements from `rng`. The roots come out in
+/// an order that depends on them.
+pub fn roots_with_rng(f: &Poly, rng: &mut impl RandomSource) -> Result<Vec<Gf128>> {
+    ensure!(
+        !f.is_zero(),
+        "the zero polynomial has every element as a root"
+    );
+    let f = f.monic();
+    // Every element of GF(2^128) is a root of X^(2^128) - X, so the gcd with it
+    // is the product of f's distinct linear factors.
+    let x = Poly::x();
+    let frobenius = x.pow2k_mod(128, &f)?;
+    let linear_factors = f.gcd(&frobenius.add(&x))?;
+
+    let mut roots = vec![];
+    split_linear_factors(linear_factors, &mut roots, rng)?;
+    Ok(roots)
+}
+
+// Equal-degree splitting: for random a, Tr(a * r) is 0 for about half of the
+// roots r, so gcd(g, Tr(a * X) mod g) usually splits g.
+fn split_linear_factors(
+    g: Poly,
+    roots: &mut Vec<Gf128>,
+    rng: &mut impl RandomSource,
+) -> Result<()> {
+    match g.degree() {
+        None | Some(0) => Ok(()),
+        Some(1) => {
+            // monic X + c has the root c
+            roots.push(g.coefficients()[0]);
+            Ok(())
+        }
+        Some(degree) => loop {
+            let a = Poly::new(vec![Gf128::ZERO, random_element(rng)]);
+            let mut term = a.rem(&g)?;
+            let mut trace = term.clone();
+            for _ in 1..128 {
+                term = term.mul(&term).rem(&g)?;
+                trace = trace.add(&term);
+            }
+            let factor = g.gcd(&trace)?;
+            if let Some(factor_degree) = factor.degree() {
+                if factor_degree > 0 && factor_degree < degree {
+                    let (other, _) = g.divmod(&factor)?;
+                    split_linear_factors(factor, roots, rng)?;
+                    return split_linear_factors(other, roots, rng);
+                }
+            }
+        },
+    }
+}
+
+fn random_element(rng: &mut impl RandomSource) -> Gf128 {
+    Gf128((rng.rand_u64() as u128) << 64 | rng.rand_u64() as u128)
+}
+
+/// Candidate authentication keys H given messages that were all encrypted
+/// under the same key and nonce. More messages narrow the candidates down.
+pub fn recover_hash_key_candidates(messages: &[GcmMessage]) -> Result<Vec<Gf128>> {
+    recover_hash_key_candidates_with_rng(messages, &mut rand::thread_rng())
+}
+
+pub fn recover_hash_key_candidates_with_rng(
+    messages: &[GcmMessage],
+    rng: &mut impl RandomSource,
+) -> Result<Vec<Gf128>> {
+    if messages.len() < 2 {
+        bail!("need at least two messages sharing a nonce");
+    }
+    let first = tag_poly(&messages[0])?;
+    let mut candidates = roots_with_rng(&first.add(&tag_poly(&messages[1])?), rng)?;
+    for message in &messages[2..] {
+        let f = first.add(&tag_poly(message)?);
+        candidates.retain(|&h| f.eval(h).is_zero());
+    }
+    Ok(candidates)
+}
+
+/// Computes a valid tag for `aad` and `ciphertext` under the nonce that `known`
+/// was encrypted with, given the authentication key.
+pub fn forge_tag(h: Gf128, known: &GcmMessage, aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+    let mask = tag_poly(known)?.eval(h);
+    Ok((ghash(h, aad, ciphertext) + mask).to_block().to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesKey,
+        gcm::{
+            attack::{
+                forge_tag, random_element, recover_hash_key_candidates_with_rng, roots_with_rng,
+                GcmMessage,
+            },
+            decrypt, encrypt,
+            gf128::{Gf128, Poly},
+            hash_key,
+        },
+        utils::{bytes, random::test_rng},
+    };
+
+    #[test]
+    fn test_roots() {
+        let mut rng = test_rng();
+        let expected: Vec<Gf128> = (0..4).map(|_| random_element(&mut rng)).collect();
+        let f = expected
+            .iter()
+            .fold(Poly::constant(random_element(&mut rng)), |f, &r| {
+                f.mul(&Poly::new(vec![r, Gf128::ONE]))
+            });
+        let mut found = roots_with_rng(&f, &mut rng).unwrap();
+        found.sort_by_key(|r| r.0);
+        let mut expected = expected;
+        expected.sort_by_key(|r| r.0);
+        assert_eq!(found, expected);
+
+        // a repeated root is reported once
+        let r = random_element(&mut rng);
+        let square = Poly::new(vec![r, Gf128::ONE]).mul(&Poly::new(vec![r, Gf128::ONE]));
+        assert_eq!(roots_with_rng(&square, &mut rng).unwrap(), vec![r]);
+    }
+
+    #[test]
+    fn test_forbidden_attack() {
+        let mut rng = test_rng();
+        let cipher = AesKey::new(&bytes::rand_of_len_from(&mut rng, 16)).unwrap();
+        let nonce = bytes::rand_of_len_from(&mut rng, 12);
+        let messages: Vec<GcmMessage> = [
+            (&b"message one"[..], &b"first secret plaintext"[..]),
+            (b"message two", b"a second, longer plaintext message"),
+            (b"message three", b"third"),
+        ]
+        .iter()
+        .map(|&(aad, plaintext)| {
+            let (ciphertext, tag) = encrypt(&cipher, &nonce, plaintext, aad).unwrap();
+            GcmMessage {
+                aad: aad.to_vec(),
+                ciphertext,
+                tag,
+            }
+        })
+        .collect();
+
+        let candidates = recover_hash_key_candidates_with_rng(&messages[..2], &mut rng).unwrap();
+        let h = hash_key(&cipher).unwrap();
+        assert!(candidates.contains(&h));
+        assert_eq!(
+            recover_hash_key_candidates_with_rng(&messages, &mut rng).unwrap(),
+            vec![h]
+        );
+
+        // flip a bit of the plaintext and forge a tag for it
+        let mut forged = messages[0].ciphertext.clone();
+        forged[0] ^= 1;
+        let tag = forge_tag(h, &messages[0], b"forged aad", &forged).unwrap();
+        let decrypted = decrypt(&cipher, &nonce, &forged, b"forged aad", &tag).unwrap();
+        assert_eq!(decrypted, b"girst secret plaintext");
+    }
+}
@@ -0,0 +1,249 @@
+// Arithmetic in GF(2^128) as defined for GHASH, and polynomials over it.
+// Reference: NIST SP 800-38D section 6.3
+//
+// GCM uses a reflected bit order: the most significant bit of the first byte
+// of a block is the coefficient of x^0. Reading the block as a big-endian u128
+// keeps that bit at the top, so "multiply by x" is a right shift.
+use std::ops::{Add, Mul};
+
+use anyhow::{bail, ensure, Result};
+
+// x^128 + x^7 + x^2 + x + 1, reflected
+const R: u128 = 0xe1 << 120;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Gf128(pub u128);
+
+impl Gf128 {
+    pub const ZERO: Gf128 = Gf128(0);
+    pub const ONE: Gf128 = Gf128(1 << 127);
+
+    pub fn from_block(block: &[u8]) -> Result<Self> {
+        ensure!(block.len() == 16, "expected 16 bytes, got {}", block.len());
+        let mut bytes = [0; 16];
+        bytes.copy_from_slice(block);
+        Ok(Gf128(u128::from_be_bytes(bytes)))
+    }
+
+    /// Like `from_block`, but zero-pads a final partial block
+    pub fn from_partial_block(block: &[u8]) -> Self {
+        let mut bytes = [0; 16];
+        bytes[..block.len()].copy_from_slice(block);
+        Gf128(u128::from_be_bytes(bytes))
+    }
+
+    pub fn to_block(self) -> [u8; 16] {
+        self.0.to_be_bytes()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn pow(self, exp: u128) -> Self {
+        let mut result = Gf128::ONE;
+        let mut base = self;
+        let mut exp = exp;
+        while exp != 0 {
+            if exp & 1 != 0 {
+                result = result * base;
+            }
+            base = base * base;
+            exp >>= 1;
+        }
+        result
+    }
+
+    /// a^(2^128 - 2) == a^-1
+    pub fn inverse(self) -> Self {
+        self.pow(u128::MAX - 1)
+    }
+}
+
+impl Add for Gf128 {
+    type Output = Gf128;
+
+    // addition in characteristic 2 is xor
+    #[allow(clippy::suspicious_arithmetic_impl)]
+    fn add(self, rhs: Gf128) -> Gf128 {
+        Gf128(self.0 ^ rhs.0)
+    }
+}
+
+impl Mul for Gf128 {
+    type Output = Gf128;
+
+    fn mul(self, rhs: Gf128) -> Gf128 {
+        let mut product = 0;
+        let mut v = rhs.0;
+        for i in 0..128 {
+            if self.0 & (1 << (127 - i)) != 0 {
+                product ^= v;
+            }
+            v = if v & 1 != 0 { (v >> 1) ^ R } else { v >> 1 };
+        }
+        Gf128(product)
+    }
+}
+
+/// A polynomial over GF(2^128), coefficients stored lowest degree first
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Poly(Vec<Gf128>);
+
+impl Poly {
+    pub fn new(coefficients: Vec<Gf128>) -> Self {
+        let mut poly = Poly(coefficients);
+        poly.trim();
+        poly
+    }
+
+    pub fn x() -> Self {
+        Poly(vec![Gf128::ZERO, Gf128::ONE])
+    }
+
+    pub fn constant(c: Gf128) -> Self {
+        Poly::new(vec![c])
+    }
+
+    pub fn coefficients(&self) -> &[Gf128] {
+        &self.0
+    }
+
+    fn trim(&mut self) {
+        while self.0.last().is_some_and(|c| c.is_zero()) {
+            self.0.pop();
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Degree of the polynomial, None for the zero polynomial
+    pub fn degree(&self) -> Option<usize> {
+        self.0.len().checked_sub(1)
+    }
+
+    pub fn eval(&self, x: Gf128) -> Gf128 {
+        self.0.iter().rev().fold(Gf128::ZERO, |acc, &c| acc * x + c)
+    }
+
+    pub fn add(&self, other: &Poly) -> Poly {
+        let len = self.0.len().max(other.0.len());
+        let coefficient = |p: &Poly, i: usize| p.0.get(i).copied().unwrap_or_default();
+        Poly::new(
+            (0..len)
+                .map(|i| coefficient(self, i) + coefficient(other, i))
+                .collect(),
+        )
+    }
+
+    pub fn mul(&self, other: &Poly) -> Poly {
+        if self.is_zero() || other.is_zero() {
+            return Poly::default();
+        }
+        let mut out = vec![Gf128::ZERO; self.0.len() + other.0.len() - 1];
+        for (i, &a) in self.0.iter().enumerate() {
+            for (j, &b) in other.0.iter().enumerate() {
+                out[i + j] = out[i + j] + a * b;
+            }
+        }
+        Poly::new(out)
+    }
+
+    pub fn monic(&self) -> Poly {
+        match self.0.last() {
+            Some(&lead) => {
+                let inv = lead.inverse();
+                Poly::new(self.0.iter().map(|&c| c * inv).collect())
+            }
+            None => Poly::default(),
+        }
+    }
+
+    /// Returns (quotient, remainder)
+    pub fn divmod(&self, divisor: &Poly) -> Result<(Poly, Poly)> {
+        let Some(divisor_degree) = divisor.degree() else {
+            bail!("division by the zero polynomial");
+        };
+        let lead_inv = divisor.0[divisor_degree].inverse();
+        let mut remainder = self.0.clone();
+        let mut quotient = vec![Gf128::ZERO; self.0.len().saturating_sub(divisor_degree)];
+        while remainder.len() > divisor_degree {
+            let shift = remainder.len() - 1 - divisor_degree;
+            let factor = *remainder.last().unwrap() * lead_inv;
+            quotient[shift] = factor;
+            for (i, &c) in divisor.0.iter().enumerate() {
+                remainder[shift + i] = remainder[shift + i] + factor * c;
+            }
+            remainder.pop();
+            while remainder.last().is_some_and(|c| c.is_zero()) {
+                remainder.pop();
+            }
+        }
+        Ok((Poly::new(quotient), Poly::new(remainder)))
+    }
+
+    pub fn rem(&self, modulus: &Poly) -> Result<Poly> {
+        Ok(self.divmod(modulus)?.1)
+    }
+
+    pub fn gcd(&self, other: &Poly) -> Result<Poly> {
+        let mut a = self.clone();
+        let mut b = other.clone();
+        while !b.is_zero() {
+            let r = a.rem(&b)?;
+            a = b;
+            b = r;
+        }
+        Ok(a.monic())
+    }
+
+    /// self^(2^n) mod modulus, by squaring n times
+    pub fn pow2k_mod(&self, n: usize, modulus: &Poly) -> Result<Poly> {
+        let mut out = self.rem(modulus)?;
+        for _ in 0..n {
+            out = out.mul(&out).rem(modulus)?;
+        }
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::gcm::gf128::{Gf128, Poly};
+
+    #[test]
+    fn test_gf128_field() {
+        let a = Gf128(rand::random());
+        let b = Gf128(rand::random());
+        let c = Gf128(rand::random());
+        assert_eq!(a * Gf128::ONE, a);
+        assert_eq!(a * b, b * a);
+        assert_eq!(a * (b + c), a * b + a * c);
+        assert_eq!(a * a.inverse(), Gf128::ONE);
+        // x * x^127 wraps around to x^7 + x^2 + x + 1
+        let x = Gf128(1 << 126);
+        assert_eq!(x * Gf128(1), Gf128(0xe1 << 120));
+    }
+
+    #[test]
+    fn test_poly_divmod_gcd() {
+        let r1 = Gf128(rand::random());
+        let r2 = Gf128(rand::random());
+        let r3 = Gf128(rand::random());
+        let linear = |r: Gf128| Poly::new(vec![r, Gf128::ONE]);
+        let f = linear(r1).mul(&linear(r2));
+        let g = linear(r1).mul(&linear(r3));
+
+        assert!(f.eval(r1).is_zero());
+        assert!(f.eval(r2).is_zero());
+        assert_eq!(f.degree(), Some(2));
+
+        let (q, r) = f.divmod(&linear(r2)).unwrap();
+        assert!(r.is_zero());
+        assert_eq!(q, linear(r1));
+
+        assert_eq!(f.gcd(&g).unwrap(), linear(r1));
+    }
+}
@@ -0,0 +1,247 @@
+// AES-GCM authenticated encryption
+// Reference: NIST SP 800-38D
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::{AesError, AesKey},
+    cipher::BlockCipher,
+    ctr::{CounterLayout, Ctr},
+    xor::Xor,
+};
+
+pub mod attack;
+pub mod gf128;
+
+use gf128::Gf128;
+
+pub const TAG_LEN: usize = 16;
+
+fn ensure_block_size(cipher: &impl BlockCipher) -> Result<()> {
+    ensure!(
+        cipher.block_size() == 16,
+        "GCM requires a 16 byte block cipher, got {}",
+        cipher.block_size()
+    );
+    Ok(())
+}
+
+/// The GHASH authentication key, E(0^128)
+pub fn hash_key(cipher: &impl BlockCipher) -> Result<Gf128> {
+    Gf128::from_block(&cipher.encrypt_block(&[0; 16])?)
+}
+
+/// The blocks GHASH absorbs: the zero-padded AAD, the zero-padded ciphertext,
+/// then the bit lengths of each as two 64-bit big-endian integers.
+pub fn ghash_blocks(aad: &[u8], ciphertext: &[u8]) -> Vec<Gf128> {
+    let mut blocks: Vec<Gf128> = aad.chunks(16).map(Gf128::from_partial_block).collect();
+    blocks.extend(ciphertext.chunks(16).map(Gf128::from_partial_block));
+    let lengths = ((aad.len() as u128 * 8) << 64) | (ciphertext.len() as u128 * 8);
+    blocks.push(Gf128(lengths));
+    blocks
+}
+
+pub fn ghash(h: Gf128, aad: &[u8], ciphertext: &[u8]) -> Gf128 {
+    ghash_blocks(aad, ciphertext)
+        .into_iter()
+        .fold(Gf128::ZERO, |acc, block| (acc + block) * h)
+}
+
+// The pre-counter block J0: for a 96-bit IV it is IV || 0^31 || 1, otherwise
+// the IV is run through GHASH.
+fn pre_counter_block(h: Gf128, iv: &[u8]) -> Result<[u8; 16]> {
+    ensure!(!iv.is_empty(), "GCM IV must not be empty");
+    if iv.len() == 12 {
+        let mut j0 = [0; 16];
+        j0[..12].copy_from_slice(iv);
+        j0[15] = 1;
+        Ok(j0)
+    } else {
+        Ok(ghash(h, &[], iv).to_block())
+    }
+}
+
+// GCTR starting from inc32(J0): the first 96 bits of J0 are fixed and the last
+// 32 are a big-endian counter that wraps modulo 2^32.
+fn gctr(cipher: &impl BlockCipher, j0: &[u8; 16], bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut nonce = [0; 12];
+    nonce.copy_from_slice(&j0[..12]);
+    let counter = u32::from_be_bytes([j0[12], j0[13], j0[14], j0[15]]);
+    Ctr::new(cipher, CounterLayout::BigEndian96 { nonce })?
+        .with_initial_counter(counter as u64 + 1)
+        .apply(bytes)
+}
+
+fn tag(
+    cipher: &impl BlockCipher,
+    h: Gf128,
+    j0: &[u8; 16],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> Result<Vec<u8>> {
+    let s = ghash(h, aad, ciphertext).to_block();
+    Ok(cipher.encrypt_block(j0)?.xor(&s))
+}
+
+/// Returns (ciphertext, tag)
+pub fn encrypt(
+    cipher: &impl BlockCipher,
+    iv: &[u8],
+    plaintext: &[u8],
+    aad: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    ensure_block_size(cipher)?;
+    let h = hash_key(cipher)?;
+    let j0 = pre_counter_block(h, iv)?;
+    let ciphertext = gctr(cipher, &j0, plaintext)?;
+    let tag = tag(cipher, h, &j0, aad, &ciphertext)?;
+    Ok((ciphertext, tag))
+}
+
+/// Verifies the tag before decrypting anything. Tags may be truncated, but not
+/// below 4 bytes.
+pub fn decrypt(
+    cipher: &impl BlockCipher,
+    iv: &[u8],
+    ciphertext: &[u8],
+    aad: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>> {
+    ensure_block_size(cipher)?;
+    ensure!(
+        (4..=TAG_LEN).contains(&tag.len()),
+        AesError::InvalidTag(format!("bad tag length {}", tag.len()))
+    );
+    let h = hash_key(cipher)?;
+    let j0 = pre_counter_block(h, iv)?;
+    let expected = self::tag(cipher, h, &j0, aad, ciphertext)?;
+    if !openssl::memcmp::eq(&expected[..tag.len()], tag) {
+        bail!(AesError::InvalidTag("GCM tag mismatch".into()));
+    }
+    gctr(cipher, &j0, ciphertext)
+}
+
+pub fn encrypt_aes_gcm(
+    bytes: &[u8],
+    aad: &[u8],
+    iv: &[u8],
+    key: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    encrypt(&AesKey::new(key)?, iv, bytes, aad)
+}
+
+pub fn decrypt_aes_gcm(
+    bytes: &[u8],
+    aad: &[u8],
+    iv: &[u8],
+    key: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>> {
+    decrypt(&AesKey::new(key)?, iv, bytes, aad, tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesError,
+        gcm::{decrypt_aes_gcm, encrypt_aes_gcm},
+        hex::{DecodeHex, EncodeHex},
+        utils::bytes,
+    };
+
+    const GCM_KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const GCM_PLAINTEXT: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b391aafd255";
+    const GCM_AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+
+    // (key, iv, plaintext, aad, ciphertext, tag)
+    // From the test cases in McGrew & Viega, "The Galois/Counter Mode of Operation",
+    // as used in the NIST GCM validation suite
+    fn vectors() -> Vec<(
+        String,
+        &'static str,
+        String,
+        &'static str,
+        &'static str,
+        &'static str,
+    )> {
+        let key_192 = format!("{GCM_KEY}feffe9928665731c");
+        let key_256 = format!("{GCM_KEY}{GCM_KEY}");
+        let plaintext_60 = GCM_PLAINTEXT[..120].to_string();
+        vec![
+            ("00000000000000000000000000000000".into(), "000000000000000000000000", "".into(), "", "", "58e2fccefa7e3061367f1d57a4e7455a"),
+            ("00000000000000000000000000000000".into(), "000000000000000000000000", "00000000000000000000000000000000".into(), "", "0388dace60b6a392f328c2b971b2fe78", "ab6e47d42cec13bdf53a67b21257bddf"),
+            (GCM_KEY.into(), "cafebabefacedbaddecaf888", GCM_PLAINTEXT.into(), "", "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091473f5985", "4d5c2af327cd64a62cf35abd2ba6fab4"),
+            (GCM_KEY.into(), "cafebabefacedbaddecaf888", plaintext_60.clone(), GCM_AAD, "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84aa051ba30b396a0aac973d58e091", "5bc94fbc3221a5db94fae95ae7121a47"),
+            (GCM_KEY.into(), "cafebabefacedbad", plaintext_60.clone(), GCM_AAD, "61353b4c2806934a777ff51fa22a4755699b2a714fcdc6f83766e5f97b6c742373806900e49f24b22b097544d4896b424989b5e1ebac0f07c23f4598", "3612d2e79e3b0785561be14aaca2fccb"),
+            (GCM_KEY.into(), "9313225df88406e555909c5aff5269aa6a7a9538534f7da1e4c303d2a318a728c3c0c95156809539fcf0e2429a6b525416aedbf5a0de6a57a637b39b", plaintext_60.clone(), GCM_AAD, "8ce24998625615b603a033aca13fb894be9112a5c3a211a8ba262a3cca7e2ca701e4a9a4fba43c90ccdcb281d48c7c6fd62875d2aca417034c34aee5", "619cc5aefffe0bfa462af43c1699d050"),
+            (key_192, "cafebabefacedbaddecaf888", plaintext_60.clone(), GCM_AAD, "3980ca0b3c00e841eb06fac4872a2757859e1ceaa6efd984628593b40ca1e19c7d773d00c144c525ac619d18c84a3f4718e2448b2fe324d9ccda2710", "2519498e80f1478f37ba55bd6d27618c"),
+            (key_256, "cafebabefacedbaddecaf888", plaintext_60, GCM_AAD, "522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662", "76fc6ece0f4e1768cddf8853bb2d551b"),
+        ]
+    }
+
+    #[test]
+    fn test_gcm_known_answers() {
+        for (key, iv, plaintext, aad, ciphertext, tag) in vectors() {
+            let key = key.as_str().decode_hex();
+            let (iv, plaintext, aad) = (
+                iv.decode_hex(),
+                plaintext.as_str().decode_hex(),
+                aad.decode_hex(),
+            );
+            let (encrypted, computed_tag) = encrypt_aes_gcm(&plaintext, &aad, &iv, &key).unwrap();
+            assert_eq!(encrypted.to_hex(), ciphertext);
+            assert_eq!(computed_tag.to_hex(), tag);
+            let decrypted = decrypt_aes_gcm(&encrypted, &aad, &iv, &key, &computed_tag).unwrap();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_gcm_rejects_tampering() {
+        let key = bytes::rand_of_len(16);
+        let iv = bytes::rand_of_len(12);
+        let plaintext = bytes::rand_of_len(40);
+        let aad = b"header";
+        let (ciphertext, tag) = encrypt_aes_gcm(&plaintext, aad, &iv, &key).unwrap();
+
+        // truncated tags are accepted
+        assert!(decrypt_aes_gcm(&ciphertext, aad, &iv, &key, &tag[..12]).is_ok());
+
+        let mut tampered = ciphertext.clone();
+        tampered[3] ^= 1;
+        let err = decrypt_aes_gcm(&tampered, aad, &iv, &key, &tag).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AesError>(),
+            Some(AesError::InvalidTag(_))
+        ));
+        assert!(decrypt_aes_gcm(&ciphertext, b"Header", &iv, &key, &tag).is_err());
+        assert!(decrypt_aes_gcm(&ciphertext, aad, &iv, &key, &tag[..3]).is_err());
+
+        let mut bad_tag = tag.clone();
+        bad_tag[15] ^= 0x80;
+        assert!(decrypt_aes_gcm(&ciphertext, aad, &iv, &key, &bad_tag).is_err());
+    }
+
+    #[test]
+    fn test_gcm_matches_openssl() {
+        use openssl::symm::{encrypt_aead, Cipher};
+        for (iv_len, len) in [(12, 0), (12, 33), (8, 16), (20, 50)] {
+            let key = bytes::rand_of_len(16);
+            let iv = bytes::rand_of_len(iv_len);
+            let plaintext = bytes::rand_of_len(len);
+            let aad = bytes::rand_of_len(len / 2);
+            let mut expected_tag = vec![0; 16];
+            let expected = encrypt_aead(
+                Cipher::aes_128_gcm(),
+                &key,
+                Some(&iv),
+                &aad,
+                &plaintext,
+                &mut expected_tag,
+            )
+            .unwrap();
+            let (ciphertext, tag) = encrypt_aes_gcm(&plaintext, &aad, &iv, &key).unwrap();
+            assert_eq!(ciphertext, expected);
+            assert_eq!(tag, expected_tag);
+        }
+    }
+}
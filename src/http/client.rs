@@ -0,0 +1,280 @@
+// The oracle traits over HTTP, for targets served by `http::server` or
+// anything else speaking the same endpoints.
+//
+// One connection is kept open and reused. If the server has dropped it, the
+// query is retried once on a fresh connection.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::BufReader,
+    net::{SocketAddr, TcpStream},
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    hex::EncodeHex,
+    http::{decode_hex, Request, Response},
+    oracle::{
+        DecryptionOracle, EditOracle, EncryptionOracle, PaddingOracle, SignatureOracle, Target,
+        VerificationOracle,
+    },
+};
+
+pub struct HttpOracle {
+    addr: SocketAddr,
+    prefix: String,
+    block_size: Option<usize>,
+    connection: RefCell<Option<(BufReader<TcpStream>, TcpStream)>>,
+}
+
+impl HttpOracle {
+    /// A client for endpoints under `prefix`, without a block size hint. No
+    /// connection is made until the first query.
+    pub fn new(addr: SocketAddr, prefix: &str) -> Self {
+        HttpOracle {
+            addr,
+            prefix: prefix.into(),
+            block_size: None,
+            connection: RefCell::new(None),
+        }
+    }
+
+    /// Connects to the oracle mounted at `prefix`, asking it for its block size
+    pub fn connect(addr: SocketAddr, prefix: &str) -> Result<Self> {
+        let mut oracle = HttpOracle::new(addr, prefix);
+        let hint = oracle.get("block-size")?;
+        if !hint.is_empty() {
+            oracle.block_size = Some(String::from_utf8(hint)?.parse()?);
+        }
+        Ok(oracle)
+    }
+
+    /// The body of `GET {prefix}/{endpoint}`
+    pub fn get(&self, endpoint: &str) -> Result<Vec<u8>> {
+        self.call("GET", endpoint, &[])
+    }
+
+    /// A hex body from `GET {prefix}/{endpoint}`, decoded
+    pub fn get_bytes(&self, endpoint: &str) -> Result<Vec<u8>> {
+        decode_hex(&String::from_utf8(self.get(endpoint)?)?)
+    }
+
+    /// Sends `{method} {prefix}/{endpoint}` and returns the response, whatever
+    /// its status
+    pub fn request(
+        &self,
+        method: &str,
+        endpoint: &str,
+        params: &[(&str, String)],
+    ) -> Result<Response> {
+        let request = Request {
+            method: method.into(),
+            path: format!("{}/{endpoint}", self.prefix),
+            params: params
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect::<HashMap<_, _>>(),
+            close: false,
+        };
+        match self.send(&request) {
+            Ok(response) => Ok(response),
+            Err(_) => {
+                self.connection.replace(None);
+                self.send(&request)
+            }
+        }
+    }
+
+    fn call(&self, method: &str, endpoint: &str, params: &[(&str, String)]) -> Result<Vec<u8>> {
+        let response = self.request(method, endpoint, params)?;
+        if response.status != 200 {
+            bail!(
+                "{}/{endpoint} {}: {}",
+                self.prefix,
+                response.status,
+                String::from_utf8_lossy(&response.body)
+            );
+        }
+        Ok(response.body)
+    }
+
+    fn send(&self, request: &Request) -> Result<Response> {
+        let mut connection = self.connection.borrow_mut();
+        if connection.is_none() {
+            let stream = TcpStream::connect(self.addr)?;
+            stream.set_nodelay(true)?;
+            *connection = Some((BufReader::new(stream.try_clone()?), stream));
+        }
+        let Some((reader, writer)) = connection.as_mut() else {
+            unreachable!()
+        };
+        request.write_to(writer)?;
+        Response::read_from(reader)
+    }
+
+    fn post_bytes(&self, endpoint: &str, params: &[(&str, String)]) -> Result<Vec<u8>> {
+        decode_hex(&String::from_utf8(self.call("POST", endpoint, params)?)?)
+    }
+
+    fn post_bool(&self, endpoint: &str, params: &[(&str, String)]) -> Result<bool> {
+        match &self.call("POST", endpoint, params)?[..] {
+            b"true" => Ok(true),
+            b"false" => Ok(false),
+            body => bail!(
+                "expected true or false, got {:?}",
+                String::from_utf8_lossy(body)
+            ),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.to_hex()
+}
+
+impl Target for HttpOracle {
+    fn block_size_hint(&self) -> Option<usize> {
+        self.block_size
+    }
+}
+
+impl EncryptionOracle for HttpOracle {
+    fn encrypt(&self, input: &[u8]) -> Result<Vec<u8>> {
+        self.post_bytes("encrypt", &[("input", hex(input))])
+    }
+}
+
+impl DecryptionOracle for HttpOracle {
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.post_bytes("decrypt", &[("ciphertext", hex(ciphertext))])
+    }
+}
+
+impl PaddingOracle for HttpOracle {
+    fn check_padding(&self, ciphertext: &[u8]) -> Result<bool> {
+        self.post_bool("padding", &[("ciphertext", hex(ciphertext))])
+    }
+}
+
+impl VerificationOracle for HttpOracle {
+    fn verify(&self, message: &[u8]) -> Result<bool> {
+        self.post_bool("verify", &[("message", hex(message))])
+    }
+}
+
+impl EditOracle for HttpOracle {
+    fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> Result<Vec<u8>> {
+        let params = [
+            ("ciphertext", hex(ciphertext)),
+            ("offset", offset.to_string()),
+            ("newtext", hex(newtext)),
+        ];
+        self.post_bytes("edit", &params)
+    }
+}
+
+// Timed from the client's side, so the round trip is part of the noise
+impl SignatureOracle for HttpOracle {
+    fn check_signature(&self, file: &str, signature: &[u8]) -> Result<(bool, Duration)> {
+        let params = [("file", file.to_string()), ("signature", hex(signature))];
+        let start = Instant::now();
+        let response = self.request("GET", "test", &params)?;
+        let elapsed = start.elapsed();
+        match response.status {
+            200 => Ok((true, elapsed)),
+            500 => Ok((false, elapsed)),
+            status => bail!("{}/test {status}", self.prefix),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{
+            break_cbc_bitflip, break_cbc_padding_oracle, break_ctr_edit, break_ecb,
+            break_ecb_cut_paste,
+        },
+        hex::EncodeHex,
+        http::{client::HttpOracle, server::Lab},
+        oracle::{
+            metered::{measure, Budget},
+            DecryptionOracle, EncryptionOracle, Target, VerificationOracle,
+        },
+        utils::random::test_rng,
+    };
+
+    // every attack, run against the lab over a socket
+    #[test]
+    fn test_attacks_over_http() {
+        let secret = b"Rollin' in my 5.0".to_vec();
+        let lab = Lab::start(&secret, &mut test_rng()).unwrap();
+        let addr = lab.server.addr();
+        let connect = |prefix| HttpOracle::connect(addr, prefix).unwrap();
+
+        let ecb = connect("/ecb");
+        assert_eq!(ecb.block_size_hint(), Some(16));
+        assert!(lab.ecb.verify(&break_ecb(&ecb).unwrap()));
+        let (result, report) = measure(
+            "break_ecb",
+            connect("/prefix-ecb"),
+            Budget::unlimited(),
+            break_ecb,
+        );
+        assert!(lab.prefix_ecb.verify(&result.unwrap()));
+        assert!(report.stats.queries > secret.len());
+
+        let profile = connect("/profile");
+        let forged = break_ecb_cut_paste(&profile).unwrap();
+        assert!(VerificationOracle::verify(&profile, &forged).unwrap());
+        assert!(String::from_utf8(profile.decrypt(&forged).unwrap())
+            .unwrap()
+            .ends_with("role=admin"));
+
+        let cbc = connect("/cbc");
+        let forged = break_cbc_bitflip(&cbc).unwrap();
+        assert!(VerificationOracle::verify(&cbc, &forged).unwrap());
+        assert!(lab.cbc.verify(&forged).unwrap());
+
+        let padding = connect("/cbc-padding");
+        let challenge = padding.get_bytes("challenge").unwrap();
+        let (iv, ciphertext) = challenge.split_at(16);
+        let plaintext = break_cbc_padding_oracle(&padding, iv, ciphertext).unwrap();
+        assert!(lab.cbc_padding.verify(&plaintext));
+
+        let edit = connect("/ctr-edit");
+        let ciphertext = edit.get_bytes("challenge").unwrap();
+        assert!(lab
+            .ctr_edit
+            .verify(&break_ctr_edit(&edit, &ciphertext).unwrap()));
+    }
+
+    #[test]
+    fn test_errors_over_http() {
+        let lab = Lab::start(b"secret", &mut test_rng()).unwrap();
+        let addr = lab.server.addr();
+        // the profile oracle wants UTF-8 email addresses
+        let profile = HttpOracle::connect(addr, "/profile").unwrap();
+        let err = profile.encrypt(&[0xff]).unwrap_err();
+        assert!(err.to_string().contains("500"), "{err}");
+        // the connection survives an error
+        assert!(profile.encrypt(b"me@example.com").is_ok());
+
+        // ciphertexts that decrypt to nothing, or to nothing valid, are
+        // refused rather than taking the connection down
+        let padding_only = lab.profile.encrypt(b"a@bar.com").unwrap()[32..].to_vec();
+        for garbage in [vec![], vec![0; 16], padding_only] {
+            let response = profile
+                .request("POST", "verify", &[("message", garbage.to_hex())])
+                .unwrap();
+            assert_eq!(response.status, 500);
+        }
+        assert!(profile.encrypt(b"me@example.com").is_ok());
+
+        assert!(HttpOracle::connect(addr, "/nothing-here").is_err());
+        let ecb = HttpOracle::connect(addr, "/ecb").unwrap();
+        assert!(ecb.decrypt(b"no such endpoint").is_err());
+    }
+}
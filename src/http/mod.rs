@@ -0,0 +1,314 @@
+// A small HTTP/1.1 server and client over std::net, enough to put the oracles
+// behind a socket.
+// References: RFC 9112
+//
+// Only what the oracles need is supported: bodies sized by Content-Length (no
+// chunked encoding), parameters from the query string or a form-encoded body,
+// and persistent connections, since an attack sends thousands of queries.
+// Binary parameters and response bodies are hex.
+use std::{
+    collections::HashMap,
+    io::{BufRead, Read, Write},
+};
+
+use anyhow::{anyhow, bail, ensure, Result};
+
+use crate::hex::{DecodeHex, EncodeHex};
+
+pub mod client;
+pub mod server;
+
+const MAX_HEAD_LEN: usize = 8 * 1024;
+const MAX_BODY_LEN: usize = 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    pub params: HashMap<String, String>,
+    /// Whether the client asked for the connection to be closed afterwards
+    pub close: bool,
+}
+
+impl Request {
+    pub fn param(&self, name: &str) -> Result<&str> {
+        self.params
+            .get(name)
+            .map(|value| value.as_str())
+            .ok_or_else(|| anyhow!("missing parameter {name}"))
+    }
+
+    /// A hex-encoded parameter
+    pub fn bytes(&self, name: &str) -> Result<Vec<u8>> {
+        decode_hex(self.param(name)?)
+    }
+
+    pub fn number(&self, name: &str) -> Result<usize> {
+        Ok(self.param(name)?.parse()?)
+    }
+
+    pub fn write_to(&self, stream: &mut impl Write) -> Result<()> {
+        let body = encode_params(&self.params);
+        let connection = if self.close { "close" } else { "keep-alive" };
+        let mut message = format!(
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: {connection}\r\n\
+             Content-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n",
+            self.method,
+            self.path,
+            body.len()
+        )
+        .into_bytes();
+        message.extend(body.as_bytes());
+        stream.write_all(&message)?;
+        Ok(stream.flush()?)
+    }
+
+    /// Reads the next request, or `None` if the connection closed cleanly
+    /// before one started
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Option<Request>> {
+        let Some((start, headers, body)) = read_message(reader)? else {
+            return Ok(None);
+        };
+        let mut parts = start.split(' ');
+        let (Some(method), Some(target), Some(version), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            bail!("malformed request line {start:?}");
+        };
+        ensure!(version == "HTTP/1.1", "unsupported version {version}");
+
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let mut params = parse_params(query)?;
+        params.extend(parse_params(&String::from_utf8(body)?)?);
+        let close = headers
+            .get("connection")
+            .is_some_and(|value| value.eq_ignore_ascii_case("close"));
+        Ok(Some(Request {
+            method: method.into(),
+            path: path.into(),
+            params,
+            close,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub status: u16,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    pub fn ok(body: impl Into<Vec<u8>>) -> Self {
+        Response {
+            status: 200,
+            body: body.into(),
+        }
+    }
+
+    /// A hex-encoded body
+    pub fn bytes(bytes: &[u8]) -> Self {
+        Response::ok(bytes.to_hex())
+    }
+
+    pub fn bool(value: bool) -> Self {
+        Response::ok(value.to_string())
+    }
+
+    pub fn error(status: u16, message: impl Into<String>) -> Self {
+        Response {
+            status,
+            body: message.into().into_bytes(),
+        }
+    }
+
+    pub fn write_to(&self, stream: &mut impl Write) -> Result<()> {
+        let mut message = format!(
+            "HTTP/1.1 {} {}\r\nContent-Type: text/plain\r\nContent-Length: {}\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.body.len()
+        )
+        .into_bytes();
+        message.extend(&self.body);
+        stream.write_all(&message)?;
+        Ok(stream.flush()?)
+    }
+
+    pub fn read_from(reader: &mut impl BufRead) -> Result<Response> {
+        let (start, _, body) = read_message(reader)?.ok_or_else(|| anyhow!("connection closed"))?;
+        let status = start
+            .strip_prefix("HTTP/1.1 ")
+            .and_then(|rest| rest.get(..3))
+            .and_then(|status| status.parse().ok())
+            .ok_or_else(|| anyhow!("malformed status line {start:?}"))?;
+        Ok(Response { status, body })
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        500 => "Internal Server Error",
+        _ => "Unknown",
+    }
+}
+
+// The start line, the headers (with lowercase names) and the body
+type Message = (String, HashMap<String, String>, Vec<u8>);
+
+fn read_message(reader: &mut impl BufRead) -> Result<Option<Message>> {
+    let mut lines = vec![];
+    let mut head_len = 0;
+    loop {
+        let mut line = String::new();
+        let read = reader.take(MAX_HEAD_LEN as u64).read_line(&mut line)?;
+        if read == 0 {
+            ensure!(lines.is_empty(), "connection closed mid-message");
+            return Ok(None);
+        }
+        head_len += read;
+        ensure!(head_len <= MAX_HEAD_LEN, "message head too long");
+        ensure!(line.ends_with("\r\n"), "line not terminated by CRLF");
+        let line = line.trim_end_matches("\r\n").to_string();
+        if line.is_empty() {
+            break;
+        }
+        lines.push(line);
+    }
+
+    let mut lines = lines.into_iter();
+    let start = lines.next().ok_or_else(|| anyhow!("missing start line"))?;
+    let mut headers = HashMap::new();
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .ok_or_else(|| anyhow!("malformed header {line:?}"))?;
+        headers.insert(name.trim().to_ascii_lowercase(), value.trim().to_string());
+    }
+    ensure!(
+        !headers.contains_key("transfer-encoding"),
+        "only Content-Length bodies are supported"
+    );
+
+    let len = match headers.get("content-length") {
+        Some(len) => len.parse()?,
+        None => 0,
+    };
+    ensure!(len <= MAX_BODY_LEN, "body of {len} bytes is too long");
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(Some((start, headers, body)))
+}
+
+fn decode_hex(hex: &str) -> Result<Vec<u8>> {
+    ensure!(
+        hex.len().is_multiple_of(2)
+            && hex
+                .bytes()
+                .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b)),
+        "expected lowercase hex"
+    );
+    Ok(hex.decode_hex())
+}
+
+fn parse_params(encoded: &str) -> Result<HashMap<String, String>> {
+    encoded
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok((percent_decode(name)?, percent_decode(value)?))
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> Result<String> {
+    let mut out = vec![];
+    let mut bytes = s.bytes();
+    while let Some(b) = bytes.next() {
+        match b {
+            b'+' => out.push(b' '),
+            b'%' => {
+                let hex: String = bytes.by_ref().take(2).map(char::from).collect();
+                out.push(u8::from_str_radix(&hex, 16)?);
+            }
+            _ => out.push(b),
+        }
+    }
+    Ok(String::from_utf8(out)?)
+}
+
+fn encode_params(params: &HashMap<String, String>) -> String {
+    let encode = |s: &str| {
+        s.bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    char::from(b).to_string()
+                }
+                _ => format!("%{b:02X}"),
+            })
+            .collect::<String>()
+    };
+    params
+        .iter()
+        .map(|(name, value)| format!("{}={}", encode(name), encode(value)))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, io::BufReader};
+
+    use crate::http::{Request, Response};
+
+    #[test]
+    fn test_message_roundtrip() {
+        let request = Request {
+            method: "POST".into(),
+            path: "/cbc/encrypt".into(),
+            params: HashMap::from([
+                ("input".into(), "00ff".into()),
+                ("note".into(), "a b&c=d%".into()),
+            ]),
+            close: false,
+        };
+        let mut wire = vec![];
+        request.write_to(&mut wire).unwrap();
+        request.write_to(&mut wire).unwrap();
+        let mut reader = BufReader::new(&wire[..]);
+        // two requests on one connection, then a clean close
+        for _ in 0..2 {
+            let read = Request::read_from(&mut reader).unwrap().unwrap();
+            assert_eq!(read, request);
+            assert_eq!(read.bytes("input").unwrap(), [0x00, 0xff]);
+        }
+        assert_eq!(Request::read_from(&mut reader).unwrap(), None);
+
+        let mut wire = vec![];
+        Response::bytes(b"hi").write_to(&mut wire).unwrap();
+        let read = Response::read_from(&mut BufReader::new(&wire[..])).unwrap();
+        assert_eq!(read, Response::ok("6869"));
+    }
+
+    #[test]
+    fn test_rejects_malformed() {
+        let read = |wire: &str| Request::read_from(&mut BufReader::new(wire.as_bytes()));
+        let request = read("GET /x?input=zz&n=3 HTTP/1.1\r\n\r\n")
+            .unwrap()
+            .unwrap();
+        assert!(request.bytes("input").is_err());
+        assert!(request.bytes("missing").is_err());
+        assert_eq!(request.number("n").unwrap(), 3);
+
+        assert!(read("GET /x HTTP/1.0\r\n\r\n").is_err());
+        assert!(read("GET /x\r\n\r\n").is_err());
+        assert!(read("GET /x HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort").is_err());
+        assert!(read("GET /x HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n").is_err());
+        assert!(read("GET /x HTTP/1.1\r\nno colon\r\n\r\n").is_err());
+        assert!(read(&format!("GET /{} HTTP/1.1\r\n\r\n", "a".repeat(10_000))).is_err());
+    }
+}
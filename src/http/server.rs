@@ -0,0 +1,276 @@
+// Serves oracles over HTTP on loopback.
+//
+// An oracle mounted at a prefix such as `/cbc` gets one endpoint per trait it
+// implements, taking hex parameters and answering in hex or `true`/`false`:
+//
+//   GET  /cbc/block-size                     the block size hint, or empty
+//   POST /cbc/encrypt       input            EncryptionOracle
+//   POST /cbc/decrypt       ciphertext       DecryptionOracle
+//   POST /cbc/padding       ciphertext       PaddingOracle
+//   POST /cbc/verify        message          VerificationOracle
+//   POST /cbc/edit          ciphertext, offset (decimal), newtext
+//   GET  /cbc/test          file (text), signature
+//                                            SignatureOracle: 200 if the
+//                                            signature is valid, else 500
+//
+// An oracle error is a 500 with the message as the body. Each connection gets
+// its own thread.
+use std::{
+    collections::HashMap,
+    io::BufReader,
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::{self, JoinHandle},
+};
+
+use anyhow::Result;
+
+use crate::{
+    http::{Request, Response},
+    oracle::{
+        CbcOracle, CbcPaddingOracle, CtrEditOracle, DecryptionOracle, EcbOracle, EditOracle,
+        EncryptionOracle, PaddingOracle, PrefixEcbOracle, ProfileOracle, SignatureOracle, Target,
+        VerificationOracle,
+    },
+    utils::random::RandomSource,
+};
+
+type Handler = Arc<dyn Fn(&Request) -> Result<Response> + Send + Sync>;
+
+#[derive(Default)]
+pub struct ServerBuilder {
+    routes: HashMap<String, Handler>,
+}
+
+impl ServerBuilder {
+    pub fn route(
+        mut self,
+        path: &str,
+        handler: impl Fn(&Request) -> Result<Response> + Send + Sync + 'static,
+    ) -> Self {
+        self.routes.insert(path.into(), Arc::new(handler));
+        self
+    }
+
+    fn target<O: Target + Send + Sync + 'static>(self, prefix: &str, oracle: Arc<O>) -> Self {
+        self.route(&format!("{prefix}/block-size"), move |_| {
+            let hint = oracle.block_size_hint();
+            Ok(Response::ok(
+                hint.map(|n| n.to_string()).unwrap_or_default(),
+            ))
+        })
+    }
+
+    pub fn encryption<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: EncryptionOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/encrypt"), move |request| {
+                Ok(Response::bytes(&oracle.encrypt(&request.bytes("input")?)?))
+            })
+    }
+
+    pub fn decryption<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: DecryptionOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/decrypt"), move |request| {
+                Ok(Response::bytes(
+                    &oracle.decrypt(&request.bytes("ciphertext")?)?,
+                ))
+            })
+    }
+
+    pub fn padding<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: PaddingOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/padding"), move |request| {
+                Ok(Response::bool(
+                    oracle.check_padding(&request.bytes("ciphertext")?)?,
+                ))
+            })
+    }
+
+    pub fn verification<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: VerificationOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/verify"), move |request| {
+                Ok(Response::bool(oracle.verify(&request.bytes("message")?)?))
+            })
+    }
+
+    pub fn edit<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: EditOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/edit"), move |request| {
+                let edited = oracle.edit(
+                    &request.bytes("ciphertext")?,
+                    request.number("offset")?,
+                    &request.bytes("newtext")?,
+                )?;
+                Ok(Response::bytes(&edited))
+            })
+    }
+
+    pub fn signature<O>(self, prefix: &str, oracle: Arc<O>) -> Self
+    where
+        O: SignatureOracle + Send + Sync + 'static,
+    {
+        self.target(prefix, oracle.clone())
+            .route(&format!("{prefix}/test"), move |request| {
+                let (valid, _) =
+                    oracle.check_signature(request.param("file")?, &request.bytes("signature")?)?;
+                Ok(match valid {
+                    true => Response::ok("ok"),
+                    false => Response::error(500, "invalid signature"),
+                })
+            })
+    }
+
+    /// Listens on an ephemeral loopback port
+    pub fn start(self) -> Result<Server> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+        let shutdown = Arc::new(AtomicBool::new(false));
+        let routes = Arc::new(self.routes);
+
+        let stopping = shutdown.clone();
+        let thread = thread::spawn(move || {
+            for stream in listener.incoming() {
+                if stopping.load(Ordering::SeqCst) {
+                    break;
+                }
+                let Ok(stream) = stream else { continue };
+                let routes = routes.clone();
+                thread::spawn(move || serve_connection(stream, &routes));
+            }
+        });
+        Ok(Server {
+            addr,
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+}
+
+fn serve_connection(stream: TcpStream, routes: &HashMap<String, Handler>) {
+    let _ = stream.set_nodelay(true);
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+    let mut reader = BufReader::new(stream);
+    loop {
+        let request = match Request::read_from(&mut reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return,
+            Err(e) => {
+                let _ = Response::error(400, e.to_string()).write_to(&mut writer);
+                return;
+            }
+        };
+        let response = match routes.get(&request.path) {
+            Some(handler) => {
+                handler(&request).unwrap_or_else(|e| Response::error(500, e.to_string()))
+            }
+            None => Response::error(404, format!("no route for {}", request.path)),
+        };
+        if response.write_to(&mut writer).is_err() || request.close {
+            return;
+        }
+    }
+}
+
+/// Stops accepting connections when dropped
+pub struct Server {
+    addr: SocketAddr,
+    shutdown: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder::default()
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+        // wake the accept loop so it sees the flag
+        let _ = TcpStream::connect(self.addr);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// The oracles from sets 2 to 4 on one server, kept here as well so the
+/// results of a remote attack can be checked
+pub struct Lab {
+    pub server: Server,
+    pub ecb: Arc<EcbOracle>,
+    pub prefix_ecb: Arc<PrefixEcbOracle>,
+    pub profile: Arc<ProfileOracle>,
+    pub cbc: Arc<CbcOracle>,
+    pub cbc_padding: Arc<CbcPaddingOracle>,
+    pub ctr_edit: Arc<CtrEditOracle>,
+}
+
+impl Lab {
+    /// Mounts the oracles at `/ecb`, `/prefix-ecb`, `/profile`, `/cbc`,
+    /// `/cbc-padding` and `/ctr-edit`, all hiding `secret`. The target
+    /// ciphertexts for the padding oracle (IV first) and the CTR edit
+    /// oracle are at `/cbc-padding/challenge` and `/ctr-edit/challenge`.
+    pub fn start(secret: &[u8], rng: &mut impl RandomSource) -> Result<Lab> {
+        let ecb = Arc::new(EcbOracle::with_rng(secret.to_vec(), rng));
+        let prefix_ecb = Arc::new(PrefixEcbOracle::with_rng(secret.to_vec(), rng));
+        let profile = Arc::new(ProfileOracle::with_rng(rng));
+        let cbc = Arc::new(CbcOracle::with_rng(rng));
+        let cbc_padding = Arc::new(CbcPaddingOracle::with_rng(secret.to_vec(), rng)?);
+        let ctr_edit = Arc::new(CtrEditOracle::with_rng(secret.to_vec(), rng)?);
+
+        let padding_challenge = [&cbc_padding.iv[..], &cbc_padding.ciphertext].concat();
+        let edit_challenge = ctr_edit.ciphertext.clone();
+        let server = Server::builder()
+            .encryption("/ecb", ecb.clone())
+            .encryption("/prefix-ecb", prefix_ecb.clone())
+            .encryption("/profile", profile.clone())
+            .decryption("/profile", profile.clone())
+            .verification("/profile", profile.clone())
+            .encryption("/cbc", cbc.clone())
+            .verification("/cbc", cbc.clone())
+            .padding("/cbc-padding", cbc_padding.clone())
+            .route("/cbc-padding/challenge", move |_| {
+                Ok(Response::bytes(&padding_challenge))
+            })
+            .edit("/ctr-edit", ctr_edit.clone())
+            .route("/ctr-edit/challenge", move |_| {
+                Ok(Response::bytes(&edit_challenge))
+            })
+            .start()?;
+        Ok(Lab {
+            server,
+            ecb,
+            prefix_ecb,
+            profile,
+            cbc,
+            cbc_padding,
+            ctr_edit,
+        })
+    }
+}
@@ -0,0 +1,260 @@
+// AES key wrap, with and without padding.
+// References: RFC 3394, RFC 5649
+//
+// The key data is split into 64-bit registers R[1..n] alongside a 64-bit
+// integrity register A. Six passes over the registers each encrypt A || R[i]
+// and fold a step counter into A. Unwrapping runs the passes backwards and
+// checks that A came back to the expected initial value.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::{AesError, AesKey},
+    cipher::BlockCipher,
+};
+
+const SEMIBLOCK: usize = 8;
+
+/// RFC 3394 default initial value
+pub const DEFAULT_IV: [u8; SEMIBLOCK] = [0xa6; SEMIBLOCK];
+
+/// RFC 5649 alternative initial value prefix, followed by the 32-bit length
+const PADDED_IV_PREFIX: [u8; 4] = [0xa6, 0x59, 0x59, 0xa6];
+
+fn ensure_block_size(cipher: &impl BlockCipher) -> Result<()> {
+    ensure!(
+        cipher.block_size() == 2 * SEMIBLOCK,
+        "key wrap requires a 16 byte block cipher, got {}",
+        cipher.block_size()
+    );
+    Ok(())
+}
+
+fn integrity_error(msg: &str) -> anyhow::Error {
+    AesError::IntegrityCheck(msg.into()).into()
+}
+
+// W: wraps the registers with the initial value `iv`
+fn wrap_registers(cipher: &impl BlockCipher, iv: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+    let mut a = iv.to_vec();
+    let mut registers: Vec<Vec<u8>> = plaintext.chunks(SEMIBLOCK).map(<[u8]>::to_vec).collect();
+    let n = registers.len();
+    for j in 0..6 {
+        for (i, register) in registers.iter_mut().enumerate() {
+            let t = (n * j + i + 1) as u64;
+            let b = cipher.encrypt_block(&[&a[..], &register[..]].concat())?;
+            a = (u64::from_be_bytes(b[..SEMIBLOCK].try_into()?) ^ t)
+                .to_be_bytes()
+                .to_vec();
+            *register = b[SEMIBLOCK..].to_vec();
+        }
+    }
+    Ok([a, registers.concat()].concat())
+}
+
+// W^-1: returns (A, key data) without checking A
+fn unwrap_registers(cipher: &impl BlockCipher, ciphertext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+    let mut a = ciphertext[..SEMIBLOCK].to_vec();
+    let mut registers: Vec<Vec<u8>> = ciphertext[SEMIBLOCK..]
+        .chunks(SEMIBLOCK)
+        .map(<[u8]>::to_vec)
+        .collect();
+    let n = registers.len();
+    for j in (0..6).rev() {
+        for (i, register) in registers.iter_mut().enumerate().rev() {
+            let t = (n * j + i + 1) as u64;
+            let a_t = (u64::from_be_bytes(a[..].try_into()?) ^ t).to_be_bytes();
+            let b = cipher.decrypt_block(&[&a_t[..], &register[..]].concat())?;
+            a = b[..SEMIBLOCK].to_vec();
+            *register = b[SEMIBLOCK..].to_vec();
+        }
+    }
+    Ok((a, registers.concat()))
+}
+
+/// RFC 3394 key wrap. The key data must be at least two 64-bit blocks.
+pub fn wrap(cipher: &impl BlockCipher, key_data: &[u8]) -> Result<Vec<u8>> {
+    ensure_block_size(cipher)?;
+    ensure!(
+        key_data.len() >= 2 * SEMIBLOCK && key_data.len().is_multiple_of(SEMIBLOCK),
+        "key data must be a multiple of 8 bytes and at least 16, got {}",
+        key_data.len()
+    );
+    wrap_registers(cipher, &DEFAULT_IV, key_data)
+}
+
+/// RFC 3394 key unwrap, failing with `AesError::IntegrityCheck` if the
+/// integrity check value doesn't match
+pub fn unwrap(cipher: &impl BlockCipher, wrapped: &[u8]) -> Result<Vec<u8>> {
+    ensure_block_size(cipher)?;
+    ensure!(
+        wrapped.len() >= 3 * SEMIBLOCK && wrapped.len().is_multiple_of(SEMIBLOCK),
+        "wrapped key must be a multiple of 8 bytes and at least 24, got {}",
+        wrapped.len()
+    );
+    let (a, key_data) = unwrap_registers(cipher, wrapped)?;
+    if !openssl::memcmp::eq(&a, &DEFAULT_IV) {
+        bail!(integrity_error("integrity check value mismatch"));
+    }
+    Ok(key_data)
+}
+
+/// RFC 5649 key wrap with padding: any non-empty length of key data. The
+/// initial value carries the real length, and a single padded block is just
+/// encrypted directly.
+pub fn wrap_with_padding(cipher: &impl BlockCipher, key_data: &[u8]) -> Result<Vec<u8>> {
+    ensure_block_size(cipher)?;
+    ensure!(!key_data.is_empty(), "key data must not be empty");
+    let mli = u32::try_from(key_data.len())?;
+    let iv = [&PADDED_IV_PREFIX[..], &mli.to_be_bytes()].concat();
+    let mut padded = key_data.to_vec();
+    padded.resize(key_data.len().next_multiple_of(SEMIBLOCK), 0);
+    if padded.len() == SEMIBLOCK {
+        cipher.encrypt_block(&[iv, padded].concat())
+    } else {
+        wrap_registers(cipher, &iv, &padded)
+    }
+}
+
+pub fn unwrap_with_padding(cipher: &impl BlockCipher, wrapped: &[u8]) -> Result<Vec<u8>> {
+    ensure_block_size(cipher)?;
+    ensure!(
+        wrapped.len() >= 2 * SEMIBLOCK && wrapped.len().is_multiple_of(SEMIBLOCK),
+        "wrapped key must be a multiple of 8 bytes and at least 16, got {}",
+        wrapped.len()
+    );
+    let (a, padded) = if wrapped.len() == 2 * SEMIBLOCK {
+        let block = cipher.decrypt_block(wrapped)?;
+        (block[..SEMIBLOCK].to_vec(), block[SEMIBLOCK..].to_vec())
+    } else {
+        unwrap_registers(cipher, wrapped)?
+    };
+
+    if !openssl::memcmp::eq(&a[..4], &PADDED_IV_PREFIX) {
+        bail!(integrity_error("integrity check value mismatch"));
+    }
+    let mli = u32::from_be_bytes(a[4..].try_into()?) as usize;
+    if mli > padded.len() || mli + SEMIBLOCK <= padded.len() {
+        bail!(integrity_error("message length indicator out of range"));
+    }
+    if padded[mli..].iter().any(|&b| b != 0) {
+        bail!(integrity_error("non-zero padding"));
+    }
+    Ok(padded[..mli].to_vec())
+}
+
+pub fn wrap_aes(kek: &[u8], key_data: &[u8]) -> Result<Vec<u8>> {
+    wrap(&AesKey::new(kek)?, key_data)
+}
+
+pub fn unwrap_aes(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+    unwrap(&AesKey::new(kek)?, wrapped)
+}
+
+pub fn wrap_aes_with_padding(kek: &[u8], key_data: &[u8]) -> Result<Vec<u8>> {
+    wrap_with_padding(&AesKey::new(kek)?, key_data)
+}
+
+pub fn unwrap_aes_with_padding(kek: &[u8], wrapped: &[u8]) -> Result<Vec<u8>> {
+    unwrap_with_padding(&AesKey::new(kek)?, wrapped)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesError,
+        hex::{DecodeHex, EncodeHex},
+        keywrap::{unwrap_aes, unwrap_aes_with_padding, wrap_aes, wrap_aes_with_padding},
+        utils::bytes,
+    };
+
+    const KEK: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+    const KEY_DATA: &str = "00112233445566778899aabbccddeeff000102030405060708090a0b0c0d0e0f";
+
+    fn is_integrity_error(err: &anyhow::Error) -> bool {
+        matches!(
+            err.downcast_ref::<AesError>(),
+            Some(AesError::IntegrityCheck(_))
+        )
+    }
+
+    #[test]
+    fn test_rfc3394_vectors() {
+        // (KEK length, key data length, wrapped), RFC 3394 section 4
+        let vectors = [
+            (16, 16, "1fa68b0a8112b447aef34bd8fb5a7b829d3e862371d2cfe5"),
+            (24, 16, "96778b25ae6ca435f92b5b97c050aed2468ab8a17ad84e5d"),
+            (32, 16, "64e8c3f9ce0f5ba263e9777905818a2a93c8191e7d6e8ae7"),
+            (
+                24,
+                24,
+                "031d33264e15d33268f24ec260743edce1c6c7ddee725a936ba814915c6762d2",
+            ),
+            (
+                32,
+                24,
+                "a8f9bc1612c68b3ff6e6f4fbe30e71e4769c8b80a32cb8958cd5d17d6b254da1",
+            ),
+            (
+                32,
+                32,
+                "28c9f404c4b810f4cbccb35cfb87f8263f5786e2d80ed326cbc7f0e71a99f43bfb988b9b7a02dd21",
+            ),
+        ];
+        let (kek, key_data) = (KEK.decode_hex(), KEY_DATA.decode_hex());
+        for (kek_len, key_len, expected) in vectors {
+            let (kek, key_data) = (&kek[..kek_len], &key_data[..key_len]);
+            let wrapped = wrap_aes(kek, key_data).unwrap();
+            assert_eq!(wrapped.to_hex(), expected);
+            assert_eq!(unwrap_aes(kek, &wrapped).unwrap(), key_data);
+        }
+    }
+
+    #[test]
+    fn test_rfc5649_vectors() {
+        // RFC 5649 section 6
+        let kek = "5840df6e29b02af1ab493b705bf16ea1ae8338f4dcc176a8".decode_hex();
+        let vectors = [
+            (
+                "c37b7e6492584340bed12207808941155068f738",
+                "138bdeaa9b8fa7fc61f97742e72248ee5ae6ae5360d1ae6a5f54f373fa543b6a",
+            ),
+            ("466f7250617369", "afbeb0f07dfbf5419200f2ccb50bb24f"),
+        ];
+        for (key_data, expected) in vectors {
+            let key_data = key_data.decode_hex();
+            let wrapped = wrap_aes_with_padding(&kek, &key_data).unwrap();
+            assert_eq!(wrapped.to_hex(), expected);
+            assert_eq!(unwrap_aes_with_padding(&kek, &wrapped).unwrap(), key_data);
+        }
+    }
+
+    #[test]
+    fn test_unwrap_detects_tampering() {
+        let kek = bytes::rand_of_len(16);
+        let key_data = bytes::rand_of_len(32);
+        let mut wrapped = wrap_aes(&kek, &key_data).unwrap();
+        wrapped[12] ^= 1;
+        assert!(is_integrity_error(&unwrap_aes(&kek, &wrapped).unwrap_err()));
+        wrapped[12] ^= 1;
+        let err = unwrap_aes(&bytes::rand_of_len(16), &wrapped).unwrap_err();
+        assert!(is_integrity_error(&err));
+
+        // a padded wrap isn't a valid plain wrap, and vice versa
+        let padded = wrap_aes_with_padding(&kek, &key_data).unwrap();
+        assert!(unwrap_aes(&kek, &padded).is_err());
+        assert!(unwrap_aes_with_padding(&kek, &wrapped).is_err());
+
+        for len in [1, 8, 9, 20] {
+            let key_data = bytes::rand_of_len(len);
+            let mut wrapped = wrap_aes_with_padding(&kek, &key_data).unwrap();
+            assert_eq!(unwrap_aes_with_padding(&kek, &wrapped).unwrap(), key_data);
+            wrapped[0] ^= 0x80;
+            let err = unwrap_aes_with_padding(&kek, &wrapped).unwrap_err();
+            assert!(is_integrity_error(&err));
+        }
+
+        assert!(wrap_aes(&kek, &[0; 8]).is_err());
+        assert!(wrap_aes(&kek, &[0; 20]).is_err());
+        assert!(wrap_aes_with_padding(&kek, &[]).is_err());
+    }
+}
@@ -1,11 +1,26 @@
 pub mod aes;
 pub mod base64;
+pub mod cipher;
+pub mod container;
+pub mod ctr;
+pub mod des;
+pub mod envelope;
+pub mod fingerprint;
 pub mod frequency;
+pub mod gcm;
 pub mod hamming;
 pub mod hex;
+pub mod http;
+pub mod keywrap;
+pub mod mac;
 pub mod mersenne;
+pub mod modes;
+pub mod openssl_enc;
 pub mod oracle;
 pub mod padding;
 pub mod sets;
+pub mod siv;
+pub mod stream;
 pub mod utils;
 pub mod xor;
+pub mod xts;
@@ -0,0 +1,159 @@
+// Forgeries against CBC-MAC.
+// Reference: https://cryptopals.com/sets/7/challenges/49 and 50
+//
+// CBC-MAC is just the final CBC state, so anyone who knows a tag knows the state
+// the chain was in after that message. Xoring a tag into the next block cancels
+// it out and restarts the chain as if from the IV.
+use anyhow::{ensure, Result};
+
+use crate::{cipher::BlockCipher, mac::cbc_mac, padding::PadPkcs7, xor::Xor};
+
+/// Attacker-controlled IV: the IV is xored into the first block only, so any
+/// change to the first block can be cancelled by the same change to the IV.
+/// Returns the forged message and the IV to send with it, which verify under
+/// the original tag.
+pub fn forge_with_iv(
+    message: &[u8],
+    iv: &[u8],
+    forged_first_block: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let block_size = iv.len();
+    ensure!(
+        forged_first_block.len() == block_size,
+        "expected a {block_size} byte block, got {}",
+        forged_first_block.len()
+    );
+    ensure!(
+        message.len() >= block_size,
+        "message is shorter than one block"
+    );
+    let forged_iv = iv.xor(&message[..block_size]).xor(forged_first_block);
+    let mut forged = forged_first_block.to_vec();
+    forged.extend(&message[block_size..]);
+    Ok((forged, forged_iv))
+}
+
+/// Length extension on variable-length CBC-MAC: given the tag of `first`, the
+/// message pad(first) || second', where the first block of `second` has been
+/// xored with that tag, carries the same tag as `second` did on its own.
+pub fn forge_length_extension(
+    iv: &[u8],
+    first: &[u8],
+    first_tag: &[u8],
+    second: &[u8],
+) -> Result<Vec<u8>> {
+    let block_size = iv.len();
+    ensure!(
+        first_tag.len() == block_size,
+        "expected a {block_size} byte tag, got {}",
+        first_tag.len()
+    );
+    // a partial first block would pull its padding into the glue block
+    ensure!(
+        second.len() >= block_size,
+        "the extension must be at least one block"
+    );
+    let mut forged = first.pad_pkcs7_blocksize(block_size);
+    forged.extend(second[..block_size].xor(iv).xor(first_tag));
+    forged.extend(&second[block_size..]);
+    Ok(forged)
+}
+
+/// A message starting with `prefix` that has the same CBC-MAC as `target`.
+/// Needs the key, so this only matters where CBC-MAC is (mis)used as a hash.
+pub fn forge_collision(
+    cipher: &impl BlockCipher,
+    iv: &[u8],
+    target: &[u8],
+    prefix: &[u8],
+) -> Result<Vec<u8>> {
+    let tag = cbc_mac(cipher, iv, prefix)?;
+    forge_length_extension(iv, prefix, &tag, target)
+}
+
+/// JavaScript that runs `payload` and has the same CBC-MAC as `target`. The
+/// glue and padding bytes are hidden behind a `//` comment, so the payload is
+/// padded with spaces until none of them would end the comment early.
+pub fn forge_javascript_collision(
+    cipher: &impl BlockCipher,
+    iv: &[u8],
+    target: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    for spaces in 0..=4 * block_size {
+        let mut prefix = payload.to_vec();
+        prefix.extend(vec![b' '; spaces]);
+        prefix.extend(b"//");
+        let forged = forge_collision(cipher, iv, target, &prefix)?;
+        let glue = &forged[prefix.len()..forged.len() - (target.len() - block_size)];
+        if !glue.iter().any(|b| matches!(b, b'\n' | b'\r')) {
+            return Ok(forged);
+        }
+    }
+    anyhow::bail!("every glue block contained a line break")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesKey,
+        cipher::{BlockCipher, ToyCipher},
+        mac::{
+            attack::{forge_javascript_collision, forge_length_extension, forge_with_iv},
+            cbc_mac, verify_cbc_mac,
+        },
+        utils::bytes,
+    };
+
+    fn check_forgeries(cipher: &impl BlockCipher) {
+        let block_size = cipher.block_size();
+        let iv = bytes::rand_of_len(block_size);
+
+        let message = b"to=eve&from=victim&amount=1000000";
+        let tag = cbc_mac(cipher, &iv, message).unwrap();
+        let mut first_block = message[..block_size].to_vec();
+        first_block[3..6].copy_from_slice(b"bob");
+        let (forged, forged_iv) = forge_with_iv(message, &iv, &first_block).unwrap();
+        assert_ne!(forged, message);
+        assert!(verify_cbc_mac(cipher, &forged_iv, &forged, &tag));
+
+        let first = b"from=alice&tx_list=bob:10";
+        let first_tag = cbc_mac(cipher, &iv, first).unwrap();
+        // the attacker's own message, signed by the server
+        let second = b"xxxxxxxxxxxxxxxx;mallory:1000000";
+        let second_tag = cbc_mac(cipher, &iv, second).unwrap();
+        let forged = forge_length_extension(&iv, first, &first_tag, second).unwrap();
+        assert!(forged.starts_with(first));
+        assert!(forged.ends_with(b";mallory:1000000"));
+        assert!(verify_cbc_mac(cipher, &iv, &forged, &second_tag));
+
+        assert!(forge_length_extension(&iv, first, &first_tag, b"x").is_err());
+    }
+
+    #[test]
+    fn test_cbc_mac_forgeries() {
+        check_forgeries(&AesKey::new(&bytes::rand_of_len(16)).unwrap());
+        check_forgeries(&ToyCipher::new(&bytes::rand_of_len(8)).unwrap());
+    }
+
+    #[test]
+    fn test_javascript_collision() {
+        let cipher = AesKey::new(b"YELLOW SUBMARINE").unwrap();
+        let iv = [0; 16];
+        let target = b"alert('MZA who was that?');\n";
+        let payload = b"alert('Ayo, the Wu is back!');";
+        let forged = forge_javascript_collision(&cipher, &iv, target, payload).unwrap();
+
+        assert!(forged.starts_with(payload));
+        assert_eq!(
+            cbc_mac(&cipher, &iv, &forged).unwrap(),
+            cbc_mac(&cipher, &iv, target).unwrap()
+        );
+        // everything after the payload is on the one commented-out line
+        let rest = &forged[payload.len()..forged.len() - 1];
+        let comment = rest.iter().position(|&b| b == b'/').unwrap();
+        assert!(rest[..comment].iter().all(|&b| b == b' '));
+        assert!(!rest.iter().any(|&b| b == b'\n' || b == b'\r'));
+    }
+}
@@ -0,0 +1,227 @@
+// MACs built from a block cipher, plus HMAC.
+// References: NIST SP 800-38B and RFC 4493 for CMAC, RFC 2104 for HMAC
+use anyhow::{bail, ensure, Result};
+use openssl::{hash::MessageDigest, pkey::PKey, sign::Signer};
+
+use crate::{cipher::BlockCipher, modes::ensure_iv_len, padding::PadPkcs7, xor::Xor};
+
+pub mod attack;
+pub mod timing;
+
+/// CBC-MAC over whole blocks: the last block of the CBC encryption of
+/// `message`. Only secure for fixed-length messages.
+pub fn cbc_mac_unpadded(cipher: &impl BlockCipher, iv: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let block_size = cipher.block_size();
+    ensure!(
+        message.len().is_multiple_of(block_size),
+        "expected multiple of {block_size}, got {}",
+        message.len()
+    );
+    let mut state = iv.to_vec();
+    for block in message.chunks_exact(block_size) {
+        state = cipher.encrypt_block(&block.xor(&state))?;
+    }
+    Ok(state)
+}
+
+/// CBC-MAC of the PKCS#7-padded message
+pub fn cbc_mac(cipher: &impl BlockCipher, iv: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    cbc_mac_unpadded(
+        cipher,
+        iv,
+        &message.pad_pkcs7_blocksize(cipher.block_size()),
+    )
+}
+
+pub fn verify_cbc_mac(cipher: &impl BlockCipher, iv: &[u8], message: &[u8], tag: &[u8]) -> bool {
+    cbc_mac(cipher, iv, message).is_ok_and(|expected| tags_equal(&expected, tag))
+}
+
+pub(crate) fn tags_equal(expected: &[u8], tag: &[u8]) -> bool {
+    expected.len() == tag.len() && openssl::memcmp::eq(expected, tag)
+}
+
+// The constant folded in when doubling shifts out a set bit: x^128 + x^7 + x^2 + x + 1
+// for 128-bit blocks and x^64 + x^4 + x^3 + x + 1 for 64-bit blocks.
+pub(crate) fn rb(block_size: usize) -> Result<u8> {
+    Ok(match block_size {
+        16 => 0x87,
+        8 => 0x1b,
+        _ => bail!("CMAC is only defined for 8 and 16 byte blocks, got {block_size}"),
+    })
+}
+
+// Multiplication by x in GF(2^n): shift the whole block left one bit
+pub(crate) fn double(block: &[u8], rb: u8) -> Vec<u8> {
+    let mut out: Vec<u8> = block
+        .iter()
+        .zip(block.iter().skip(1).chain(std::iter::once(&0)))
+        .map(|(&b, &next)| (b << 1) | (next >> 7))
+        .collect();
+    if block[0] & 0x80 != 0 {
+        *out.last_mut().unwrap() ^= rb;
+    }
+    out
+}
+
+/// The CMAC subkeys (K1, K2), derived from L = E(0)
+pub fn cmac_subkeys(cipher: &impl BlockCipher) -> Result<(Vec<u8>, Vec<u8>)> {
+    let block_size = cipher.block_size();
+    let rb = rb(block_size)?;
+    let l = cipher.encrypt_block(&vec![0; block_size])?;
+    let k1 = double(&l, rb);
+    let k2 = double(&k1, rb);
+    Ok((k1, k2))
+}
+
+/// CMAC: CBC-MAC with a zero IV where the last block is xored with K1 if it is
+/// complete, or padded with 10* and xored with K2 if not. Safe for variable
+/// length messages.
+pub fn cmac(cipher: &impl BlockCipher, message: &[u8]) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    let (k1, k2) = cmac_subkeys(cipher)?;
+    let split = match message.len() {
+        0 => 0,
+        len => (len - 1) / block_size * block_size,
+    };
+    let (head, last) = message.split_at(split);
+    let last = if last.len() == block_size {
+        last.xor(&k1)
+    } else {
+        let mut padded = last.to_vec();
+        padded.push(0x80);
+        padded.resize(block_size, 0);
+        padded.xor(&k2)
+    };
+    let mut blocks = head.to_vec();
+    blocks.extend(last);
+    cbc_mac_unpadded(cipher, &vec![0; block_size], &blocks)
+}
+
+pub fn verify_cmac(cipher: &impl BlockCipher, message: &[u8], tag: &[u8]) -> bool {
+    cmac(cipher, message).is_ok_and(|expected| tags_equal(&expected, tag))
+}
+
+fn hmac(digest: MessageDigest, key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(digest, &key)?;
+    signer.update(message)?;
+    Ok(signer.sign_to_vec()?)
+}
+
+/// HMAC-SHA256, via OpenSSL
+pub fn hmac_sha256(key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    hmac(MessageDigest::sha256(), key, message)
+}
+
+/// HMAC-SHA1, via OpenSSL
+pub fn hmac_sha1(key: &[u8], message: &[u8]) -> Result<Vec<u8>> {
+    hmac(MessageDigest::sha1(), key, message)
+}
+
+pub fn verify_hmac_sha256(key: &[u8], message: &[u8], tag: &[u8]) -> bool {
+    hmac_sha256(key, message).is_ok_and(|expected| tags_equal(&expected, tag))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesKey,
+        cipher::ToyCipher,
+        hex::{DecodeHex, EncodeHex},
+        mac::{
+            cbc_mac, cbc_mac_unpadded, cmac, cmac_subkeys, hmac_sha1, hmac_sha256, verify_cbc_mac,
+            verify_cmac, verify_hmac_sha256,
+        },
+        utils::bytes,
+    };
+
+    // RFC 4493 section 4
+    const RFC4493_KEY: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const RFC4493_MESSAGE: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+
+    #[test]
+    fn test_cmac_rfc4493() {
+        let cipher = AesKey::new(&RFC4493_KEY.decode_hex()).unwrap();
+        let (k1, k2) = cmac_subkeys(&cipher).unwrap();
+        assert_eq!(k1.to_hex(), "fbeed618357133667c85e08f7236a8de");
+        assert_eq!(k2.to_hex(), "f7ddac306ae266ccf90bc11ee46d513b");
+
+        let message = RFC4493_MESSAGE.decode_hex();
+        let vectors = [
+            (0, "bb1d6929e95937287fa37d129b756746"),
+            (16, "070a16b46b4d4144f79bdd9dd04a287c"),
+            (40, "dfa66747de9ae63030ca32611497c827"),
+            (64, "51f0bebf7e3b9d92fc49741779363cfe"),
+        ];
+        for (len, expected) in vectors {
+            let tag = cmac(&cipher, &message[..len]).unwrap();
+            assert_eq!(tag.to_hex(), expected);
+            assert!(verify_cmac(&cipher, &message[..len], &tag));
+            let mut tampered = tag.clone();
+            tampered[0] ^= 1;
+            assert!(!verify_cmac(&cipher, &message[..len], &tampered));
+        }
+    }
+
+    #[test]
+    fn test_cbc_mac() {
+        // from cryptopals challenge 50
+        let cipher = AesKey::new(b"YELLOW SUBMARINE").unwrap();
+        let iv = [0; 16];
+        let snippet = b"alert('MZA who was that?');\n";
+        let tag = cbc_mac(&cipher, &iv, snippet).unwrap();
+        assert_eq!(tag.to_hex(), "296b8d7cb78a243dda4d0a61d33bbdd1");
+        assert!(verify_cbc_mac(&cipher, &iv, snippet, &tag));
+        assert!(!verify_cbc_mac(&cipher, &iv, snippet, &tag[..8]));
+        assert!(!verify_cbc_mac(&cipher, &[1; 16], snippet, &tag));
+
+        assert!(cbc_mac_unpadded(&cipher, &iv, snippet).is_err());
+        assert!(cbc_mac(&cipher, &[0; 8], snippet).is_err());
+    }
+
+    #[test]
+    fn test_cmac_8_byte_blocks() {
+        let cipher = ToyCipher::new(&bytes::rand_of_len(8)).unwrap();
+        let (k1, k2) = cmac_subkeys(&cipher).unwrap();
+        assert_ne!(k1, k2);
+        let tag = cmac(&cipher, b"a message for the toy cipher").unwrap();
+        assert_eq!(tag.len(), 8);
+        assert!(verify_cmac(&cipher, b"a message for the toy cipher", &tag));
+        assert!(!verify_cmac(&cipher, b"a message for the toy cipheR", &tag));
+    }
+
+    #[test]
+    fn test_hmac_sha256_rfc4231() {
+        // RFC 4231 test cases 1 and 2
+        let vectors = [
+            (
+                "0b".repeat(20).as_str().decode_hex(),
+                b"Hi There".to_vec(),
+                "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7",
+            ),
+            (
+                b"Jefe".to_vec(),
+                b"what do ya want for nothing?".to_vec(),
+                "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843",
+            ),
+        ];
+        for (key, message, expected) in vectors {
+            let tag = hmac_sha256(&key, &message).unwrap();
+            assert_eq!(tag.to_hex(), expected);
+            assert!(verify_hmac_sha256(&key, &message, &tag));
+            assert!(!verify_hmac_sha256(&key, &message, &tag[..16]));
+            assert!(!verify_hmac_sha256(&key, b"something else", &tag));
+        }
+    }
+
+    #[test]
+    fn test_hmac_sha1_rfc2202() {
+        // RFC 2202 section 3, test cases 1 and 2
+        let tag = hmac_sha1(&"0b".repeat(20).as_str().decode_hex(), b"Hi There").unwrap();
+        assert_eq!(tag.to_hex(), "b617318655057264e28bc0b6fb378c8ef146be00");
+        let tag = hmac_sha1(b"Jefe", b"what do ya want for nothing?").unwrap();
+        assert_eq!(tag.to_hex(), "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+    }
+}
@@ -0,0 +1,538 @@
+// Recovering an HMAC from the time taken to reject it.
+// Reference: https://cryptopals.com/sets/4/challenges/31 and 32
+//
+// The service compares signatures a byte at a time, sleeping after each match
+// and returning at the first mismatch, so a guess that gets one more byte right
+// takes one delay longer to reject. The attack tries all 256 values of the next
+// byte and keeps the slowest.
+//
+// With a large delay a couple of samples per candidate is enough. Once the
+// delay shrinks towards the noise the averages stop agreeing, and the attack
+// switches to sampling repeatedly: means are trimmed so a stalled request
+// cannot decide a byte, the field is cut to a few contenders, and those are
+// sampled in rounds until a Welch t-test separates the slowest from the rest.
+use std::{
+    fmt, thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::{
+    hex::EncodeHex,
+    mac::hmac_sha1,
+    oracle::{SignatureOracle, Target},
+    utils::random::RandomSource,
+};
+
+pub const SIGNATURE_LEN: usize = 20;
+
+/// Compares a byte at a time, sleeping `delay` after each byte that matches
+/// and giving up at the first one that doesn't
+pub fn insecure_compare(a: &[u8], b: &[u8], delay: Duration) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    for (x, y) in a.iter().zip(b) {
+        if x != y {
+            return false;
+        }
+        thread::sleep(delay);
+    }
+    true
+}
+
+/// Signs files with HMAC-SHA1 under a secret key, and checks signatures with
+/// `insecure_compare`
+pub struct SignatureService {
+    key: Vec<u8>,
+    delay: Duration,
+}
+
+impl SignatureService {
+    pub fn new(delay: Duration) -> Self {
+        SignatureService::with_rng(delay, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(delay: Duration, rng: &mut impl RandomSource) -> Self {
+        SignatureService {
+            key: rng.rand_bytes(16),
+            delay,
+        }
+    }
+
+    pub fn sign(&self, file: &str) -> Result<Vec<u8>> {
+        hmac_sha1(&self.key, file.as_bytes())
+    }
+
+    pub fn check(&self, file: &str, signature: &[u8]) -> Result<bool> {
+        Ok(insecure_compare(&self.sign(file)?, signature, self.delay))
+    }
+}
+
+impl Target for SignatureService {}
+
+impl SignatureOracle for SignatureService {
+    fn check_signature(&self, file: &str, signature: &[u8]) -> Result<(bool, Duration)> {
+        let start = Instant::now();
+        let valid = self.check(file, signature)?;
+        Ok((valid, start.elapsed()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TimingConfig {
+    /// Samples of every candidate before deciding whether averaging is enough
+    pub initial_passes: usize,
+    /// How far the slowest candidate's fastest sample must be above the
+    /// runner-up's mean, in robust standard deviations of all the means, for
+    /// averaging to decide the byte
+    pub clear_margin: f64,
+    /// Fraction of samples dropped from each end of a trimmed mean
+    pub trim: f64,
+    /// Further samples of every candidate once averaging isn't enough
+    pub screening_passes: usize,
+    /// Candidates kept after screening
+    pub contenders: usize,
+    /// Welch t statistic the slowest contender must reach over each of the
+    /// others
+    pub t_threshold: f64,
+    /// Rounds of sampling the contenders before settling for the leader
+    pub max_rounds: usize,
+    /// Times the attack may go back and redo its least certain byte when no
+    /// final byte is accepted
+    pub max_backtracks: usize,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        TimingConfig {
+            initial_passes: 2,
+            clear_margin: 6.0,
+            trim: 0.1,
+            screening_passes: 4,
+            contenders: 8,
+            t_threshold: 4.0,
+            max_rounds: 200,
+            max_backtracks: 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    Averaging,
+    Statistical,
+    /// The last byte, found by trying each value until one is accepted
+    Exhaustive,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteVerdict {
+    pub byte: u8,
+    pub strategy: Strategy,
+    pub queries: usize,
+    /// The averaging margin or the smallest t statistic, depending on the
+    /// strategy. Infinite for an exhaustive search.
+    pub confidence: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimingReport {
+    pub signature: Vec<u8>,
+    pub bytes: Vec<ByteVerdict>,
+    /// Every query, including those spent on bytes that were later redone
+    pub queries: usize,
+    pub backtracks: usize,
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for TimingReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let by = |strategy| self.bytes.iter().filter(|b| b.strategy == strategy).count();
+        write!(
+            f,
+            "{} in {} queries over {:?}: {} bytes by averaging, {} statistically, {} backtracks",
+            self.signature.to_hex(),
+            self.queries,
+            self.elapsed,
+            by(Strategy::Averaging),
+            by(Strategy::Statistical),
+            self.backtracks
+        )
+    }
+}
+
+/// Recovers the whole signature of `file`. The last byte changes no timing
+/// that matters, so it is found by trying all 256 values; if none is accepted
+/// an earlier byte was wrong, and the attack redoes everything from the first
+/// byte it wasn't sure of.
+pub fn break_hmac_timing(
+    oracle: &impl SignatureOracle,
+    file: &str,
+    config: &TimingConfig,
+) -> Result<TimingReport> {
+    let start = Instant::now();
+    let mut bytes: Vec<ByteVerdict> = vec![];
+    let mut queries = 0;
+    let mut backtracks = 0;
+    loop {
+        while bytes.len() < SIGNATURE_LEN - 1 {
+            let known = bytes.iter().map(|b| b.byte).collect::<Vec<_>>();
+            let verdict = recover_byte(oracle, file, &known, config)?;
+            queries += verdict.queries;
+            bytes.push(verdict);
+        }
+
+        let known = bytes.iter().map(|b| b.byte).collect::<Vec<_>>();
+        for (tried, candidate) in (0..=255).enumerate() {
+            let (valid, _) = oracle.check_signature(file, &guess(&known, candidate))?;
+            if valid {
+                queries += tried + 1;
+                bytes.push(ByteVerdict {
+                    byte: candidate,
+                    strategy: Strategy::Exhaustive,
+                    queries: tried + 1,
+                    confidence: f64::INFINITY,
+                });
+                return Ok(TimingReport {
+                    signature: guess(&known, candidate),
+                    bytes,
+                    queries,
+                    backtracks,
+                    elapsed: start.elapsed(),
+                });
+            }
+        }
+        queries += 256;
+
+        if backtracks == config.max_backtracks {
+            bail!("no signature accepted after {backtracks} backtracks");
+        }
+        backtracks += 1;
+        // past a wrong byte nothing stands out, so the first byte that was
+        // settled for without convincing is the likeliest culprit
+        let convincing = |b: &ByteVerdict| match b.strategy {
+            Strategy::Averaging => b.confidence >= config.clear_margin,
+            _ => b.confidence >= config.t_threshold,
+        };
+        let weakest = bytes
+            .iter()
+            .position(|b| !convincing(b))
+            .unwrap_or_else(|| {
+                (0..bytes.len())
+                    .min_by(|&a, &b| bytes[a].confidence.total_cmp(&bytes[b].confidence))
+                    .unwrap_or(0)
+            });
+        bytes.truncate(weakest);
+    }
+}
+
+/// Recovers the signature byte after `known` from timings alone
+pub fn recover_byte(
+    oracle: &impl SignatureOracle,
+    file: &str,
+    known: &[u8],
+    config: &TimingConfig,
+) -> Result<ByteVerdict> {
+    let time = |candidate: u8| -> Result<f64> {
+        let (_, elapsed) = oracle.check_signature(file, &guess(known, candidate))?;
+        Ok(elapsed.as_secs_f64())
+    };
+    let mut samples = vec![vec![]; 256];
+    let sample_all = |samples: &mut Vec<Vec<f64>>, passes| -> Result<()> {
+        for _ in 0..passes {
+            for (candidate, times) in samples.iter_mut().enumerate() {
+                times.push(time(candidate as u8)?);
+            }
+        }
+        Ok(())
+    };
+
+    sample_all(&mut samples, config.initial_passes.max(1))?;
+    let means = samples.iter().map(|s| mean(s)).collect::<Vec<_>>();
+    let ranked = rank(&means);
+    let (leader, runner_up) = (ranked[0], ranked[1]);
+    let spread = robust_spread(&means);
+    let margin = |samples: &[f64]| {
+        let fastest = samples.iter().copied().fold(f64::INFINITY, f64::min);
+        in_units(fastest - means[runner_up], spread)
+    };
+    // a candidate that stalled on every pass looks just as clear, so the
+    // leader has to be slow once more before it is believed
+    if margin(&samples[leader]) >= config.clear_margin {
+        samples[leader].push(time(leader as u8)?);
+    }
+    let margin = margin(&samples[leader]);
+    if margin >= config.clear_margin {
+        return Ok(ByteVerdict {
+            byte: leader as u8,
+            strategy: Strategy::Averaging,
+            queries: samples.iter().map(Vec::len).sum(),
+            confidence: margin,
+        });
+    }
+
+    sample_all(&mut samples, config.screening_passes)?;
+    let screened = samples
+        .iter()
+        .map(|s| mean(&trimmed(s, config.trim)))
+        .collect::<Vec<_>>();
+    let mut contenders = rank(&screened);
+    contenders.truncate(config.contenders.max(2));
+
+    let mut rounds = 0;
+    loop {
+        let trimmed = |c: usize| trimmed(&samples[c], config.trim);
+        let means = (0..256)
+            .map(|c| match contenders.contains(&c) {
+                true => mean(&trimmed(c)),
+                false => f64::NEG_INFINITY,
+            })
+            .collect::<Vec<_>>();
+        let leader = rank(&means)[0];
+        let ts = contenders
+            .iter()
+            .filter(|&&c| c != leader)
+            .map(|&c| (c, welch_t(&trimmed(leader), &trimmed(c))))
+            .collect::<Vec<_>>();
+        let confidence = ts.iter().map(|&(_, t)| t).fold(f64::INFINITY, f64::min);
+        if confidence >= config.t_threshold || rounds == config.max_rounds {
+            return Ok(ByteVerdict {
+                byte: leader as u8,
+                strategy: Strategy::Statistical,
+                queries: samples.iter().map(Vec::len).sum(),
+                confidence,
+            });
+        }
+
+        // anything already clearly faster than the leader is out
+        contenders
+            .retain(|&c| c == leader || ts.iter().any(|&(d, t)| d == c && t < config.t_threshold));
+        for &candidate in &contenders {
+            samples[candidate].push(time(candidate as u8)?);
+        }
+        rounds += 1;
+    }
+}
+
+// `known`, then `candidate`, then zeros up to the full length
+fn guess(known: &[u8], candidate: u8) -> Vec<u8> {
+    let mut signature = known.to_vec();
+    signature.push(candidate);
+    signature.resize(SIGNATURE_LEN, 0);
+    signature
+}
+
+// Indices by value, largest first
+fn rank(values: &[f64]) -> Vec<usize> {
+    let mut order = (0..values.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| values[b].total_cmp(&values[a]));
+    order
+}
+
+fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+fn median(values: &[f64]) -> f64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    match sorted.len() % 2 {
+        0 => (sorted[mid - 1] + sorted[mid]) / 2.0,
+        _ => sorted[mid],
+    }
+}
+
+// The median absolute deviation, scaled to match the standard deviation of
+// normally distributed values
+fn robust_spread(values: &[f64]) -> f64 {
+    let centre = median(values);
+    let deviations = values
+        .iter()
+        .map(|v| (v - centre).abs())
+        .collect::<Vec<_>>();
+    1.4826 * median(&deviations)
+}
+
+// `gap` in units of `spread`, when there is no spread only its sign counts
+fn in_units(gap: f64, spread: f64) -> f64 {
+    match spread > 0.0 {
+        true => gap / spread,
+        false if gap > 0.0 => f64::INFINITY,
+        false => 0.0,
+    }
+}
+
+// The samples left after dropping `trim` of them (rounded up) from each end,
+// always keeping at least one
+fn trimmed(values: &[f64], trim: f64) -> Vec<f64> {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let drop = ((sorted.len() as f64 * trim).ceil() as usize).min((sorted.len() - 1) / 2);
+    sorted[drop..sorted.len() - drop].to_vec()
+}
+
+fn variance(values: &[f64]) -> f64 {
+    if values.len() < 2 {
+        return 0.0;
+    }
+    let centre = mean(values);
+    values.iter().map(|v| (v - centre).powi(2)).sum::<f64>() / (values.len() - 1) as f64
+}
+
+// How far `a`'s mean is above `b`'s, in standard errors of the difference
+fn welch_t(a: &[f64], b: &[f64]) -> f64 {
+    let error = (variance(a) / a.len() as f64 + variance(b) / b.len() as f64).sqrt();
+    in_units(mean(a) - mean(b), error)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, sync::Arc, time::Duration};
+
+    use rand::{rngs::StdRng, Rng};
+
+    use crate::{
+        http::{client::HttpOracle, server::Server},
+        mac::timing::{
+            break_hmac_timing, insecure_compare, recover_byte, SignatureService, Strategy,
+            TimingConfig, SIGNATURE_LEN,
+        },
+        oracle::{
+            metered::{measure, Budget, BudgetExceeded},
+            SignatureOracle, Target,
+        },
+        utils::random::test_rng,
+    };
+
+    // The same leak as the service, in virtual time: a fixed cost, `delay` per
+    // matching byte, jitter, and now and then a stall
+    struct Simulated {
+        signature: Vec<u8>,
+        delay: f64,
+        jitter: f64,
+        rng: RefCell<StdRng>,
+    }
+
+    impl Target for Simulated {}
+
+    impl SignatureOracle for Simulated {
+        fn check_signature(
+            &self,
+            _file: &str,
+            signature: &[u8],
+        ) -> anyhow::Result<(bool, Duration)> {
+            let matching = self
+                .signature
+                .iter()
+                .zip(signature)
+                .take_while(|(a, b)| a == b)
+                .count();
+            let mut rng = self.rng.borrow_mut();
+            // roughly normal
+            let noise = (0..12).map(|_| rng.gen::<f64>()).sum::<f64>() - 6.0;
+            let stall = match rng.gen_bool(0.01) {
+                true => rng.gen_range(0.0..500e-6),
+                false => 0.0,
+            };
+            let time = 100e-6 + matching as f64 * self.delay + noise * self.jitter + stall;
+            Ok((
+                matching == self.signature.len(),
+                Duration::from_secs_f64(time.max(0.0)),
+            ))
+        }
+    }
+
+    fn simulated(delay: f64) -> Simulated {
+        let mut rng = test_rng();
+        Simulated {
+            signature: (0..SIGNATURE_LEN).map(|_| rng.gen()).collect(),
+            delay,
+            jitter: 10e-6,
+            rng: RefCell::new(rng),
+        }
+    }
+
+    #[test]
+    fn test_insecure_compare() {
+        let service = SignatureService::with_rng(Duration::ZERO, &mut test_rng());
+        let signature = service.sign("foo").unwrap();
+        assert_eq!(signature.len(), SIGNATURE_LEN);
+        assert!(service.check("foo", &signature).unwrap());
+        assert!(!service.check("bar", &signature).unwrap());
+        assert!(!service.check("foo", &signature[1..]).unwrap());
+        assert!(insecure_compare(b"", b"", Duration::ZERO));
+        assert!(!insecure_compare(b"ab", b"ac", Duration::ZERO));
+    }
+
+    #[test]
+    fn test_large_delay_averages() {
+        let oracle = simulated(1e-3);
+        let report = break_hmac_timing(&oracle, "foo", &TimingConfig::default()).unwrap();
+        assert_eq!(report.signature, oracle.signature);
+        assert!(report.bytes[..SIGNATURE_LEN - 1]
+            .iter()
+            .all(|b| b.strategy == Strategy::Averaging));
+        assert_eq!(
+            report.bytes[SIGNATURE_LEN - 1].strategy,
+            Strategy::Exhaustive
+        );
+        assert_eq!(report.backtracks, 0);
+        assert!(report.queries <= 256 * 2 * SIGNATURE_LEN);
+    }
+
+    // at twice the jitter a pair of samples can't be trusted, but trimmed
+    // means and t-tests still get there
+    #[test]
+    fn test_small_delay_samples_repeatedly() {
+        let oracle = simulated(20e-6);
+        let (result, report) = measure("hmac timing", &oracle, Budget::unlimited(), |o| {
+            break_hmac_timing(o, "foo", &TimingConfig::default())
+        });
+        let result = result.unwrap();
+        assert_eq!(result.signature, oracle.signature);
+        assert!(result
+            .bytes
+            .iter()
+            .any(|b| b.strategy == Strategy::Statistical));
+        assert_eq!(report.stats.queries, result.queries);
+        assert!(result.to_string().contains(" statistically"));
+
+        // and a budget cuts it short
+        let (result, report) = measure("hmac timing", &oracle, Budget::queries(1000), |o| {
+            break_hmac_timing(o, "foo", &TimingConfig::default())
+        });
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BudgetExceeded>(),
+            Some(&BudgetExceeded::Queries(1000))
+        );
+        assert_eq!(report.stats.queries, 1000);
+    }
+
+    // the real thing over loopback, for the first two bytes only since every
+    // byte costs a delay per query more than the last
+    #[test]
+    fn test_timing_over_http() {
+        let service = Arc::new(SignatureService::with_rng(
+            Duration::from_millis(3),
+            &mut test_rng(),
+        ));
+        let server = Server::builder()
+            .signature("", service.clone())
+            .start()
+            .unwrap();
+        let oracle = HttpOracle::new(server.addr(), "");
+        let expected = service.sign("foo").unwrap();
+        assert!(oracle.check_signature("foo", &expected).unwrap().0);
+
+        let config = TimingConfig::default();
+        let mut known = vec![];
+        for _ in 0..2 {
+            known.push(recover_byte(&oracle, "foo", &known, &config).unwrap().byte);
+        }
+        assert_eq!(known, expected[..2]);
+    }
+}
@@ -57,7 +57,7 @@ impl Mt19937 {
             let next_idx = ((i + 1) % N) as usize;
             let x = (self.mt[idx] & UPPER_MASK) | (self.mt[next_idx] & LOWER_MASK);
             let mut x_a = x >> 1;
-            if (x % 2) != 0 {
+            if !x.is_multiple_of(2) {
                 x_a ^= A;
             }
             let twist_idx = ((i + M) % N) as usize;
@@ -91,7 +91,7 @@ pub fn random_mt19937() -> anyhow::Result<Mt19937> {
     let duration = time::Duration::from_millis(rng.gen_range(0..=100));
     thread::sleep(duration);
 
-    Ok(Mt19937::new(Some(seed as u32)))
+    Ok(Mt19937::new(Some(seed)))
 }
 
 pub fn crack_random_mt19937(rnd: &mut Mt19937) -> anyhow::Result<u32> {
@@ -0,0 +1,341 @@
+use anyhow::{ensure, Result};
+
+use crate::{
+    cipher::BlockCipher,
+    ctr::{CounterLayout, Ctr},
+    padding::{PadPkcs7, UnpadPkcs7},
+    xor::Xor,
+};
+
+fn ensure_block_multiple(cipher: &impl BlockCipher, bytes: &[u8]) -> Result<()> {
+    let block_size = cipher.block_size();
+    ensure!(
+        bytes.len().is_multiple_of(block_size),
+        "expected multiple of {block_size}, got {}",
+        bytes.len()
+    );
+    Ok(())
+}
+
+pub(crate) fn ensure_iv_len(cipher: &impl BlockCipher, iv: &[u8]) -> Result<()> {
+    ensure!(
+        iv.len() == cipher.block_size(),
+        "expected iv of len {}, got {}",
+        cipher.block_size(),
+        iv.len()
+    );
+    Ok(())
+}
+
+pub fn encrypt_ecb(cipher: &impl BlockCipher, bytes: &[u8]) -> Result<Vec<u8>> {
+    let block_size = cipher.block_size();
+    let bytes = bytes.pad_pkcs7_blocksize(block_size);
+    let mut encrypted = vec![];
+    for block in bytes.chunks_exact(block_size) {
+        encrypted.extend(cipher.encrypt_block(block)?);
+    }
+    Ok(encrypted)
+}
+
+pub fn decrypt_ecb(cipher: &impl BlockCipher, bytes: &[u8]) -> Result<Vec<u8>> {
+    ensure_block_multiple(cipher, bytes)?;
+    let mut decrypted = vec![];
+    for block in bytes.chunks_exact(cipher.block_size()) {
+        decrypted.extend(cipher.decrypt_block(block)?);
+    }
+    decrypted.validate_unpad_pkcs7_blocksize(cipher.block_size())
+}
+
+pub fn encrypt_cbc(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let block_size = cipher.block_size();
+    let bytes = bytes.pad_pkcs7_blocksize(block_size);
+    let mut iv = iv.to_vec();
+    let mut encrypted = vec![];
+    for block in bytes.chunks_exact(block_size) {
+        let encrypted_block = cipher.encrypt_block(&block.xor(&iv))?;
+        encrypted.extend(&encrypted_block);
+        iv = encrypted_block;
+    }
+
+    Ok(encrypted)
+}
+
+pub fn decrypt_cbc(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    ensure_block_multiple(cipher, bytes)?;
+    let mut iv = iv;
+    let mut decrypted = vec![];
+    for block in bytes.chunks_exact(cipher.block_size()) {
+        decrypted.extend(cipher.decrypt_block(block)?.xor(iv));
+        iv = block;
+    }
+
+    decrypted.validate_unpad_pkcs7_blocksize(cipher.block_size())
+}
+
+/// CTR keystream applied to `bytes`. The counter block is the little-endian nonce
+/// followed by the little-endian block counter, each taking half of the block.
+/// See `ctr::Ctr` for other layouts and random access.
+pub fn ctr(cipher: &impl BlockCipher, bytes: &[u8], nonce: u64) -> Result<Vec<u8>> {
+    Ctr::new(cipher, CounterLayout::LittleEndian64 { nonce })?.apply(bytes)
+}
+
+/// Full-block CFB: each plaintext block is xored with the encryption of the
+/// previous ciphertext block (the IV for the first). No padding is needed.
+pub fn encrypt_cfb(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let mut prev = iv.to_vec();
+    let mut encrypted = vec![];
+    for block in bytes.chunks(cipher.block_size()) {
+        let encrypted_block = block.xor(&cipher.encrypt_block(&prev)?);
+        encrypted.extend(&encrypted_block);
+        prev = encrypted_block;
+    }
+    Ok(encrypted)
+}
+
+pub fn decrypt_cfb(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let mut prev = iv;
+    let mut decrypted = vec![];
+    for block in bytes.chunks(cipher.block_size()) {
+        decrypted.extend(block.xor(&cipher.encrypt_block(prev)?));
+        prev = block;
+    }
+    Ok(decrypted)
+}
+
+/// CFB with an 8-bit segment: one block encryption per byte, shifting each
+/// ciphertext byte into the register.
+///
+/// With an all-zero IV and all-zero plaintext, the output is all zeroes whenever
+/// the first byte of E(0) is zero, which happens for 1 in 256 keys. This is the
+/// weakness behind Zerologon.
+pub fn encrypt_cfb8(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let mut register = iv.to_vec();
+    let mut encrypted = vec![];
+    for &byte in bytes {
+        let encrypted_byte = byte ^ cipher.encrypt_block(&register)?[0];
+        register.remove(0);
+        register.push(encrypted_byte);
+        encrypted.push(encrypted_byte);
+    }
+    Ok(encrypted)
+}
+
+pub fn decrypt_cfb8(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let mut register = iv.to_vec();
+    let mut decrypted = vec![];
+    for &byte in bytes {
+        decrypted.push(byte ^ cipher.encrypt_block(&register)?[0]);
+        register.remove(0);
+        register.push(byte);
+    }
+    Ok(decrypted)
+}
+
+/// OFB keystream applied to `bytes`: the IV is repeatedly encrypted, independent
+/// of the data, so encryption and decryption are the same operation.
+pub fn ofb(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let mut keystream = iv.to_vec();
+    let mut out = vec![];
+    for block in bytes.chunks(cipher.block_size()) {
+        keystream = cipher.encrypt_block(&keystream)?;
+        out.extend(block.xor(&keystream));
+    }
+    Ok(out)
+}
+
+/// Propagating CBC: the value chained into the next block is the xor of the
+/// previous plaintext and ciphertext blocks, so a corrupted ciphertext block
+/// garbles every plaintext block after it.
+pub fn encrypt_pcbc(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    let block_size = cipher.block_size();
+    let bytes = bytes.pad_pkcs7_blocksize(block_size);
+    let mut chain = iv.to_vec();
+    let mut encrypted = vec![];
+    for block in bytes.chunks_exact(block_size) {
+        let encrypted_block = cipher.encrypt_block(&block.xor(&chain))?;
+        chain = block.xor(&encrypted_block);
+        encrypted.extend(encrypted_block);
+    }
+    Ok(encrypted)
+}
+
+pub fn decrypt_pcbc(cipher: &impl BlockCipher, bytes: &[u8], iv: &[u8]) -> Result<Vec<u8>> {
+    ensure_iv_len(cipher, iv)?;
+    ensure_block_multiple(cipher, bytes)?;
+    let mut chain = iv.to_vec();
+    let mut decrypted = vec![];
+    for block in bytes.chunks_exact(cipher.block_size()) {
+        let decrypted_block = cipher.decrypt_block(block)?.xor(&chain);
+        chain = decrypted_block.xor(block);
+        decrypted.extend(decrypted_block);
+    }
+    decrypted.validate_unpad_pkcs7_blocksize(cipher.block_size())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use crate::{
+        aes::AesKey,
+        cipher::{BlockCipher, ToyCipher},
+        hex::{DecodeHex, EncodeHex},
+        modes::{
+            ctr, decrypt_cbc, decrypt_cfb, decrypt_cfb8, decrypt_ecb, decrypt_pcbc, encrypt_cbc,
+            encrypt_cfb, encrypt_cfb8, encrypt_ecb, encrypt_pcbc, ofb,
+        },
+        utils::bytes,
+        xor::Xor,
+    };
+
+    // NIST SP 800-38A Appendix F
+    const SP800_38A_PLAINTEXT: &str = "6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e5130c81c46a35ce411e5fbc1191a0a52eff69f2445df4f9b17ad2b417be66c3710";
+    const SP800_38A_IV: &str = "000102030405060708090a0b0c0d0e0f";
+    const SP800_38A_KEY_128: &str = "2b7e151628aed2a6abf7158809cf4f3c";
+    const SP800_38A_KEY_256: &str =
+        "603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4";
+
+    fn roundtrip(cipher: &impl BlockCipher) {
+        let mut rng = rand::thread_rng();
+        let block_size = cipher.block_size();
+        for _ in 0..20 {
+            let len = rng.gen_range(0..=50);
+            let plaintext = bytes::rand_of_len(len);
+            let iv = bytes::rand_of_len(block_size);
+
+            let encrypted = encrypt_ecb(cipher, &plaintext).unwrap();
+            assert_eq!(encrypted.len() % block_size, 0);
+            assert_eq!(decrypt_ecb(cipher, &encrypted).unwrap(), plaintext);
+
+            let encrypted = encrypt_cbc(cipher, &plaintext, &iv).unwrap();
+            assert_eq!(decrypt_cbc(cipher, &encrypted, &iv).unwrap(), plaintext);
+
+            let encrypted = ctr(cipher, &plaintext, 7).unwrap();
+            assert_eq!(encrypted.len(), plaintext.len());
+            assert_eq!(ctr(cipher, &encrypted, 7).unwrap(), plaintext);
+
+            let encrypted = encrypt_cfb(cipher, &plaintext, &iv).unwrap();
+            assert_eq!(encrypted.len(), plaintext.len());
+            assert_eq!(decrypt_cfb(cipher, &encrypted, &iv).unwrap(), plaintext);
+
+            let encrypted = encrypt_cfb8(cipher, &plaintext, &iv).unwrap();
+            assert_eq!(decrypt_cfb8(cipher, &encrypted, &iv).unwrap(), plaintext);
+
+            let encrypted = ofb(cipher, &plaintext, &iv).unwrap();
+            assert_eq!(ofb(cipher, &encrypted, &iv).unwrap(), plaintext);
+
+            let encrypted = encrypt_pcbc(cipher, &plaintext, &iv).unwrap();
+            assert_eq!(decrypt_pcbc(cipher, &encrypted, &iv).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_modes_roundtrip() {
+        roundtrip(&AesKey::new(&bytes::rand_of_len(16)).unwrap());
+        roundtrip(&AesKey::new(&bytes::rand_of_len(32)).unwrap());
+        roundtrip(&ToyCipher::new(&bytes::rand_of_len(8)).unwrap());
+    }
+
+    #[test]
+    fn test_mode_errors() {
+        let cipher = ToyCipher::new(&bytes::rand_of_len(8)).unwrap();
+        assert!(encrypt_cbc(&cipher, b"data", &[0; 16]).is_err());
+        assert!(decrypt_cbc(&cipher, &[0; 12], &[0; 8]).is_err());
+        assert!(decrypt_ecb(&cipher, &[0; 12]).is_err());
+        // an 8-byte block leaves only 4 bytes for the nonce
+        assert!(ctr(&cipher, b"data", u64::MAX).is_err());
+    }
+
+    #[test]
+    fn test_cfb_ofb_known_answers() {
+        let plaintext = SP800_38A_PLAINTEXT.decode_hex();
+        let iv = SP800_38A_IV.decode_hex();
+        let vectors = [
+            (SP800_38A_KEY_128, "3b3fd92eb72dad20333449f8e83cfb4ac8a64537a0b3a93fcde3cdad9f1ce58b26751f67a3cbb140b1808cf187a4f4dfc04b05357c5d1c0eeac4c66f9ff7f2e6", "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed8259740051e9c5fecf64344f7a82260edcc304c6528f659c77866a510d9c1d6ae5e"),
+            (SP800_38A_KEY_256, "dc7e84bfda79164b7ecd8486985d386039ffed143b28b1c832113c6331e5407bdf10132415e54b92a13ed0a8267ae2f975a385741ab9cef82031623d55b1e471", "dc7e84bfda79164b7ecd8486985d38604febdc6740d20b3ac88f6ad82a4fb08d71ab47a086e86eedf39d1c5bba97c4080126141d67f37be8538f5a8be740e484"),
+        ];
+        for (key, cfb, ofb_expected) in vectors {
+            let cipher = AesKey::new(&key.decode_hex()).unwrap();
+
+            let encrypted = encrypt_cfb(&cipher, &plaintext, &iv).unwrap();
+            assert_eq!(encrypted.to_hex(), cfb);
+            assert_eq!(decrypt_cfb(&cipher, &encrypted, &iv).unwrap(), plaintext);
+
+            let encrypted = ofb(&cipher, &plaintext, &iv).unwrap();
+            assert_eq!(encrypted.to_hex(), ofb_expected);
+            assert_eq!(ofb(&cipher, &encrypted, &iv).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_cfb8_known_answers() {
+        let plaintext = &SP800_38A_PLAINTEXT.decode_hex()[..18];
+        let iv = SP800_38A_IV.decode_hex();
+        let vectors = [
+            (SP800_38A_KEY_128, "3b79424c9c0dd436bace9e0ed4586a4f32b9"),
+            (SP800_38A_KEY_256, "dc1f1a8520a64db55fcc8ac554844e889700"),
+        ];
+        for (key, expected) in vectors {
+            let cipher = AesKey::new(&key.decode_hex()).unwrap();
+            let encrypted = encrypt_cfb8(&cipher, plaintext, &iv).unwrap();
+            assert_eq!(encrypted.to_hex(), expected);
+            assert_eq!(decrypt_cfb8(&cipher, &encrypted, &iv).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_cfb8_zero_iv() {
+        // Find a key where E(0) starts with a zero byte; it takes ~256 tries
+        let cipher = loop {
+            let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+            if cipher.encrypt_block(&[0; 16]).unwrap()[0] == 0 {
+                break cipher;
+            }
+        };
+        // ...then every all-zero message encrypts to all zeroes
+        for len in [1, 8, 16, 100] {
+            let encrypted = encrypt_cfb8(&cipher, &vec![0; len], &[0; 16]).unwrap();
+            assert_eq!(encrypted, vec![0; len]);
+        }
+    }
+
+    #[test]
+    fn test_pcbc_error_propagation() {
+        let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+        let iv = bytes::rand_of_len(16);
+        let plaintext = bytes::rand_of_len(16 * 4);
+
+        let mut encrypted = encrypt_pcbc(&cipher, &plaintext, &iv).unwrap();
+        encrypted[16] ^= 1;
+        // the final padding block is garbled too, so decrypt without validating it
+        let mut chain = iv.clone();
+        let mut decrypted = vec![];
+        for block in encrypted.chunks_exact(16) {
+            let decrypted_block = cipher.decrypt_block(block).unwrap().xor(&chain);
+            chain = decrypted_block.xor(block);
+            decrypted.extend(decrypted_block);
+        }
+        assert_eq!(decrypted[..16], plaintext[..16]);
+        for idx in 1..4 {
+            let range = idx * 16..(idx + 1) * 16;
+            assert_ne!(decrypted[range.clone()], plaintext[range]);
+        }
+
+        // unlike CBC, swapping two ciphertext blocks only affects those blocks
+        let mut encrypted = encrypt_pcbc(&cipher, &plaintext, &iv).unwrap();
+        let (first, second) = encrypted.split_at_mut(32);
+        first[16..].swap_with_slice(&mut second[..16]);
+        assert_eq!(
+            decrypt_pcbc(&cipher, &encrypted, &iv).unwrap()[48..],
+            plaintext[48..]
+        );
+    }
+}
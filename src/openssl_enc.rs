@@ -0,0 +1,311 @@
+// The file format written by `openssl enc -aes-*-cbc` with a password, and a
+// dictionary attack on it.
+// References: openssl-enc(1), EVP_BytesToKey(3), RFC 8018 for PBKDF2
+//
+// The file is "Salted__" || salt (8) || AES-CBC ciphertext, with the key and
+// IV both derived from the password and salt. Nothing else is stored, so the
+// reader has to know the cipher and the key derivation that were used.
+use std::{fs, path::Path};
+
+use anyhow::{ensure, Result};
+use openssl::{
+    hash::{hash, MessageDigest},
+    pkcs5::pbkdf2_hmac,
+};
+
+use crate::{
+    aes::{decrypt_aes_cbc, encrypt_aes_cbc, KeySize},
+    frequency::score,
+    utils::{bytes, random::RandomSource},
+};
+
+pub const MAGIC: &[u8; 8] = b"Salted__";
+pub const SALT_LEN: usize = 8;
+const IV_LEN: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Digest {
+    Md5,
+    Sha256,
+}
+
+impl Digest {
+    fn message_digest(&self) -> MessageDigest {
+        match self {
+            Digest::Md5 => MessageDigest::md5(),
+            Digest::Sha256 => MessageDigest::sha256(),
+        }
+    }
+}
+
+/// How the key and IV are derived from the password
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDerivation {
+    /// `EVP_BytesToKey` with one iteration. SHA-256 is the default since
+    /// OpenSSL 1.1.0 and MD5 was the default before (`-md md5`).
+    BytesToKey(Digest),
+    /// PBKDF2-HMAC-SHA256 (`-pbkdf2`, with `-iter` iterations)
+    Pbkdf2 { iterations: usize },
+}
+
+impl Default for KeyDerivation {
+    fn default() -> Self {
+        KeyDerivation::BytesToKey(Digest::Sha256)
+    }
+}
+
+impl KeyDerivation {
+    /// The iteration count `openssl enc -pbkdf2` uses without `-iter`
+    pub const PBKDF2_DEFAULT_ITERATIONS: usize = 10000;
+
+    /// Returns (key, IV)
+    pub fn derive(
+        &self,
+        password: &[u8],
+        salt: &[u8],
+        key_size: KeySize,
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let key_len = key_size.key_len();
+        let mut derived = vec![];
+        match self {
+            KeyDerivation::BytesToKey(digest) => {
+                // D_i = H(D_{i-1} || password || salt)
+                let mut block: Vec<u8> = vec![];
+                while derived.len() < key_len + IV_LEN {
+                    block =
+                        hash(digest.message_digest(), &[&block, password, salt].concat())?.to_vec();
+                    derived.extend(&block);
+                }
+            }
+            KeyDerivation::Pbkdf2 { iterations } => {
+                derived.resize(key_len + IV_LEN, 0);
+                pbkdf2_hmac(
+                    password,
+                    salt,
+                    *iterations,
+                    MessageDigest::sha256(),
+                    &mut derived,
+                )?;
+            }
+        }
+        let iv = derived[key_len..key_len + IV_LEN].to_vec();
+        derived.truncate(key_len);
+        Ok((derived, iv))
+    }
+}
+
+/// Splits a file into its salt and ciphertext
+pub fn parse(data: &[u8]) -> Result<(&[u8], &[u8])> {
+    ensure!(
+        data.len() >= MAGIC.len() + SALT_LEN && data.starts_with(MAGIC),
+        "missing the Salted__ header"
+    );
+    Ok(data[MAGIC.len()..].split_at(SALT_LEN))
+}
+
+pub fn encrypt_with_salt(
+    plaintext: &[u8],
+    password: &[u8],
+    salt: &[u8],
+    kdf: KeyDerivation,
+    key_size: KeySize,
+) -> Result<Vec<u8>> {
+    ensure!(
+        salt.len() == SALT_LEN,
+        "expected {SALT_LEN} byte salt, got {}",
+        salt.len()
+    );
+    let (key, iv) = kdf.derive(password, salt, key_size)?;
+    let mut out = MAGIC.to_vec();
+    out.extend(salt);
+    out.extend(encrypt_aes_cbc(plaintext, &iv, &key)?);
+    Ok(out)
+}
+
+/// Encrypts under a random salt, like `openssl enc -e`
+pub fn encrypt(
+    plaintext: &[u8],
+    password: &[u8],
+    kdf: KeyDerivation,
+    key_size: KeySize,
+) -> Result<Vec<u8>> {
+    encrypt_with_rng(plaintext, password, kdf, key_size, &mut rand::thread_rng())
+}
+
+/// `encrypt`, drawing the salt from `rng`
+pub fn encrypt_with_rng(
+    plaintext: &[u8],
+    password: &[u8],
+    kdf: KeyDerivation,
+    key_size: KeySize,
+    rng: &mut impl RandomSource,
+) -> Result<Vec<u8>> {
+    let salt = bytes::rand_of_len_from(rng, SALT_LEN);
+    encrypt_with_salt(plaintext, password, &salt, kdf, key_size)
+}
+
+pub fn decrypt(
+    data: &[u8],
+    password: &[u8],
+    kdf: KeyDerivation,
+    key_size: KeySize,
+) -> Result<Vec<u8>> {
+    let (salt, ciphertext) = parse(data)?;
+    let (key, iv) = kdf.derive(password, salt, key_size)?;
+    decrypt_aes_cbc(ciphertext, &iv, &key)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cracked {
+    pub password: String,
+    pub plaintext: Vec<u8>,
+}
+
+/// Tries each password in turn. About 1 in 256 wrong passwords still gives
+/// valid padding, so the candidates that decrypt are ranked by how much they
+/// look like English text and the best is returned.
+pub fn dictionary_attack<S: AsRef<str>>(
+    data: &[u8],
+    passwords: impl IntoIterator<Item = S>,
+    kdf: KeyDerivation,
+    key_size: KeySize,
+) -> Result<Option<Cracked>> {
+    parse(data)?;
+    let best = passwords
+        .into_iter()
+        .filter_map(|password| {
+            let password = password.as_ref();
+            let plaintext = decrypt(data, password.as_bytes(), kdf, key_size).ok()?;
+            let score = score(&plaintext);
+            (score < u32::MAX).then(|| {
+                let cracked = Cracked {
+                    password: password.to_string(),
+                    plaintext,
+                };
+                (score, cracked)
+            })
+        })
+        .min_by_key(|(score, _)| *score);
+    Ok(best.map(|(_, cracked)| cracked))
+}
+
+/// `dictionary_attack` with a wordlist file of one password per line
+pub fn dictionary_attack_file(
+    data: &[u8],
+    wordlist: impl AsRef<Path>,
+    kdf: KeyDerivation,
+    key_size: KeySize,
+) -> Result<Option<Cracked>> {
+    let words = fs::read_to_string(wordlist)?;
+    dictionary_attack(data, words.lines(), kdf, key_size)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::KeySize,
+        hex::{DecodeHex, EncodeHex},
+        openssl_enc::{
+            decrypt, dictionary_attack, dictionary_attack_file, encrypt_with_rng,
+            encrypt_with_salt, parse, Digest, KeyDerivation,
+        },
+        utils::random::test_rng,
+    };
+
+    const WORDLIST: &str = include_str!("files/passwords.txt");
+
+    #[test]
+    fn test_key_derivation() {
+        // from `openssl enc -P -S 0102030405060708`
+        let salt = "0102030405060708".decode_hex();
+        let (key, iv) = KeyDerivation::BytesToKey(Digest::Md5)
+            .derive(b"hunter2", &salt, KeySize::Aes128)
+            .unwrap();
+        assert_eq!(key.to_hex(), "dd076b4bcd49c33676d8185c3dd67e93");
+        assert_eq!(iv.to_hex(), "5d3b7324ff7d8e1074d9734059f0971e");
+
+        let pbkdf2 = KeyDerivation::Pbkdf2 {
+            iterations: KeyDerivation::PBKDF2_DEFAULT_ITERATIONS,
+        };
+        let (key, iv) = pbkdf2.derive(b"x", &salt, KeySize::Aes128).unwrap();
+        assert_eq!(key.to_hex(), "5c2bdde12f9952b39c017d85cc3f009a");
+        assert_eq!(iv.to_hex(), "0d1477aee22c8e2d8e8f5dc54c2501f2");
+    }
+
+    #[test]
+    fn test_reads_openssl_files() {
+        // `openssl enc <options> -pass pass:hunter2` of "Rollin in my 5.0\n"
+        let files = [
+            (
+                "53616c7465645f5f17cf7b86c9e861074d10913a931f6afc201d116ad16d789aff21001c3491b04b259859c9e20e9d0f",
+                KeyDerivation::BytesToKey(Digest::Md5),
+                KeySize::Aes128,
+            ),
+            (
+                "53616c7465645f5fb237d29c3cb0642dcfd6059b93e4019f9737fbe20036f2b4b95ea6df8f7682152389ab341d16533a",
+                KeyDerivation::default(),
+                KeySize::Aes128,
+            ),
+            (
+                "53616c7465645f5f4f43d3fead53aaeede696a009481012a5d16405dfabe49c728f9e9d143ec5162bdcc5462fb6e3ff7",
+                KeyDerivation::Pbkdf2 { iterations: 10000 },
+                KeySize::Aes256,
+            ),
+            (
+                "53616c7465645f5f85a062fd98334d8b6040779571b4d8f047384204400d96c85920ee144d8d740f20fc1188a9c229cf",
+                KeyDerivation::Pbkdf2 { iterations: 1000 },
+                KeySize::Aes192,
+            ),
+        ];
+        for (file, kdf, key_size) in files {
+            let data = file.decode_hex();
+            let plaintext = decrypt(&data, b"hunter2", kdf, key_size).unwrap();
+            assert_eq!(plaintext, b"Rollin in my 5.0\n");
+            // writing with the same salt gives back the same file
+            let (salt, _) = parse(&data).unwrap();
+            let written = encrypt_with_salt(&plaintext, b"hunter2", salt, kdf, key_size).unwrap();
+            assert_eq!(written, data);
+        }
+        assert!(parse(b"Salted_").is_err());
+        assert!(parse(b"NotSalted_12345678").is_err());
+    }
+
+    #[test]
+    fn test_dictionary_attack() {
+        let mut rng = test_rng();
+        let plaintext = b"Meet me at the usual place at ten o'clock.";
+        for kdf in [
+            KeyDerivation::BytesToKey(Digest::Md5),
+            KeyDerivation::Pbkdf2 { iterations: 100 },
+        ] {
+            let data =
+                encrypt_with_rng(plaintext, b"sunshine", kdf, KeySize::Aes128, &mut rng).unwrap();
+            let cracked = dictionary_attack(&data, WORDLIST.lines(), kdf, KeySize::Aes128)
+                .unwrap()
+                .unwrap();
+            assert_eq!(cracked.password, "sunshine");
+            assert_eq!(cracked.plaintext, plaintext);
+        }
+
+        let kdf = KeyDerivation::default();
+        let data = encrypt_with_rng(
+            plaintext,
+            b"correct horse battery staple",
+            kdf,
+            KeySize::Aes128,
+            &mut rng,
+        )
+        .unwrap();
+        assert_eq!(
+            dictionary_attack(&data, WORDLIST.lines(), kdf, KeySize::Aes128).unwrap(),
+            None
+        );
+
+        let data = encrypt_with_rng(plaintext, b"hunter2", kdf, KeySize::Aes128, &mut rng).unwrap();
+        let wordlist = concat!(env!("CARGO_MANIFEST_DIR"), "/src/files/passwords.txt");
+        let cracked = dictionary_attack_file(&data, wordlist, kdf, KeySize::Aes128)
+            .unwrap()
+            .unwrap();
+        assert_eq!(cracked.password, "hunter2");
+    }
+}
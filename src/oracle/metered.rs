@@ -0,0 +1,338 @@
+// Middleware that counts what an attack spends on an oracle, and cuts it off
+// once a budget is used up.
+//
+// Every query through a `Metered` oracle adds to its `Stats`: the number of
+// queries, the bytes sent and received, and the time spent waiting on the
+// inner oracle. A query over budget fails with `BudgetExceeded` without
+// reaching the inner oracle, the way a rate-limited target would refuse it.
+use std::{
+    cell::RefCell,
+    error::Error,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use anyhow::{bail, Result};
+
+use crate::oracle::{
+    DecryptionOracle, EditOracle, EncryptionOracle, PaddingOracle, SignatureOracle, Target,
+    VerificationOracle,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetExceeded {
+    Queries(usize),
+    Bytes(usize),
+}
+
+impl Error for BudgetExceeded {}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BudgetExceeded::Queries(limit) => write!(f, "query budget of {limit} exceeded"),
+            BudgetExceeded::Bytes(limit) => write!(f, "byte budget of {limit} exceeded"),
+        }
+    }
+}
+
+/// Hard limits on what may be spent. `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Budget {
+    pub queries: Option<usize>,
+    /// Bytes sent to the oracle
+    pub bytes: Option<usize>,
+}
+
+impl Budget {
+    pub fn unlimited() -> Self {
+        Budget::default()
+    }
+
+    pub fn queries(queries: usize) -> Self {
+        Budget {
+            queries: Some(queries),
+            bytes: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    pub queries: usize,
+    pub bytes_sent: usize,
+    /// Bytes of returned data: yes/no answers count as nothing
+    pub bytes_received: usize,
+    pub elapsed: Duration,
+}
+
+impl Stats {
+    pub fn mean_latency(&self) -> Duration {
+        match self.queries {
+            0 => Duration::ZERO,
+            queries => self.elapsed / queries as u32,
+        }
+    }
+}
+
+pub struct Metered<O> {
+    inner: O,
+    budget: Budget,
+    stats: RefCell<Stats>,
+}
+
+impl<O> Metered<O> {
+    pub fn new(inner: O) -> Self {
+        Metered::with_budget(inner, Budget::unlimited())
+    }
+
+    pub fn with_budget(inner: O, budget: Budget) -> Self {
+        Metered {
+            inner,
+            budget,
+            stats: RefCell::new(Stats::default()),
+        }
+    }
+
+    pub fn inner(&self) -> &O {
+        &self.inner
+    }
+
+    pub fn stats(&self) -> Stats {
+        *self.stats.borrow()
+    }
+
+    pub fn report(&self, attack: &str) -> Report {
+        Report {
+            attack: attack.into(),
+            budget: self.budget,
+            stats: self.stats(),
+        }
+    }
+
+    // Charges a query of `sent` bytes against the budget, then runs it
+    fn query<T>(
+        &self,
+        sent: usize,
+        received: impl Fn(&T) -> usize,
+        run: impl FnOnce(&O) -> Result<T>,
+    ) -> Result<T> {
+        let stats = self.stats();
+        if let Some(limit) = self.budget.queries {
+            if stats.queries >= limit {
+                bail!(BudgetExceeded::Queries(limit));
+            }
+        }
+        if let Some(limit) = self.budget.bytes {
+            if stats.bytes_sent + sent > limit {
+                bail!(BudgetExceeded::Bytes(limit));
+            }
+        }
+
+        let start = Instant::now();
+        let result = run(&self.inner);
+        let elapsed = start.elapsed();
+
+        let mut stats = self.stats.borrow_mut();
+        stats.queries += 1;
+        stats.bytes_sent += sent;
+        stats.elapsed += elapsed;
+        if let Ok(response) = &result {
+            stats.bytes_received += received(response);
+        }
+        result
+    }
+}
+
+fn nothing<T>(_: &T) -> usize {
+    0
+}
+
+impl<O: Target> Target for Metered<O> {
+    fn block_size_hint(&self) -> Option<usize> {
+        self.inner.block_size_hint()
+    }
+}
+
+impl<O: EncryptionOracle> EncryptionOracle for Metered<O> {
+    fn encrypt(&self, input: &[u8]) -> Result<Vec<u8>> {
+        self.query(input.len(), Vec::len, |o| o.encrypt(input))
+    }
+}
+
+impl<O: DecryptionOracle> DecryptionOracle for Metered<O> {
+    fn decrypt(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.query(ciphertext.len(), Vec::len, |o| o.decrypt(ciphertext))
+    }
+}
+
+impl<O: PaddingOracle> PaddingOracle for Metered<O> {
+    fn check_padding(&self, ciphertext: &[u8]) -> Result<bool> {
+        self.query(ciphertext.len(), nothing, |o| o.check_padding(ciphertext))
+    }
+}
+
+impl<O: VerificationOracle> VerificationOracle for Metered<O> {
+    fn verify(&self, message: &[u8]) -> Result<bool> {
+        self.query(message.len(), nothing, |o| o.verify(message))
+    }
+}
+
+impl<O: EditOracle> EditOracle for Metered<O> {
+    fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> Result<Vec<u8>> {
+        let sent = ciphertext.len() + newtext.len();
+        self.query(sent, Vec::len, |o| o.edit(ciphertext, offset, newtext))
+    }
+}
+
+impl<O: SignatureOracle> SignatureOracle for Metered<O> {
+    fn check_signature(&self, file: &str, signature: &[u8]) -> Result<(bool, Duration)> {
+        let sent = file.len() + signature.len();
+        self.query(sent, nothing, |o| o.check_signature(file, signature))
+    }
+}
+
+/// What one attack spent, for comparing attacks by query complexity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    pub attack: String,
+    pub budget: Budget,
+    pub stats: Stats,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let stats = &self.stats;
+        write!(
+            f,
+            "{}: {} queries, {} bytes sent, {} bytes received, {:?} total ({:?} per query)",
+            self.attack,
+            stats.queries,
+            stats.bytes_sent,
+            stats.bytes_received,
+            stats.elapsed,
+            stats.mean_latency()
+        )?;
+        if let Some(limit) = self.budget.queries {
+            write!(f, ", budget {limit} queries")?;
+        }
+        if let Some(limit) = self.budget.bytes {
+            write!(f, ", budget {limit} bytes")?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs `attack` against `oracle` under `budget`, returning its result along
+/// with what it spent. The report is produced even when the attack fails.
+pub fn measure<O, T>(
+    name: &str,
+    oracle: O,
+    budget: Budget,
+    attack: impl FnOnce(&Metered<O>) -> Result<T>,
+) -> (Result<T>, Report) {
+    let metered = Metered::with_budget(oracle, budget);
+    let result = attack(&metered);
+    (result, metered.report(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::{break_cbc_padding_oracle, break_ecb, break_ecb_with_rng},
+        oracle::{
+            metered::{measure, Budget, BudgetExceeded, Metered},
+            CbcPaddingOracle, EcbOracle, EncryptionOracle, PaddingOracle, PrefixEcbOracle, Target,
+        },
+        utils::random::test_rng,
+    };
+
+    #[test]
+    fn test_counts_queries() {
+        let metered = Metered::new(EcbOracle::new(b"secret".to_vec()));
+        assert_eq!(metered.block_size_hint(), Some(16));
+        metered.encrypt(b"").unwrap();
+        metered.encrypt(&[0; 20]).unwrap();
+        let stats = metered.stats();
+        assert_eq!(stats.queries, 2);
+        assert_eq!(stats.bytes_sent, 20);
+        assert_eq!(stats.bytes_received, 16 + 32);
+
+        let oracle = CbcPaddingOracle::new(b"secret".to_vec()).unwrap();
+        let metered = Metered::new(&oracle);
+        assert!(metered.check_padding(&oracle.ciphertext).unwrap());
+        assert_eq!(metered.stats().bytes_received, 0);
+    }
+
+    #[test]
+    fn test_budget() {
+        let secret = b"Rollin' in my 5.0".to_vec();
+        let (result, report) = measure(
+            "break_ecb",
+            EcbOracle::new(secret.clone()),
+            Budget::unlimited(),
+            break_ecb,
+        );
+        assert_eq!(result.unwrap(), secret);
+        let needed = report.stats.queries;
+        assert!(needed > secret.len());
+        assert!(report.to_string().starts_with("break_ecb: "));
+
+        // with half the queries it needs, the attack stops at the limit
+        let (result, report) = measure(
+            "break_ecb",
+            EcbOracle::new(secret.clone()),
+            Budget::queries(needed / 2),
+            break_ecb,
+        );
+        let err = result.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BudgetExceeded>(),
+            Some(&BudgetExceeded::Queries(needed / 2))
+        );
+        assert_eq!(report.stats.queries, needed / 2);
+
+        let budget = Budget {
+            queries: None,
+            bytes: Some(10),
+        };
+        let metered = Metered::with_budget(EcbOracle::new(secret), budget);
+        metered.encrypt(&[0; 10]).unwrap();
+        let err = metered.encrypt(&[0; 1]).unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<BudgetExceeded>(),
+            Some(&BudgetExceeded::Bytes(10))
+        );
+        assert_eq!(metered.stats().queries, 1);
+    }
+
+    // the padding oracle attack needs at most 256 queries per byte, and
+    // working around a prefix never costs less than the same secret alone
+    #[test]
+    fn test_compare_attacks() {
+        let mut rng = test_rng();
+        let secret = b"YELLOW SUBMARINE, yellow submarine".to_vec();
+        let oracle = CbcPaddingOracle::with_rng(secret.clone(), &mut rng).unwrap();
+        let (result, report) = measure("padding oracle", &oracle, Budget::unlimited(), |o| {
+            break_cbc_padding_oracle(o, &oracle.iv, &oracle.ciphertext)
+        });
+        assert_eq!(result.unwrap(), secret);
+        assert!(report.stats.queries <= 256 * oracle.ciphertext.len());
+
+        let (result, plain) = measure(
+            "ecb",
+            EcbOracle::with_rng(secret.clone(), &mut rng),
+            Budget::unlimited(),
+            |o| break_ecb_with_rng(o, &mut rng),
+        );
+        assert_eq!(result.unwrap(), secret);
+        let (result, prefixed) = measure(
+            "prefixed ecb",
+            PrefixEcbOracle::with_rng(secret.clone(), &mut rng),
+            Budget::unlimited(),
+            |o| break_ecb_with_rng(o, &mut rng),
+        );
+        assert_eq!(result.unwrap(), secret);
+        assert!(prefixed.stats.queries >= plain.stats.queries);
+    }
+}
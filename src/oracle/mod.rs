@@ -0,0 +1,713 @@
+use crate::{
+    aes::{self, dfa, native, AesKey},
+    cipher::BlockCipher,
+    ctr::{CounterLayout, Ctr},
+    fingerprint, modes,
+    utils::random::RandomSource,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::{cell::RefCell, collections::HashMap, time::Duration};
+
+pub mod metered;
+
+pub struct Oracle<T> {
+    pub ciphertext: Vec<u8>,
+    mode: T,
+}
+
+impl Oracle<aes::Mode> {
+    pub fn verify(&self, mode: &aes::Mode) -> bool {
+        &self.mode == mode
+    }
+}
+
+pub fn guess(oracle: &Oracle<aes::Mode>) -> aes::Mode {
+    match fingerprint::fingerprint_ciphertexts(&[&oracle.ciphertext])
+        .mode
+        .value
+    {
+        aes::Mode::ECB => aes::Mode::ECB,
+        _ => aes::Mode::CBC,
+    }
+}
+
+pub fn encrypt(plaintext: &[u8]) -> anyhow::Result<Oracle<aes::Mode>> {
+    encrypt_with_rng(plaintext, &mut rand::thread_rng())
+}
+
+pub fn encrypt_with_rng(
+    plaintext: &[u8],
+    rng: &mut impl RandomSource,
+) -> anyhow::Result<Oracle<aes::Mode>> {
+    let block_size = 16;
+    let key = &rng.rand_bytes(block_size);
+    let mut data = vec![];
+
+    let prepend_len = rng.rand_range(5..=15);
+    let append_len = rng.rand_range(5..=15);
+    data.extend(rng.rand_bytes(prepend_len));
+    data.extend(plaintext);
+    data.extend(rng.rand_bytes(append_len));
+
+    let mode;
+    let ciphertext = if rng.rand_bool() {
+        mode = aes::Mode::ECB;
+        aes::encrypt_aes_ecb(&data, key)?
+    } else {
+        mode = aes::Mode::CBC;
+        let iv = rng.rand_bytes(block_size);
+        aes::encrypt_aes_cbc(&data, &iv, key)?
+    };
+    Ok(Oracle { ciphertext, mode })
+}
+
+/// Anything an attack can query. Targets that know their block size can say
+/// so, which saves the attack from working it out.
+pub trait Target {
+    fn block_size_hint(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// Encrypts attacker-chosen input, possibly with its own data around it
+pub trait EncryptionOracle: Target {
+    fn encrypt(&self, input: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Decrypts attacker-chosen ciphertext and returns the plaintext
+pub trait DecryptionOracle: Target {
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Says whether a ciphertext decrypts to valid padding, and nothing else
+pub trait PaddingOracle: Target {
+    fn check_padding(&self, ciphertext: &[u8]) -> anyhow::Result<bool>;
+}
+
+/// Says whether a forged message is accepted
+pub trait VerificationOracle: Target {
+    fn verify(&self, message: &[u8]) -> anyhow::Result<bool>;
+}
+
+/// Overwrites part of a ciphertext with new plaintext, returning the new
+/// ciphertext
+pub trait EditOracle: Target {
+    fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> anyhow::Result<Vec<u8>>;
+}
+
+/// Checks a file's signature, saying how long the check took as well as
+/// whether it passed
+pub trait SignatureOracle: Target {
+    fn check_signature(&self, file: &str, signature: &[u8]) -> anyhow::Result<(bool, Duration)>;
+}
+
+impl<T: Target + ?Sized> Target for &T {
+    fn block_size_hint(&self) -> Option<usize> {
+        (**self).block_size_hint()
+    }
+}
+
+impl<T: EncryptionOracle + ?Sized> EncryptionOracle for &T {
+    fn encrypt(&self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        (**self).encrypt(input)
+    }
+}
+
+impl<T: DecryptionOracle + ?Sized> DecryptionOracle for &T {
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        (**self).decrypt(ciphertext)
+    }
+}
+
+impl<T: PaddingOracle + ?Sized> PaddingOracle for &T {
+    fn check_padding(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        (**self).check_padding(ciphertext)
+    }
+}
+
+impl<T: VerificationOracle + ?Sized> VerificationOracle for &T {
+    fn verify(&self, message: &[u8]) -> anyhow::Result<bool> {
+        (**self).verify(message)
+    }
+}
+
+impl<T: EditOracle + ?Sized> EditOracle for &T {
+    fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        (**self).edit(ciphertext, offset, newtext)
+    }
+}
+
+impl<T: SignatureOracle + ?Sized> SignatureOracle for &T {
+    fn check_signature(&self, file: &str, signature: &[u8]) -> anyhow::Result<(bool, Duration)> {
+        (**self).check_signature(file, signature)
+    }
+}
+
+pub struct EcbOracle<C = AesKey> {
+    cipher: C,
+    secret: Vec<u8>,
+}
+
+impl EcbOracle<AesKey> {
+    pub fn new(secret: Vec<u8>) -> Self {
+        EcbOracle::with_rng(secret, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(secret: Vec<u8>, rng: &mut impl RandomSource) -> Self {
+        let blocksize = 16;
+        let cipher = AesKey::new(&rng.rand_bytes(blocksize)).expect("valid key length");
+        EcbOracle::with_cipher(cipher, secret)
+    }
+}
+
+impl<C: BlockCipher> EcbOracle<C> {
+    pub fn with_cipher(cipher: C, secret: Vec<u8>) -> Self {
+        EcbOracle { cipher, secret }
+    }
+    pub fn encrypt(&self, padding: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut plaintext = vec![];
+        plaintext.extend(padding);
+        plaintext.extend(&self.secret);
+        modes::encrypt_ecb(&self.cipher, &plaintext)
+    }
+    pub fn verify(&self, plaintext: &[u8]) -> bool {
+        self.secret == plaintext
+    }
+}
+
+impl<C: BlockCipher> Target for EcbOracle<C> {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(self.cipher.block_size())
+    }
+}
+
+impl<C: BlockCipher> EncryptionOracle for EcbOracle<C> {
+    fn encrypt(&self, padding: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encrypt(padding)
+    }
+}
+
+impl<C: BlockCipher> Target for PrefixEcbOracle<C> {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(self.cipher.block_size())
+    }
+}
+
+impl<C: BlockCipher> EncryptionOracle for PrefixEcbOracle<C> {
+    fn encrypt(&self, padding: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encrypt(padding)
+    }
+}
+
+impl Target for ProfileOracle {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(16)
+    }
+}
+
+impl EncryptionOracle for ProfileOracle {
+    fn encrypt(&self, padding: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encrypt(padding)
+    }
+}
+
+impl DecryptionOracle for ProfileOracle {
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        aes::decrypt_aes_ecb(ciphertext, &self.key)
+    }
+}
+
+impl VerificationOracle for ProfileOracle {
+    fn verify(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        self.verify(ciphertext)
+    }
+}
+
+#[derive(Debug)]
+pub struct PrefixEcbOracle<C = AesKey> {
+    cipher: C,
+    pub prefix: Vec<u8>,
+    secret: Vec<u8>,
+}
+
+impl PrefixEcbOracle<AesKey> {
+    pub fn new(secret: Vec<u8>) -> Self {
+        PrefixEcbOracle::with_rng(secret, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(secret: Vec<u8>, rng: &mut impl RandomSource) -> Self {
+        let blocksize = 16;
+        let cipher = AesKey::new(&rng.rand_bytes(blocksize)).expect("valid key length");
+        PrefixEcbOracle::with_cipher_and_rng(cipher, secret, rng)
+    }
+}
+
+impl<C: BlockCipher> PrefixEcbOracle<C> {
+    pub fn with_cipher(cipher: C, secret: Vec<u8>) -> Self {
+        PrefixEcbOracle::with_cipher_and_rng(cipher, secret, &mut rand::thread_rng())
+    }
+
+    pub fn with_cipher_and_rng(cipher: C, secret: Vec<u8>, rng: &mut impl RandomSource) -> Self {
+        let prefix_len = rng.rand_range(5..=40);
+        let prefix = rng.rand_bytes(prefix_len);
+        PrefixEcbOracle {
+            cipher,
+            prefix,
+            secret,
+        }
+    }
+    pub fn encrypt(&self, bytes: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut plaintext = vec![];
+        plaintext.extend(&self.prefix);
+        plaintext.extend(bytes);
+        plaintext.extend(&self.secret);
+        modes::encrypt_ecb(&self.cipher, &plaintext)
+    }
+
+    pub fn verify(&self, plaintext: &[u8]) -> bool {
+        self.secret == plaintext
+    }
+
+    pub fn verify_prefixlen(&self, len: usize) -> bool {
+        dbg!(self.prefix.len() == len)
+    }
+
+    pub fn verify_payload_len(&self, len: usize) -> bool {
+        dbg!(self.secret.len() == len)
+    }
+}
+
+pub struct ProfileOracle {
+    key: Vec<u8>,
+}
+
+impl ProfileOracle {
+    pub fn new() -> Self {
+        Self::with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn with_rng(rng: &mut impl RandomSource) -> Self {
+        Self {
+            key: rng.rand_bytes(16),
+        }
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let email = String::from_utf8(data.to_vec())?;
+        aes::encrypt_aes_ecb(Self::profile_for(&email).as_bytes(), &self.key)
+    }
+
+    pub fn verify(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        let profile = self.decrypt_profile(ciphertext)?;
+        if let Some(v) = profile.get("role") {
+            Ok(v == "admin")
+        } else {
+            Ok(false)
+        }
+    }
+
+    pub fn decrypt_profile(&self, ciphertext: &[u8]) -> anyhow::Result<HashMap<String, String>> {
+        let decrypted = DecryptionOracle::decrypt(self, ciphertext)?;
+        Self::kvparse(&String::from_utf8_lossy(&decrypted))
+    }
+
+    // The ciphertext is attacker-controlled, so its plaintext may be anything
+    fn kvparse(s: &str) -> anyhow::Result<HashMap<String, String>> {
+        s.split('&')
+            .map(|kv| {
+                let (k, v) = kv
+                    .split_once('=')
+                    .ok_or_else(|| anyhow::anyhow!("malformed profile field {kv:?}"))?;
+                Ok((k.into(), v.into()))
+            })
+            .collect()
+    }
+
+    fn profile_for(email: &str) -> String {
+        let email = email.replace('=', "");
+        let email = email.replace('&', "");
+        format!("email={email}&uid=10&role=user")
+    }
+}
+
+impl Default for ProfileOracle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct CbcOracle<C = AesKey> {
+    cipher: C,
+    iv: Vec<u8>,
+    prefix: String,
+    suffix: String,
+    target: String,
+}
+
+impl CbcOracle<AesKey> {
+    pub fn new() -> Self {
+        CbcOracle::with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn with_rng(rng: &mut impl RandomSource) -> Self {
+        let cipher = AesKey::new(&rng.rand_bytes(16)).expect("valid key length");
+        CbcOracle::with_cipher_and_rng(cipher, rng)
+    }
+}
+
+impl<C: BlockCipher> CbcOracle<C> {
+    pub fn with_cipher(cipher: C) -> Self {
+        CbcOracle::with_cipher_and_rng(cipher, &mut rand::thread_rng())
+    }
+
+    pub fn with_cipher_and_rng(cipher: C, rng: &mut impl RandomSource) -> Self {
+        CbcOracle {
+            iv: rng.rand_bytes(cipher.block_size()),
+            cipher,
+            prefix: "comment1=cooking%20MCs;userdata=".into(),
+            suffix: ";comment2=%20like%20a%20pound%20of%20bacon".into(),
+            target: ";admin=true;".into(),
+        }
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.cipher.block_size()
+    }
+
+    pub fn encrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        let data = String::from_utf8_lossy(data);
+        let data = data.replace(';', "%3B");
+        let data = data.replace('=', "%3D");
+        bytes.extend(self.prefix.as_bytes());
+        bytes.extend(data.as_bytes());
+        bytes.extend(self.suffix.as_bytes());
+
+        modes::encrypt_cbc(&self.cipher, &bytes, &self.iv)
+    }
+
+    fn decrypt(&self, ciphertext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        modes::decrypt_cbc(&self.cipher, ciphertext, &self.iv)
+    }
+
+    pub fn verify(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        let result = self.decrypt(ciphertext)?;
+        Ok(String::from_utf8_lossy(&result).contains(&self.target))
+    }
+}
+
+impl Default for CbcOracle<AesKey> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: BlockCipher> Target for CbcOracle<C> {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(self.block_size())
+    }
+}
+
+impl<C: BlockCipher> EncryptionOracle for CbcOracle<C> {
+    fn encrypt(&self, padding: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encrypt(padding)
+    }
+}
+
+impl<C: BlockCipher> VerificationOracle for CbcOracle<C> {
+    fn verify(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        self.verify(ciphertext)
+    }
+}
+
+pub struct CbcPaddingOracle<C = AesKey> {
+    cipher: C,
+    pub iv: Vec<u8>,
+    pub plaintext: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl CbcPaddingOracle<AesKey> {
+    pub fn new(plaintext: Vec<u8>) -> anyhow::Result<Self> {
+        CbcPaddingOracle::with_rng(plaintext, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(plaintext: Vec<u8>, rng: &mut impl RandomSource) -> anyhow::Result<Self> {
+        let blocksize = 16;
+        let cipher = AesKey::new(&rng.rand_bytes(blocksize))?;
+        CbcPaddingOracle::with_cipher_and_rng(cipher, plaintext, rng)
+    }
+}
+
+impl<C: BlockCipher> CbcPaddingOracle<C> {
+    pub fn with_cipher(cipher: C, plaintext: Vec<u8>) -> anyhow::Result<Self> {
+        CbcPaddingOracle::with_cipher_and_rng(cipher, plaintext, &mut rand::thread_rng())
+    }
+
+    pub fn with_cipher_and_rng(
+        cipher: C,
+        plaintext: Vec<u8>,
+        rng: &mut impl RandomSource,
+    ) -> anyhow::Result<Self> {
+        let iv = rng.rand_bytes(cipher.block_size());
+        let ciphertext = modes::encrypt_cbc(&cipher, &plaintext, &iv)?;
+        Ok(CbcPaddingOracle {
+            cipher,
+            iv,
+            plaintext,
+            ciphertext,
+        })
+    }
+
+    pub fn block_size(&self) -> usize {
+        self.cipher.block_size()
+    }
+
+    pub fn check_padding(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        match modes::decrypt_cbc(&self.cipher, ciphertext, &self.iv) {
+            Ok(_) => Ok(true),
+            Err(_e) => Ok(false),
+        }
+    }
+
+    pub fn verify(&self, plaintext: &[u8]) -> bool {
+        self.plaintext == plaintext
+    }
+}
+
+impl<C: BlockCipher> Target for CbcPaddingOracle<C> {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(self.block_size())
+    }
+}
+
+impl<C: BlockCipher> PaddingOracle for CbcPaddingOracle<C> {
+    fn check_padding(&self, ciphertext: &[u8]) -> anyhow::Result<bool> {
+        self.check_padding(ciphertext)
+    }
+}
+
+/// Random-access read/write CTR: exposes an `edit` that re-encrypts part of
+/// the ciphertext under the same keystream.
+pub struct CtrEditOracle<C = AesKey> {
+    ctr: Ctr<C>,
+    plaintext: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+impl CtrEditOracle<AesKey> {
+    pub fn new(plaintext: Vec<u8>) -> anyhow::Result<Self> {
+        CtrEditOracle::with_rng(plaintext, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(plaintext: Vec<u8>, rng: &mut impl RandomSource) -> anyhow::Result<Self> {
+        let cipher = AesKey::new(&rng.rand_bytes(16))?;
+        CtrEditOracle::with_cipher_and_rng(cipher, plaintext, rng)
+    }
+}
+
+impl<C: BlockCipher> CtrEditOracle<C> {
+    pub fn with_cipher(cipher: C, plaintext: Vec<u8>) -> anyhow::Result<Self> {
+        CtrEditOracle::with_cipher_and_rng(cipher, plaintext, &mut rand::thread_rng())
+    }
+
+    pub fn with_cipher_and_rng(
+        cipher: C,
+        plaintext: Vec<u8>,
+        rng: &mut impl RandomSource,
+    ) -> anyhow::Result<Self> {
+        let block_size = cipher.block_size();
+        // keep the nonce within half the block, as LittleEndian64 requires
+        let nonce = rng.rand_u64() >> (64 - 8 * (block_size / 2).min(8));
+        let ctr = Ctr::new(cipher, CounterLayout::LittleEndian64 { nonce })?;
+        let ciphertext = ctr.apply(&plaintext)?;
+        Ok(CtrEditOracle {
+            ctr,
+            plaintext,
+            ciphertext,
+        })
+    }
+
+    pub fn edit(
+        &self,
+        ciphertext: &[u8],
+        offset: usize,
+        newtext: &[u8],
+    ) -> anyhow::Result<Vec<u8>> {
+        self.ctr.edit(ciphertext, offset, newtext)
+    }
+
+    pub fn verify(&self, plaintext: &[u8]) -> bool {
+        self.plaintext == plaintext
+    }
+}
+
+impl<C: BlockCipher> Target for CtrEditOracle<C> {}
+
+impl<C: BlockCipher> EditOracle for CtrEditOracle<C> {
+    fn edit(&self, ciphertext: &[u8], offset: usize, newtext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.edit(ciphertext, offset, newtext)
+    }
+}
+
+/// Chosen-plaintext access to AES-128 cut down to a few rounds, for the
+/// structural attacks in `aes::square`
+pub struct ReducedAesOracle {
+    key: Vec<u8>,
+    cipher: native::Aes,
+}
+
+impl ReducedAesOracle {
+    pub fn new(rounds: usize) -> anyhow::Result<Self> {
+        ReducedAesOracle::with_rng(rounds, &mut rand::thread_rng())
+    }
+
+    pub fn with_rng(rounds: usize, rng: &mut impl RandomSource) -> anyhow::Result<Self> {
+        let key = rng.rand_bytes(16);
+        let cipher = native::Aes::with_rounds(&key, rounds)?;
+        Ok(ReducedAesOracle { key, cipher })
+    }
+
+    pub fn rounds(&self) -> usize {
+        self.cipher.rounds()
+    }
+
+    pub fn encrypt(&self, block: &[u8]) -> anyhow::Result<Vec<u8>> {
+        BlockCipher::encrypt_block(&self.cipher, block)
+    }
+
+    pub fn verify(&self, key: &[u8]) -> bool {
+        self.key == key
+    }
+}
+
+impl Target for ReducedAesOracle {
+    fn block_size_hint(&self) -> Option<usize> {
+        Some(native::BLOCK_SIZE)
+    }
+}
+
+impl EncryptionOracle for ReducedAesOracle {
+    fn encrypt(&self, block: &[u8]) -> anyhow::Result<Vec<u8>> {
+        self.encrypt(block)
+    }
+}
+
+/// Full AES-128 that can be made to glitch: `encrypt_pair` returns the correct
+/// ciphertext of a random plaintext along with one where a random byte of the
+/// state was corrupted at the start of round 9
+pub struct FaultOracle {
+    key: Vec<u8>,
+    cipher: native::Aes,
+    // the glitches are drawn from here, so a seeded oracle glitches the same
+    // way every run
+    rng: RefCell<StdRng>,
+}
+
+impl FaultOracle {
+    pub fn new() -> anyhow::Result<Self> {
+        FaultOracle::with_rng(&mut rand::thread_rng())
+    }
+
+    pub fn with_rng(rng: &mut impl RandomSource) -> anyhow::Result<Self> {
+        let key = rng.rand_bytes(16);
+        let cipher = native::Aes::new(&key)?;
+        let rng = RefCell::new(StdRng::seed_from_u64(rng.rand_u64()));
+        Ok(FaultOracle { key, cipher, rng })
+    }
+
+    pub fn encrypt_pair(&self) -> anyhow::Result<(native::Block, native::Block)> {
+        let mut rng = self.rng.borrow_mut();
+        let mut correct: native::Block = rng
+            .rand_bytes(16)
+            .try_into()
+            .map_err(|_| anyhow::anyhow!("expected 16 bytes"))?;
+        let fault = dfa::Fault {
+            round: dfa::FAULT_ROUND,
+            position: rng.rand_range(0..=15),
+            mask: rng.rand_range(1..=255) as u8,
+        };
+        let mut faulty = correct;
+        self.cipher.encrypt_block(&mut correct);
+        self.cipher
+            .encrypt_block_with_hook(&mut faulty, |round, state| fault.apply(round, state));
+        Ok((correct, faulty))
+    }
+
+    pub fn verify(&self, key: &[u8]) -> bool {
+        self.key == key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        oracle::{
+            self, CbcOracle, CbcPaddingOracle, CtrEditOracle, EcbOracle, FaultOracle,
+            PrefixEcbOracle, ProfileOracle, VerificationOracle,
+        },
+        utils::random::seeded,
+    };
+    use std::collections::HashMap;
+
+    // the same seed builds the same oracles, down to the keys and IVs
+    #[test]
+    fn test_seeded_oracles_repeat() {
+        let secret = b"the same every time".to_vec();
+        let build = |seed| {
+            let mut rng = seeded(seed);
+            let mut outputs = vec![
+                oracle::encrypt_with_rng(b"input", &mut rng)
+                    .unwrap()
+                    .ciphertext,
+                EcbOracle::with_rng(secret.clone(), &mut rng)
+                    .encrypt(b"input")
+                    .unwrap(),
+                PrefixEcbOracle::with_rng(secret.clone(), &mut rng)
+                    .encrypt(b"input")
+                    .unwrap(),
+                ProfileOracle::with_rng(&mut rng)
+                    .encrypt(b"me@example.com")
+                    .unwrap(),
+                CbcOracle::with_rng(&mut rng).encrypt(b"input").unwrap(),
+                CbcPaddingOracle::with_rng(secret.clone(), &mut rng)
+                    .unwrap()
+                    .ciphertext,
+                CtrEditOracle::with_rng(secret.clone(), &mut rng)
+                    .unwrap()
+                    .ciphertext,
+            ];
+            let fault = FaultOracle::with_rng(&mut rng).unwrap();
+            for _ in 0..3 {
+                let (correct, faulty) = fault.encrypt_pair().unwrap();
+                outputs.extend([correct.to_vec(), faulty.to_vec()]);
+            }
+            outputs
+        };
+        assert_eq!(build(1), build(1));
+        assert_ne!(build(1), build(2));
+    }
+
+    #[test]
+    fn test_kvparse() {
+        let parsed = ProfileOracle::kvparse("foo=bar").unwrap();
+        assert_eq!(parsed, HashMap::from([("foo".into(), "bar".into())]));
+
+        let parsed = ProfileOracle::kvparse("foo=bar&baz=qux").unwrap();
+        assert_eq!(
+            parsed,
+            HashMap::from([("foo".into(), "bar".into()), ("baz".into(), "qux".into())])
+        );
+
+        assert!(ProfileOracle::kvparse("").is_err());
+        assert!(ProfileOracle::kvparse("foo=bar&baz").is_err());
+
+        // "email=a@bar.com&uid=10&role=user" is two blocks, so the third is
+        // all padding and decrypts to nothing
+        let oracle = ProfileOracle::new();
+        let ciphertext = oracle.encrypt(b"a@bar.com").unwrap();
+        let padding = &ciphertext[32..];
+        assert!(oracle.decrypt_profile(padding).is_err());
+        assert!(VerificationOracle::verify(&oracle, padding).is_err());
+    }
+}
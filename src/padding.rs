@@ -1,30 +1,40 @@
 use crate::{aes::AesError, utils::bytes};
 use anyhow::{ensure, Result};
 
+const DEFAULT_BLOCK_SIZE: usize = 16;
+
 pub trait PadPkcs7 {
-    fn pad_pkcs7(&self) -> Vec<u8>;
+    fn pad_pkcs7(&self) -> Vec<u8> {
+        self.pad_pkcs7_blocksize(DEFAULT_BLOCK_SIZE)
+    }
+    fn pad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8>;
 }
 
 pub trait UnpadPkcs7 {
-    fn unpad_pkcs7(&self) -> Vec<u8>;
-    fn validate_unpad_pkcs7(&self) -> Result<Vec<u8>>;
+    fn unpad_pkcs7(&self) -> Vec<u8> {
+        self.unpad_pkcs7_blocksize(DEFAULT_BLOCK_SIZE)
+    }
+    fn validate_unpad_pkcs7(&self) -> Result<Vec<u8>> {
+        self.validate_unpad_pkcs7_blocksize(DEFAULT_BLOCK_SIZE)
+    }
+    fn unpad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8>;
+    fn validate_unpad_pkcs7_blocksize(&self, block_size: usize) -> Result<Vec<u8>>;
 }
 
 impl PadPkcs7 for &[u8] {
-    fn pad_pkcs7(&self) -> Vec<u8> {
-        pad_pkcs7(self)
+    fn pad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8> {
+        pad_pkcs7(self, block_size)
     }
 }
 
 impl PadPkcs7 for Vec<u8> {
-    fn pad_pkcs7(&self) -> Vec<u8> {
-        pad_pkcs7(self)
+    fn pad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8> {
+        pad_pkcs7(self, block_size)
     }
 }
 
-fn pad_pkcs7(data: &[u8]) -> Vec<u8> {
-    let block_size = 16;
-    let rem = 16 - data.len() % block_size;
+fn pad_pkcs7(data: &[u8], block_size: usize) -> Vec<u8> {
+    let rem = block_size - data.len() % block_size;
 
     let mut padded = data.to_vec();
     padded.extend(bytes::of_len(rem, rem as u8));
@@ -32,33 +42,32 @@ fn pad_pkcs7(data: &[u8]) -> Vec<u8> {
 }
 
 impl UnpadPkcs7 for &[u8] {
-    fn unpad_pkcs7(&self) -> Vec<u8> {
-        unpad_pkcs7(self)
+    fn unpad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8> {
+        unpad_pkcs7(self, block_size)
     }
 
-    fn validate_unpad_pkcs7(&self) -> Result<Vec<u8>> {
-        validate_unpad_pkcs7(self)
+    fn validate_unpad_pkcs7_blocksize(&self, block_size: usize) -> Result<Vec<u8>> {
+        validate_unpad_pkcs7(self, block_size)
     }
 }
 
 impl UnpadPkcs7 for Vec<u8> {
-    fn unpad_pkcs7(&self) -> Vec<u8> {
-        unpad_pkcs7(self)
+    fn unpad_pkcs7_blocksize(&self, block_size: usize) -> Vec<u8> {
+        unpad_pkcs7(self, block_size)
     }
 
-    fn validate_unpad_pkcs7(&self) -> Result<Vec<u8>> {
-        validate_unpad_pkcs7(self)
+    fn validate_unpad_pkcs7_blocksize(&self, block_size: usize) -> Result<Vec<u8>> {
+        validate_unpad_pkcs7(self, block_size)
     }
 }
 
-fn validate_unpad_pkcs7(data: &[u8]) -> Result<Vec<u8>> {
-    let blocksize = 16;
+fn validate_unpad_pkcs7(data: &[u8], blocksize: usize) -> Result<Vec<u8>> {
     ensure!(
         !data.is_empty(),
         AesError::InvalidPadding("Empty data".into())
     );
     ensure!(
-        data.len() % blocksize == 0,
+        data.len().is_multiple_of(blocksize),
         AesError::InvalidPadding(format!("Not multiple of blocksize: {}", data.len()))
     );
 
@@ -85,12 +94,11 @@ fn validate_unpad_pkcs7(data: &[u8]) -> Result<Vec<u8>> {
         AesError::InvalidPadding(format!("expected ending {:?}, got {:?}", expected, ending))
     );
 
-    Ok(data.unpad_pkcs7())
+    Ok(unpad_pkcs7(data, blocksize))
 }
 
-fn unpad_pkcs7(data: &[u8]) -> Vec<u8> {
-    let block_size = 16;
-    if data.len() % block_size != 0 {
+fn unpad_pkcs7(data: &[u8], block_size: usize) -> Vec<u8> {
+    if !data.len().is_multiple_of(block_size) {
         panic!(
             "Cannot unpad when length is not multiple of {}, len: {}",
             block_size,
@@ -150,6 +158,28 @@ mod tests {
         assert_eq!(padded.unpad_pkcs7(), "YELLOW SUBMARINE".as_bytes());
     }
 
+    #[test]
+    fn test_pad_unpad_blocksize() {
+        assert_eq!(
+            "YELLOW".as_bytes().pad_pkcs7_blocksize(8),
+            "YELLOW\x02\x02".as_bytes()
+        );
+        let padded = "YELLOW SUBMARINE".as_bytes().pad_pkcs7_blocksize(8);
+        assert_eq!(padded.len(), 24);
+        assert_eq!(
+            padded.validate_unpad_pkcs7_blocksize(8).unwrap(),
+            "YELLOW SUBMARINE".as_bytes()
+        );
+        assert_eq!(
+            padded.unpad_pkcs7_blocksize(8),
+            "YELLOW SUBMARINE".as_bytes()
+        );
+
+        // valid for 16 but the padding byte is larger than an 8-byte block
+        assert!(vec![16; 16].validate_unpad_pkcs7_blocksize(8).is_err());
+        assert!(vec![1; 12].validate_unpad_pkcs7_blocksize(8).is_err());
+    }
+
     #[test]
     fn test_validate_unpad() {
         assert!("ICE ICE BABY\x04\x04\x04\x04"
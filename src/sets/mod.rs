@@ -1,10 +1,12 @@
 pub mod set1;
 pub mod set2;
 pub mod set3;
+pub mod set4;
 
 pub fn main() -> anyhow::Result<()> {
     set1::main()?;
     set2::main()?;
     set3::main()?;
+    set4::main()?;
     Ok(())
 }
@@ -3,7 +3,7 @@ use anyhow::ensure;
 use crate::{
     aes::{self, break_cbc_bitflip, break_ecb, break_ecb_cut_paste},
     base64,
-    oracle::{self, CbcOracle, PaddingOracle, PrefixPaddingOracle, ProfileOracle},
+    oracle::{self, CbcOracle, EcbOracle, PrefixEcbOracle, ProfileOracle},
     padding::UnpadPkcs7,
     utils::{self, bytes},
 };
@@ -36,7 +36,7 @@ fn challenge11() -> anyhow::Result<()> {
 
 fn challenge12() -> anyhow::Result<()> {
     let secret = base64::from_file_str(CHALLENGE12_INPUT);
-    let oracle = PaddingOracle::new(secret);
+    let oracle = EcbOracle::new(secret);
     let result = break_ecb(&oracle)?;
     ensure!(oracle.verify(&result));
     println!(
@@ -52,7 +52,7 @@ fn challenge13() -> anyhow::Result<()> {
 
     println!(
         "✅ Challenge 13: Cut/Paste ECB to create fake admin profile\n\t{:?}",
-        oracle.decrypt(&result)?
+        oracle.decrypt_profile(&result)?
     );
     ensure!(oracle.verify(&result)?);
 
@@ -61,7 +61,7 @@ fn challenge13() -> anyhow::Result<()> {
 
 fn challenge14() -> anyhow::Result<()> {
     let secret = base64::from_file_str(CHALLENGE12_INPUT);
-    let oracle = PrefixPaddingOracle::new(secret);
+    let oracle = PrefixEcbOracle::new(secret);
     let result = break_ecb(&oracle)?;
     ensure!(oracle.verify(&result));
     println!(
@@ -117,11 +117,11 @@ pub fn main() -> anyhow::Result<()> {
 #[cfg(test)]
 mod tests {
     use crate::{
-        aes::{self, break_cbc_bitflip, break_ecb, break_ecb_cut_paste},
+        aes::{self, break_cbc_bitflip, break_ecb_cut_paste, break_ecb_with_rng},
         base64,
-        oracle::{self, CbcOracle, PaddingOracle, PrefixPaddingOracle, ProfileOracle},
+        oracle::{self, CbcOracle, EcbOracle, PrefixEcbOracle, ProfileOracle},
         sets::set2::CHALLENGE12_INPUT,
-        utils::bytes,
+        utils::{bytes, random::test_rng},
     };
     const CHALLENGE10_EXPECTED: &str = include_str!("../files/funky_music_lyrics.txt");
     const CHALLENGE12_EXPECTED: &str = include_str!("../files/rollin_lyrics.txt");
@@ -143,8 +143,9 @@ mod tests {
     fn test_challenge11() -> anyhow::Result<()> {
         // 3x block size
         let plaintext = &[100; 16 * 3];
+        let mut rng = test_rng();
         for _ in 0..100 {
-            let oracle = oracle::encrypt(plaintext)?;
+            let oracle = oracle::encrypt_with_rng(plaintext, &mut rng)?;
             let guess = &oracle::guess(&oracle);
             assert!(oracle.verify(guess), "guess {:?}", guess);
         }
@@ -153,10 +154,11 @@ mod tests {
 
     #[test]
     fn test_challenge12() -> anyhow::Result<()> {
+        let mut rng = test_rng();
         for _ in 0..100 {
             let secret = base64::from_file_str(CHALLENGE12_INPUT);
-            let oracle = PaddingOracle::new(secret);
-            let result = break_ecb(&oracle)?;
+            let oracle = EcbOracle::with_rng(secret, &mut rng);
+            let result = break_ecb_with_rng(&oracle, &mut rng)?;
             assert!(oracle.verify(&result));
         }
         Ok(())
@@ -164,8 +166,9 @@ mod tests {
 
     #[test]
     fn test_challenge13() -> anyhow::Result<()> {
+        let mut rng = test_rng();
         for _ in 0..100 {
-            let oracle = ProfileOracle::new();
+            let oracle = ProfileOracle::with_rng(&mut rng);
             let result = break_ecb_cut_paste(&oracle)?;
             assert!(oracle.verify(&result)?);
         }
@@ -174,12 +177,11 @@ mod tests {
 
     #[test]
     fn test_challenge14() -> anyhow::Result<()> {
-        // This fails about every now and again.
-        // If the loop count is increased to 1000 it fails basically every time
+        let mut rng = test_rng();
         for _ in 0..50 {
             let secret = base64::from_file_str(CHALLENGE12_INPUT);
-            let oracle = PrefixPaddingOracle::new(secret);
-            let result = break_ecb(&oracle)?;
+            let oracle = PrefixEcbOracle::with_rng(secret, &mut rng);
+            let result = break_ecb_with_rng(&oracle, &mut rng)?;
             assert_eq!(String::from_utf8_lossy(&result), CHALLENGE12_EXPECTED);
             assert!(oracle.verify(&result));
         }
@@ -188,8 +190,9 @@ mod tests {
 
     #[test]
     fn test_challenge16() -> anyhow::Result<()> {
+        let mut rng = test_rng();
         for _ in 0..100 {
-            let oracle = CbcOracle::new();
+            let oracle = CbcOracle::with_rng(&mut rng);
             let result = break_cbc_bitflip(&oracle)?;
             assert!(oracle.verify(&result)?);
         }
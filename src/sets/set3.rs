@@ -14,7 +14,7 @@ fn challenge17() -> Result<()> {
     for line in b64_plaintexts.lines() {
         let plaintext = line.trim().decode_base64();
         let oracle = CbcPaddingOracle::new(plaintext)?;
-        let result = break_cbc_padding_oracle(&oracle)?;
+        let result = break_cbc_padding_oracle(&oracle, &oracle.iv, &oracle.ciphertext)?;
 
         let emoji = if oracle.verify(&result) { "✅" } else { "❌" };
         println!("\t{emoji} {}", String::from_utf8(result)?);
@@ -129,7 +129,7 @@ mod tests {
         for line in b64_plaintexts.lines() {
             let plaintext = line.trim().decode_base64();
             let oracle = CbcPaddingOracle::new(plaintext)?;
-            let result = break_cbc_padding_oracle(&oracle)?;
+            let result = break_cbc_padding_oracle(&oracle, &oracle.iv, &oracle.ciphertext)?;
             assert!(oracle.verify(&result));
         }
         Ok(())
@@ -0,0 +1,51 @@
+use crate::{
+    aes::{self, break_ctr_edit},
+    base64,
+    oracle::CtrEditOracle,
+    utils,
+};
+use anyhow::Result;
+
+// Challenge 25's input is the same file as challenge 7's, ECB-encrypted under
+// "YELLOW SUBMARINE"
+const CHALLENGE25_INPUT: &str = include_str!("../files/7.txt");
+const CHALLENGE25_KEY: &[u8] = b"YELLOW SUBMARINE";
+
+fn challenge25_plaintext() -> Result<Vec<u8>> {
+    let input = base64::from_file_str(CHALLENGE25_INPUT);
+    aes::decrypt_aes_ecb(&input, CHALLENGE25_KEY)
+}
+
+fn challenge25() -> Result<()> {
+    println!("Challenge 25: Break \"random access read/write\" AES CTR");
+    let oracle = CtrEditOracle::new(challenge25_plaintext()?)?;
+    let result = break_ctr_edit(&oracle, &oracle.ciphertext)?;
+    let emoji = if oracle.verify(&result) { "✅" } else { "❌" };
+    println!(
+        "\t{emoji} {}",
+        utils::truncate(String::from_utf8_lossy(&result).into())
+    );
+    Ok(())
+}
+
+pub fn main() -> Result<()> {
+    println!("\n========= Set 4 =======\n-----------------------");
+    challenge25()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{aes::break_ctr_edit, oracle::CtrEditOracle, sets::set4::challenge25_plaintext};
+    use anyhow::Result;
+
+    #[test]
+    fn test_challenge25() -> Result<()> {
+        let plaintext = challenge25_plaintext()?;
+        let oracle = CtrEditOracle::new(plaintext.clone())?;
+        assert_ne!(oracle.ciphertext, plaintext);
+        let result = break_ctr_edit(&oracle, &oracle.ciphertext)?;
+        assert!(oracle.verify(&result));
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+// AES-GCM-SIV
+// Reference: RFC 8452
+//
+// Per-nonce keys are derived from the key-generating key, the tag is POLYVAL
+// over the associated data and plaintext (mixed with the nonce and encrypted),
+// and the tag doubles as the initial counter block.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::{AesError, AesKey, KeySize},
+    cipher::BlockCipher,
+    ctr::{CounterLayout, Ctr},
+    gcm::{gf128::Gf128, TAG_LEN},
+};
+
+pub const NONCE_LEN: usize = 12;
+
+fn reversed(block: &[u8]) -> Vec<u8> {
+    block.iter().rev().copied().collect()
+}
+
+/// POLYVAL is GHASH with the bytes reversed: POLYVAL(H, X) =
+/// ByteReverse(GHASH(H * x, ByteReverse(X_1), ..., ByteReverse(X_n))), RFC 8452
+/// Appendix A. Short final blocks are zero-padded.
+pub fn polyval(h: &[u8], blocks: &[u8]) -> Result<Vec<u8>> {
+    // x is the second coefficient, one bit below ONE in GHASH order
+    let h = Gf128::from_block(&reversed(h))? * Gf128(1 << 126);
+    let acc = blocks.chunks(16).fold(Gf128::ZERO, |acc, block| {
+        let mut padded = block.to_vec();
+        padded.resize(16, 0);
+        (acc + Gf128::from_partial_block(&reversed(&padded))) * h
+    });
+    Ok(reversed(&acc.to_block()))
+}
+
+pub struct GcmSiv {
+    key_generating_key: AesKey,
+}
+
+impl GcmSiv {
+    pub fn new(key: &[u8]) -> Result<Self> {
+        let key_generating_key = AesKey::new(key)?;
+        ensure!(
+            key_generating_key.size() != KeySize::Aes192,
+            AesError::InvalidKeyLength("AES-GCM-SIV uses 16 or 32 byte keys".into())
+        );
+        Ok(GcmSiv { key_generating_key })
+    }
+
+    /// The per-nonce (message authentication key, message encryption key):
+    /// the first 8 bytes of E(K, le32(i) || nonce) for consecutive i
+    fn derive_keys(&self, nonce: &[u8]) -> Result<(Vec<u8>, AesKey)> {
+        ensure!(
+            nonce.len() == NONCE_LEN,
+            "expected a {NONCE_LEN} byte nonce, got {}",
+            nonce.len()
+        );
+        let enc_blocks = match self.key_generating_key.size() {
+            KeySize::Aes256 => 4,
+            _ => 2,
+        };
+        let mut derived = vec![];
+        for i in 0..2 + enc_blocks as u32 {
+            let mut block = i.to_le_bytes().to_vec();
+            block.extend(nonce);
+            derived.extend(&self.key_generating_key.encrypt_block(&block)?[..8]);
+        }
+        let enc_key = AesKey::new(&derived[16..])?;
+        derived.truncate(16);
+        Ok((derived, enc_key))
+    }
+
+    fn tag(
+        auth_key: &[u8],
+        enc_key: &AesKey,
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>> {
+        let mut blocks = aad.to_vec();
+        blocks.resize(aad.len().next_multiple_of(16), 0);
+        blocks.extend(plaintext);
+        blocks.resize(blocks.len().next_multiple_of(16), 0);
+        blocks.extend((aad.len() as u64 * 8).to_le_bytes());
+        blocks.extend((plaintext.len() as u64 * 8).to_le_bytes());
+
+        let mut s = polyval(auth_key, &blocks)?;
+        for (byte, n) in s.iter_mut().zip(nonce) {
+            *byte ^= n;
+        }
+        s[15] &= 0x7f;
+        enc_key.encrypt_block(&s)
+    }
+
+    fn ctr<'a>(enc_key: &'a AesKey, tag: &[u8]) -> Result<Ctr<&'a AesKey>> {
+        let mut initial = [0; 16];
+        initial.copy_from_slice(tag);
+        initial[15] |= 0x80;
+        Ctr::new(enc_key, CounterLayout::LittleEndian32 { initial })
+    }
+
+    /// Returns (ciphertext, tag)
+    pub fn encrypt(
+        &self,
+        nonce: &[u8],
+        plaintext: &[u8],
+        aad: &[u8],
+    ) -> Result<(Vec<u8>, Vec<u8>)> {
+        let (auth_key, enc_key) = self.derive_keys(nonce)?;
+        let tag = Self::tag(&auth_key, &enc_key, nonce, plaintext, aad)?;
+        let ciphertext = Self::ctr(&enc_key, &tag)?.apply(plaintext)?;
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypts, then recomputes the tag over the recovered plaintext
+    pub fn decrypt(
+        &self,
+        nonce: &[u8],
+        ciphertext: &[u8],
+        aad: &[u8],
+        tag: &[u8],
+    ) -> Result<Vec<u8>> {
+        ensure!(
+            tag.len() == TAG_LEN,
+            AesError::InvalidTag(format!("bad tag length {}", tag.len()))
+        );
+        let (auth_key, enc_key) = self.derive_keys(nonce)?;
+        let plaintext = Self::ctr(&enc_key, tag)?.apply(ciphertext)?;
+        let expected = Self::tag(&auth_key, &enc_key, nonce, &plaintext, aad)?;
+        if !openssl::memcmp::eq(&expected, tag) {
+            bail!(AesError::InvalidTag("GCM-SIV tag mismatch".into()));
+        }
+        Ok(plaintext)
+    }
+}
+
+pub fn encrypt_aes_gcm_siv(
+    bytes: &[u8],
+    aad: &[u8],
+    nonce: &[u8],
+    key: &[u8],
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    GcmSiv::new(key)?.encrypt(nonce, bytes, aad)
+}
+
+pub fn decrypt_aes_gcm_siv(
+    bytes: &[u8],
+    aad: &[u8],
+    nonce: &[u8],
+    key: &[u8],
+    tag: &[u8],
+) -> Result<Vec<u8>> {
+    GcmSiv::new(key)?.decrypt(nonce, bytes, aad, tag)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        hex::{DecodeHex, EncodeHex},
+        siv::gcm_siv::{decrypt_aes_gcm_siv, encrypt_aes_gcm_siv, polyval},
+        utils::bytes,
+    };
+
+    #[test]
+    fn test_polyval() {
+        // RFC 8452 Appendix A
+        let h = "25629347589242761d31f826ba4b757b".decode_hex();
+        let x = "4f4f95668c83dfb6401762bb2d01a262d1a24ddd2721d006bbe45f20d3c9f362".decode_hex();
+        assert_eq!(
+            polyval(&h, &x).unwrap().to_hex(),
+            "f7a3b47b846119fae5b7866cf5e5b77e"
+        );
+    }
+
+    #[test]
+    fn test_rfc8452_vectors() {
+        // (key, plaintext, aad, ciphertext || tag), RFC 8452 Appendix C.1 and
+        // C.2, all with nonce 030000000000000000000000
+        let key_128 = "01000000000000000000000000000000";
+        let key_256 = "0100000000000000000000000000000000000000000000000000000000000000";
+        let vectors = [
+            (key_128, "", "", "dc20e2d83f25705bb49e439eca56de25"),
+            (key_128, "0100000000000000", "", "b5d839330ac7b786578782fff6013b815b287c22493a364c"),
+            (key_128, "02000000000000000000000000000000", "01", "e2b0c5da79a901c1745f700525cb335b8f8936ec039e4e4bb97ebd8c4457441f"),
+            (key_128, "020000000000000000000000000000000300000000000000", "01", "28082a1c48e4b7af371e8deb55d9edc03c687e653c2753a4b0180672cfc6114dad40c5407aeffa5c"),
+            (key_256, "0200000000000000", "01", "1de22967237a813291213f267e3b452f02d01ae33e4ec854"),
+            (key_256, "02000000000000000000000000000000030000000000000000000000000000000400000000000000", "01", "bdf21f4913e5f08f4ae4afaf2ab98d4c2bfdb33e78df11fe84e4572a412ca639508f8f535cea6342c7c492f145d7a8bb3c5973eaae7a3dc8"),
+        ];
+        let nonce = "030000000000000000000000".decode_hex();
+        for (key, plaintext, aad, expected) in vectors {
+            let (key, plaintext, aad) =
+                (key.decode_hex(), plaintext.decode_hex(), aad.decode_hex());
+            let (ciphertext, tag) = encrypt_aes_gcm_siv(&plaintext, &aad, &nonce, &key).unwrap();
+            assert_eq!([&ciphertext[..], &tag[..]].concat().to_hex(), expected);
+            assert_eq!(
+                decrypt_aes_gcm_siv(&ciphertext, &aad, &nonce, &key, &tag).unwrap(),
+                plaintext
+            );
+        }
+    }
+
+    #[test]
+    fn test_gcm_siv_rejects_tampering() {
+        let key = bytes::rand_of_len(32);
+        let nonce = bytes::rand_of_len(12);
+        let (mut ciphertext, tag) =
+            encrypt_aes_gcm_siv(b"attack at dawn", b"hdr", &nonce, &key).unwrap();
+        assert!(decrypt_aes_gcm_siv(&ciphertext, b"HDR", &nonce, &key, &tag).is_err());
+        assert!(decrypt_aes_gcm_siv(&ciphertext, b"hdr", &nonce, &key, &tag[..12]).is_err());
+        ciphertext[0] ^= 1;
+        assert!(decrypt_aes_gcm_siv(&ciphertext, b"hdr", &nonce, &key, &tag).is_err());
+        assert!(encrypt_aes_gcm_siv(b"", b"", &nonce, &bytes::rand_of_len(24)).is_err());
+        assert!(encrypt_aes_gcm_siv(b"", b"", &[0; 16], &key).is_err());
+    }
+}
@@ -0,0 +1,217 @@
+// Misuse-resistant AEAD: the IV is derived from the message itself.
+// References: RFC 5297 (AES-SIV), RFC 8452 (AES-GCM-SIV)
+//
+// Both modes compute a MAC over the associated data and the plaintext first,
+// then use it as the IV for CTR. Reusing a nonce no longer leaks the xor of
+// two plaintexts, only whether the two messages were identical.
+use anyhow::{bail, ensure, Result};
+
+use crate::{
+    aes::{AesError, AesKey},
+    cipher::BlockCipher,
+    ctr::{CounterLayout, Ctr},
+    mac::{cmac, double, rb},
+    xor::Xor,
+};
+
+pub mod gcm_siv;
+
+const BLOCK_SIZE: usize = 16;
+
+/// SIV mode: S2V over CMAC with one key, CTR with the other
+#[derive(Debug, Clone)]
+pub struct Siv<C> {
+    mac: C,
+    ctr: C,
+}
+
+impl Siv<AesKey> {
+    /// Splits a 32, 48 or 64 byte key into the S2V key and the CTR key
+    pub fn aes(key: &[u8]) -> Result<Self> {
+        ensure!(
+            matches!(key.len(), 32 | 48 | 64),
+            "AES-SIV needs a 32, 48 or 64 byte key, got {}",
+            key.len()
+        );
+        let (mac, ctr) = key.split_at(key.len() / 2);
+        Siv::new(AesKey::new(mac)?, AesKey::new(ctr)?)
+    }
+}
+
+impl<C: BlockCipher> Siv<C> {
+    pub fn new(mac: C, ctr: C) -> Result<Self> {
+        ensure!(
+            mac.block_size() == BLOCK_SIZE && ctr.block_size() == BLOCK_SIZE,
+            "SIV requires 16 byte block ciphers"
+        );
+        Ok(Siv { mac, ctr })
+    }
+
+    /// S2V: a CMAC-based PRF over a vector of strings. Each string but the last
+    /// is CMACed and folded in with doubling; the last is xored into the end
+    /// of the state, or padded if it is shorter than a block.
+    pub fn s2v(&self, components: &[&[u8]]) -> Result<Vec<u8>> {
+        let rb = rb(BLOCK_SIZE)?;
+        let Some((last, rest)) = components.split_last() else {
+            let mut one = vec![0; BLOCK_SIZE];
+            one[BLOCK_SIZE - 1] = 1;
+            return cmac(&self.mac, &one);
+        };
+        let mut d = cmac(&self.mac, &[0; BLOCK_SIZE])?;
+        for component in rest {
+            d = double(&d, rb).xor(&cmac(&self.mac, component)?);
+        }
+        let t = if last.len() >= BLOCK_SIZE {
+            let split = last.len() - BLOCK_SIZE;
+            let mut t = last[..split].to_vec();
+            t.extend(last[split..].xor(&d));
+            t
+        } else {
+            let mut padded = last.to_vec();
+            padded.push(0x80);
+            padded.resize(BLOCK_SIZE, 0);
+            double(&d, rb).xor(&padded)
+        };
+        cmac(&self.mac, &t)
+    }
+
+    // The synthetic IV with bits 63 and 31 cleared, so implementations can use
+    // 32-bit counter arithmetic without carrying
+    fn ctr_for(&self, v: &[u8]) -> Result<Ctr<&C>> {
+        let mut initial = [0; BLOCK_SIZE];
+        initial.copy_from_slice(v);
+        initial[8] &= 0x7f;
+        initial[12] &= 0x7f;
+        Ctr::new(&self.ctr, CounterLayout::BigEndian128 { initial })
+    }
+
+    /// Returns V || C. A nonce, if any, is passed as the last associated data
+    /// component.
+    pub fn encrypt(&self, associated_data: &[&[u8]], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut components = associated_data.to_vec();
+        components.push(plaintext);
+        let mut out = self.s2v(&components)?;
+        let ciphertext = self.ctr_for(&out)?.apply(plaintext)?;
+        out.extend(ciphertext);
+        Ok(out)
+    }
+
+    pub fn decrypt(&self, associated_data: &[&[u8]], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        ensure!(
+            ciphertext.len() >= BLOCK_SIZE,
+            AesError::InvalidTag(format!("ciphertext too short: {}", ciphertext.len()))
+        );
+        let (v, ciphertext) = ciphertext.split_at(BLOCK_SIZE);
+        let plaintext = self.ctr_for(v)?.apply(ciphertext)?;
+        let mut components = associated_data.to_vec();
+        components.push(&plaintext);
+        let expected = self.s2v(&components)?;
+        if !openssl::memcmp::eq(&expected, v) {
+            bail!(AesError::InvalidTag("SIV mismatch".into()));
+        }
+        Ok(plaintext)
+    }
+}
+
+pub fn encrypt_aes_siv(bytes: &[u8], aad: &[u8], nonce: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    Siv::aes(key)?.encrypt(&[aad, nonce], bytes)
+}
+
+pub fn decrypt_aes_siv(bytes: &[u8], aad: &[u8], nonce: &[u8], key: &[u8]) -> Result<Vec<u8>> {
+    Siv::aes(key)?.decrypt(&[aad, nonce], bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        aes::AesError,
+        gcm,
+        hex::{DecodeHex, EncodeHex},
+        siv::{decrypt_aes_siv, encrypt_aes_siv, gcm_siv, Siv},
+        utils::bytes,
+        xor::Xor,
+    };
+
+    #[test]
+    fn test_rfc5297_vectors() {
+        // A.1, deterministic authenticated encryption
+        let siv = Siv::aes(
+            &"fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff".decode_hex(),
+        )
+        .unwrap();
+        let ad = "101112131415161718191a1b1c1d1e1f2021222324252627".decode_hex();
+        let plaintext = "112233445566778899aabbccddee".decode_hex();
+        let encrypted = siv.encrypt(&[&ad], &plaintext).unwrap();
+        assert_eq!(
+            encrypted.to_hex(),
+            "85632d07c6e8f37f950acd320a2ecc9340c02b9690c4dc04daef7f6afe5c"
+        );
+        assert_eq!(siv.decrypt(&[&ad], &encrypted).unwrap(), plaintext);
+
+        // A.2, nonce-based authenticated encryption
+        let siv = Siv::aes(
+            &"7f7e7d7c7b7a79787776757473727170404142434445464748494a4b4c4d4e4f".decode_hex(),
+        )
+        .unwrap();
+        let ad1 =
+            "00112233445566778899aabbccddeeffdeaddadadeaddadaffeeddccbbaa99887766554433221100"
+                .decode_hex();
+        let ad2 = "102030405060708090a0".decode_hex();
+        let nonce = "09f911029d74e35bd84156c5635688c0".decode_hex();
+        let plaintext = b"this is some plaintext to encrypt using SIV-AES";
+        let encrypted = siv.encrypt(&[&ad1, &ad2, &nonce], plaintext).unwrap();
+        assert_eq!(
+            encrypted.to_hex(),
+            "7bdb6e3b432667eb06f4d14bff2fbd0fcb900f2fddbe404326601965c889bf17dba77ceb094fa663b7a3f748ba8af829ea64ad544a272e9c485b62a3fd5c0d"
+        );
+        assert_eq!(
+            siv.decrypt(&[&ad1, &ad2, &nonce], &encrypted).unwrap(),
+            plaintext
+        );
+    }
+
+    #[test]
+    fn test_siv_rejects_tampering() {
+        let key = bytes::rand_of_len(32);
+        let nonce = bytes::rand_of_len(16);
+        let mut encrypted = encrypt_aes_siv(b"attack at dawn", b"header", &nonce, &key).unwrap();
+        assert!(decrypt_aes_siv(&encrypted, b"Header", &nonce, &key).is_err());
+        encrypted[20] ^= 1;
+        let err = decrypt_aes_siv(&encrypted, b"header", &nonce, &key).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<AesError>(),
+            Some(AesError::InvalidTag(_))
+        ));
+        assert!(decrypt_aes_siv(&[0; 15], b"header", &nonce, &key).is_err());
+    }
+
+    // Under a repeated nonce, GCM's keystream repeats, so the ciphertexts xor to
+    // the xor of the plaintexts. The SIV modes only reveal that two messages
+    // were identical: any change gives an unrelated IV and keystream.
+    #[test]
+    fn test_nonce_reuse_leaks_only_equality() {
+        let key = bytes::rand_of_len(16);
+        let nonce = bytes::rand_of_len(12);
+        let p1 = b"transfer $100 to alice, ref 0001".to_vec();
+        let p2 = b"transfer $100 to alice, ref 0002".to_vec();
+
+        let (c1, _) = gcm::encrypt_aes_gcm(&p1, b"", &nonce, &key).unwrap();
+        let (c2, _) = gcm::encrypt_aes_gcm(&p2, b"", &nonce, &key).unwrap();
+        assert_eq!(c1.xor(&c2), p1.xor(&p2));
+
+        let (c1, t1) = gcm_siv::encrypt_aes_gcm_siv(&p1, b"", &nonce, &key).unwrap();
+        let (c2, t2) = gcm_siv::encrypt_aes_gcm_siv(&p2, b"", &nonce, &key).unwrap();
+        let (c3, t3) = gcm_siv::encrypt_aes_gcm_siv(&p1, b"", &nonce, &key).unwrap();
+        assert_eq!((&c1, &t1), (&c3, &t3));
+        assert_ne!(t1, t2);
+        assert_ne!(c1.xor(&c2), p1.xor(&p2));
+        assert_ne!(c1[..16], c2[..16]);
+
+        let siv_key = bytes::rand_of_len(32);
+        let s1 = encrypt_aes_siv(&p1, b"", &nonce, &siv_key).unwrap();
+        let s2 = encrypt_aes_siv(&p2, b"", &nonce, &siv_key).unwrap();
+        assert_eq!(s1, encrypt_aes_siv(&p1, b"", &nonce, &siv_key).unwrap());
+        assert_ne!(s1[..16], s2[..16]);
+        assert_ne!(s1[16..].xor(&s2[16..]), p1.xor(&p2));
+    }
+}
@@ -0,0 +1,406 @@
+// Incremental CBC and CTR over `Read`/`Write`, for inputs too large to buffer.
+//
+// The CBC writers only ever hold a block or so in memory. They need an explicit
+// `finish` to deal with the padding, and dropping one without finishing leaves
+// the output truncated. The CBC reader has no such step: it knows the input is
+// over when `inner` runs dry, and unpads then.
+use std::io::{self, Read, Write};
+
+use crate::{
+    cipher::BlockCipher,
+    ctr::Ctr,
+    modes::ensure_iv_len,
+    padding::{PadPkcs7, UnpadPkcs7},
+    xor::Xor,
+};
+
+fn io_error(e: anyhow::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+/// Encrypts everything written to it with CBC and PKCS#7 padding, writing the
+/// ciphertext to `inner`. Call `finish` to write the final padded block.
+pub struct CbcEncryptor<W: Write, C: BlockCipher> {
+    cipher: C,
+    inner: W,
+    chain: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl<W: Write, C: BlockCipher> CbcEncryptor<W, C> {
+    pub fn new(cipher: C, iv: &[u8], inner: W) -> anyhow::Result<Self> {
+        ensure_iv_len(&cipher, iv)?;
+        Ok(CbcEncryptor {
+            cipher,
+            inner,
+            chain: iv.to_vec(),
+            pending: vec![],
+        })
+    }
+
+    fn encrypt_blocks(&mut self, bytes: &[u8]) -> io::Result<()> {
+        for block in bytes.chunks_exact(self.cipher.block_size()) {
+            self.chain = self
+                .cipher
+                .encrypt_block(&block.xor(&self.chain))
+                .map_err(io_error)?;
+            self.inner.write_all(&self.chain)?;
+        }
+        Ok(())
+    }
+
+    /// Pads and encrypts whatever is left, returning the inner writer
+    pub fn finish(mut self) -> io::Result<W> {
+        let last = self.pending.pad_pkcs7_blocksize(self.cipher.block_size());
+        self.encrypt_blocks(&last)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, C: BlockCipher> Write for CbcEncryptor<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let block_size = self.cipher.block_size();
+        let full = self.pending.len() - self.pending.len() % block_size;
+        let blocks: Vec<u8> = self.pending.drain(..full).collect();
+        self.encrypt_blocks(&blocks)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// One CBC decryption step, moving the chain on to `block`
+fn decrypt_chained(
+    cipher: &impl BlockCipher,
+    chain: &mut Vec<u8>,
+    block: &[u8],
+) -> io::Result<Vec<u8>> {
+    let decrypted = cipher.decrypt_block(block).map_err(io_error)?.xor(chain);
+    *chain = block.to_vec();
+    Ok(decrypted)
+}
+
+/// Decrypts CBC ciphertext written to it, writing the plaintext to `inner`.
+/// The last block is held back until `finish`, since only then is it known to
+/// be the one carrying the padding.
+pub struct CbcDecryptor<W: Write, C: BlockCipher> {
+    cipher: C,
+    inner: W,
+    chain: Vec<u8>,
+    pending: Vec<u8>,
+}
+
+impl<W: Write, C: BlockCipher> CbcDecryptor<W, C> {
+    pub fn new(cipher: C, iv: &[u8], inner: W) -> anyhow::Result<Self> {
+        ensure_iv_len(&cipher, iv)?;
+        Ok(CbcDecryptor {
+            cipher,
+            inner,
+            chain: iv.to_vec(),
+            pending: vec![],
+        })
+    }
+
+    fn decrypt_block(&mut self, block: &[u8]) -> io::Result<Vec<u8>> {
+        decrypt_chained(&self.cipher, &mut self.chain, block)
+    }
+
+    /// Decrypts the held-back block and strips its padding, returning the
+    /// inner writer. Fails if the ciphertext wasn't a whole number of blocks
+    /// or the padding is invalid.
+    pub fn finish(mut self) -> io::Result<W> {
+        let block_size = self.cipher.block_size();
+        if self.pending.len() != block_size {
+            return Err(io_error(anyhow::anyhow!(
+                "expected a final {block_size} byte block, got {} bytes",
+                self.pending.len()
+            )));
+        }
+        let last = std::mem::take(&mut self.pending);
+        let decrypted = self.decrypt_block(&last)?;
+        let unpadded = decrypted
+            .validate_unpad_pkcs7_blocksize(block_size)
+            .map_err(io_error)?;
+        self.inner.write_all(&unpadded)?;
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write, C: BlockCipher> Write for CbcDecryptor<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.extend_from_slice(buf);
+        let block_size = self.cipher.block_size();
+        // always keep at least one whole block back for `finish`
+        while self.pending.len() > block_size {
+            let block: Vec<u8> = self.pending.drain(..block_size).collect();
+            let decrypted = self.decrypt_block(&block)?;
+            self.inner.write_all(&decrypted)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Decrypts CBC ciphertext read from `inner`. The last block is held back
+/// until `inner` reaches end of file, then unpadded; a truncated ciphertext or
+/// bad padding is an `InvalidData` error from that final read.
+pub struct CbcDecryptReader<R: Read, C: BlockCipher> {
+    cipher: C,
+    inner: R,
+    chain: Vec<u8>,
+    pending: Vec<u8>,
+    plaintext: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read, C: BlockCipher> CbcDecryptReader<R, C> {
+    pub fn new(cipher: C, iv: &[u8], inner: R) -> anyhow::Result<Self> {
+        ensure_iv_len(&cipher, iv)?;
+        Ok(CbcDecryptReader {
+            cipher,
+            inner,
+            chain: iv.to_vec(),
+            pending: vec![],
+            plaintext: vec![],
+            done: false,
+        })
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    // Reads more ciphertext, decrypting all but the last block of it, or
+    // everything once the input has run out
+    fn fill(&mut self) -> io::Result<()> {
+        let block_size = self.cipher.block_size();
+        let mut buf = vec![0; 4 * block_size];
+        let read = self.inner.read(&mut buf)?;
+        if read == 0 {
+            // Nothing changes until the last block checks out, so a caller
+            // that retries gets the same error rather than a clean end of file
+            if self.pending.len() != block_size {
+                return Err(io_error(anyhow::anyhow!(
+                    "expected a final {block_size} byte block, got {} bytes",
+                    self.pending.len()
+                )));
+            }
+            let mut chain = self.chain.clone();
+            let decrypted = decrypt_chained(&self.cipher, &mut chain, &self.pending)?;
+            let unpadded = decrypted
+                .validate_unpad_pkcs7_blocksize(block_size)
+                .map_err(io_error)?;
+            self.plaintext.extend(unpadded);
+            self.pending.clear();
+            self.done = true;
+            return Ok(());
+        }
+        self.pending.extend_from_slice(&buf[..read]);
+        while self.pending.len() > block_size {
+            let block: Vec<u8> = self.pending.drain(..block_size).collect();
+            let decrypted = decrypt_chained(&self.cipher, &mut self.chain, &block)?;
+            self.plaintext.extend(decrypted);
+        }
+        Ok(())
+    }
+}
+
+impl<R: Read, C: BlockCipher> Read for CbcDecryptReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.plaintext.is_empty() && !self.done {
+            self.fill()?;
+        }
+        let len = buf.len().min(self.plaintext.len());
+        buf[..len].copy_from_slice(&self.plaintext[..len]);
+        self.plaintext.drain(..len);
+        Ok(len)
+    }
+}
+
+/// Applies the CTR keystream to everything read from `inner`. Encryption and
+/// decryption are the same operation.
+pub struct CtrReader<R: Read, C: BlockCipher> {
+    ctr: Ctr<C>,
+    inner: R,
+    position: usize,
+}
+
+impl<R: Read, C: BlockCipher> CtrReader<R, C> {
+    pub fn new(ctr: Ctr<C>, inner: R) -> Self {
+        CtrReader {
+            ctr,
+            inner,
+            position: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read, C: BlockCipher> Read for CtrReader<R, C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        let keystream = self.ctr.keystream(self.position, read).map_err(io_error)?;
+        for (byte, key) in buf[..read].iter_mut().zip(keystream) {
+            *byte ^= key;
+        }
+        self.position += read;
+        Ok(read)
+    }
+}
+
+/// Applies the CTR keystream to everything written to it. Nothing is held
+/// back, so there is no `finish`.
+pub struct CtrWriter<W: Write, C: BlockCipher> {
+    ctr: Ctr<C>,
+    inner: W,
+    position: usize,
+}
+
+impl<W: Write, C: BlockCipher> CtrWriter<W, C> {
+    pub fn new(ctr: Ctr<C>, inner: W) -> Self {
+        CtrWriter {
+            ctr,
+            inner,
+            position: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write, C: BlockCipher> Write for CtrWriter<W, C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let applied = self.ctr.apply_at(self.position, buf).map_err(io_error)?;
+        self.inner.write_all(&applied)?;
+        self.position += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Read, Write};
+
+    use rand::Rng;
+
+    use crate::{
+        aes::AesKey,
+        cipher::{BlockCipher, ToyCipher},
+        ctr::{CounterLayout, Ctr},
+        modes,
+        stream::{CbcDecryptReader, CbcDecryptor, CbcEncryptor, CtrReader, CtrWriter},
+        utils::bytes,
+    };
+
+    // writes `bytes` in randomly sized pieces
+    fn write_chunked(writer: &mut impl Write, bytes: &[u8]) {
+        let mut rng = rand::thread_rng();
+        let mut rest = bytes;
+        while !rest.is_empty() {
+            let len = rng.gen_range(1..=rest.len().min(37));
+            writer.write_all(&rest[..len]).unwrap();
+            rest = &rest[len..];
+        }
+    }
+
+    // reads everything through a small buffer, so reads straddle blocks
+    fn read_chunked(mut reader: impl Read) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        let mut buf = [0; 13];
+        loop {
+            let read = reader.read(&mut buf)?;
+            if read == 0 {
+                return Ok(out);
+            }
+            out.extend_from_slice(&buf[..read]);
+        }
+    }
+
+    fn check_cbc(cipher: impl BlockCipher + Clone) {
+        let iv = bytes::rand_of_len(cipher.block_size());
+        for len in [0, 1, 7, 8, 15, 16, 17, 100, 1000] {
+            let plaintext = bytes::rand_of_len(len);
+            let expected = modes::encrypt_cbc(&cipher, &plaintext, &iv).unwrap();
+
+            let mut encryptor = CbcEncryptor::new(cipher.clone(), &iv, vec![]).unwrap();
+            write_chunked(&mut encryptor, &plaintext);
+            let encrypted = encryptor.finish().unwrap();
+            assert_eq!(encrypted, expected);
+
+            let mut decryptor = CbcDecryptor::new(cipher.clone(), &iv, vec![]).unwrap();
+            write_chunked(&mut decryptor, &encrypted);
+            assert_eq!(decryptor.finish().unwrap(), plaintext);
+
+            let reader = CbcDecryptReader::new(cipher.clone(), &iv, &encrypted[..]).unwrap();
+            assert_eq!(read_chunked(reader).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn test_cbc_stream_matches_modes() {
+        check_cbc(AesKey::new(&bytes::rand_of_len(16)).unwrap());
+        check_cbc(ToyCipher::new(&bytes::rand_of_len(8)).unwrap());
+    }
+
+    #[test]
+    fn test_cbc_decryptor_errors() {
+        let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+        let iv = bytes::rand_of_len(16);
+        let mut encrypted = modes::encrypt_cbc(&cipher, b"some plaintext", &iv).unwrap();
+
+        let mut decryptor = CbcDecryptor::new(&cipher, &iv, vec![]).unwrap();
+        decryptor.write_all(&encrypted[..10]).unwrap();
+        let err = decryptor.finish().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // corrupt the padding byte
+        encrypted[15] ^= 0x20;
+        let mut decryptor = CbcDecryptor::new(&cipher, &iv, vec![]).unwrap();
+        decryptor.write_all(&encrypted).unwrap();
+        assert!(decryptor.finish().is_err());
+        let mut reader = CbcDecryptReader::new(&cipher, &iv, &encrypted[..]).unwrap();
+        assert!(read_chunked(&mut reader).is_err());
+        // and keeps failing, rather than looking like the end of the file
+        assert!(read_chunked(&mut reader).is_err());
+
+        let mut reader = CbcDecryptReader::new(&cipher, &iv, &encrypted[..10]).unwrap();
+        let err = read_chunked(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let err = read_chunked(&mut reader).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        assert!(CbcEncryptor::new(&cipher, &[0; 8], vec![]).is_err());
+    }
+
+    #[test]
+    fn test_ctr_stream() {
+        let cipher = AesKey::new(&bytes::rand_of_len(16)).unwrap();
+        let ctr = Ctr::new(cipher, CounterLayout::LittleEndian64 { nonce: 5 }).unwrap();
+        let plaintext = bytes::rand_of_len(1000);
+        let expected = ctr.apply(&plaintext).unwrap();
+
+        let mut writer = CtrWriter::new(ctr.clone(), vec![]);
+        write_chunked(&mut writer, &plaintext);
+        assert_eq!(writer.into_inner(), expected);
+
+        let reader = CtrReader::new(ctr, &expected[..]);
+        assert_eq!(read_chunked(reader).unwrap(), plaintext);
+    }
+}
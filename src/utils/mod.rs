@@ -20,14 +20,137 @@ pub mod time {
     }
 }
 
+pub mod random {
+    use std::{env, ops::RangeInclusive};
+
+    use rand::{rngs::StdRng, Rng, RngCore, SeedableRng};
+
+    /// Where oracles and helpers get their randomness. Every `rand` generator
+    /// is one, so a seeded `StdRng` makes a run repeatable.
+    pub trait RandomSource {
+        fn rand_bytes(&mut self, len: usize) -> Vec<u8>;
+        fn rand_range(&mut self, range: RangeInclusive<usize>) -> usize;
+        fn rand_u64(&mut self) -> u64;
+
+        fn rand_bool(&mut self) -> bool {
+            self.rand_u64() & 1 == 1
+        }
+    }
+
+    impl<R: RngCore> RandomSource for R {
+        fn rand_bytes(&mut self, len: usize) -> Vec<u8> {
+            let mut bytes = vec![0; len];
+            self.fill_bytes(&mut bytes);
+            bytes
+        }
+
+        fn rand_range(&mut self, range: RangeInclusive<usize>) -> usize {
+            self.gen_range(range)
+        }
+
+        fn rand_u64(&mut self) -> u64 {
+            self.next_u64()
+        }
+    }
+
+    /// The environment variable `test_rng` takes its seed from
+    pub const SEED_VAR: &str = "CRYPTOPALS_SEED";
+
+    pub fn seeded(seed: u64) -> StdRng {
+        StdRng::seed_from_u64(seed)
+    }
+
+    /// A generator for tests, seeded from `CRYPTOPALS_SEED` or else at random.
+    /// The seed is printed, so a failing test can be rerun with it.
+    pub fn test_rng() -> StdRng {
+        let seed = env::var(SEED_VAR)
+            .ok()
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(rand::random);
+        println!("{SEED_VAR}={seed}");
+        seeded(seed)
+    }
+}
+
 pub mod bytes {
+    use super::random::RandomSource;
+
     pub fn of_len(len: usize, val: u8) -> Vec<u8> {
         vec![val; len]
     }
 
     pub fn rand_of_len(len: usize) -> Vec<u8> {
-        let mut v = vec![];
-        (0..len).for_each(|_| v.push(rand::random()));
-        v
+        rand_of_len_from(&mut rand::thread_rng(), len)
+    }
+
+    pub fn rand_of_len_from(rng: &mut impl RandomSource, len: usize) -> Vec<u8> {
+        rng.rand_bytes(len)
+    }
+}
+
+pub mod hexdump {
+    fn line(offset: usize, block: &[u8], block_size: usize) -> String {
+        let hex: Vec<String> = block.iter().map(|b| format!("{b:02x}")).collect();
+        let padding = "   ".repeat(block_size - block.len());
+        let ascii: String = block
+            .iter()
+            .map(|&b| {
+                if b.is_ascii_graphic() || b == b' ' {
+                    b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        format!("{offset:08x}  {}{padding}  |{ascii}|", hex.join(" "))
+    }
+
+    /// One line per block: the offset, the bytes in hex, then the printable ones
+    pub fn hexdump(bytes: &[u8], block_size: usize) -> String {
+        bytes
+            .chunks(block_size)
+            .enumerate()
+            .map(|(idx, block)| line(idx * block_size, block, block_size))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Indices of the blocks that differ between `a` and `b`, including any
+    /// block that only one of them has
+    pub fn changed_blocks(a: &[u8], b: &[u8], block_size: usize) -> Vec<usize> {
+        let blocks = a.len().max(b.len()).div_ceil(block_size);
+        (0..blocks)
+            .filter(|&idx| {
+                let block = |bytes: &[u8]| {
+                    bytes
+                        .get(idx * block_size..)
+                        .map(|rest| rest[..block_size.min(rest.len())].to_vec())
+                };
+                block(a) != block(b)
+            })
+            .collect()
+    }
+
+    /// A hexdump of `a` against `b`: unchanged blocks are printed once with a
+    /// leading space, changed ones as a `-` line from `a` and a `+` line from `b`
+    pub fn block_diff(a: &[u8], b: &[u8], block_size: usize) -> String {
+        let changed = changed_blocks(a, b, block_size);
+        let blocks = a.len().max(b.len()).div_ceil(block_size);
+        let block = |bytes: &'_ [u8], idx: usize| {
+            bytes
+                .chunks(block_size)
+                .nth(idx)
+                .map(|block| line(idx * block_size, block, block_size))
+        };
+        let mut out = vec![];
+        for idx in 0..blocks {
+            if changed.contains(&idx) {
+                out.extend(block(a, idx).map(|line| format!("-{line}")));
+                out.extend(block(b, idx).map(|line| format!("+{line}")));
+            } else {
+                out.extend(block(a, idx).map(|line| format!(" {line}")));
+            }
+        }
+        out.join("\n")
     }
 }
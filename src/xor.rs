@@ -73,7 +73,7 @@ pub fn find_repeating_xor_keysize(bytes: &[u8]) -> impl Iterator<Item = (usize,
             let x = chunks.combinations(2);
             let sum: u32 = x
                 .map(|x| {
-                    let lhs = x.get(0).unwrap();
+                    let lhs = x.first().unwrap();
                     let rhs = x.get(1).unwrap();
                     lhs.hamming_distance(rhs)
                 })